@@ -1,38 +1,152 @@
 use crate::token::Token;
-use crate::visitor::{ExprVisitor, StmtVisitor};
+
+// tells apart the statements and expressions of a program, numbered in the order they start in the source
+// with a node numbered before the nodes inside it. the resolver keys what it works out on them, and the
+// syntax tree links its nodes to the ast with them
+pub(crate) type NodeId = usize;
 
 #[derive(Debug, PartialEq, Clone)]
 pub(crate) enum Expr {
-    Binary { op: Token, left: Box<Expr>, right: Box<Expr> },
-    Unary { op: Token, right: Box<Expr> },
-    Int { val: i32 }, Float { val: f64 }, String { val: String },
-    Grouping { expr: Box<Expr> },
-    Name { val: String },
-    Bool { val: bool }
+    Binary { op: Token, left: Box<Expr>, right: Box<Expr>, id: NodeId },
+    Unary { op: Token, right: Box<Expr>, id: NodeId },
+    Int { val: i64, id: NodeId }, Float { val: f64, id: NodeId }, String { val: String, id: NodeId },
+    // the digits of an integer literal too large for an i64
+    BigInt { val: String, id: NodeId },
+    // `"a ${b} c"`, where the parts that aren't string literals are converted to strings and all of them joined
+    Interpolation { parts: Vec<Expr>, id: NodeId },
+    Grouping { expr: Box<Expr>, id: NodeId },
+    Name { val: String, id: NodeId },
+    Bool { val: bool, id: NodeId },
+    Nil { id: NodeId },
+    Assign { name: Token, expr: Box<Expr>, id: NodeId },
+    // `x += 1`, `a.b -= 1` or `a[i] *= 2`, which evaluate `a` and `i` once. `target` is the `Name`, `Get` or
+    // `Index` assigned to and `op` the binary operator applied, like `Plus` for `+=`
+    CompoundAssign { target: Box<Expr>, op: Token, expr: Box<Expr>, id: NodeId },
+    Array { elements: Vec<Expr>, id: NodeId },
+    Map { entries: Vec<(Expr, Expr)>, id: NodeId },
+    // `a[i]` or `m[key]`
+    Index { object: Box<Expr>, index: Box<Expr>, id: NodeId },
+    SetIndex { object: Box<Expr>, index: Box<Expr>, expr: Box<Expr>, id: NodeId },
+    Range { start: Box<Expr>, end: Box<Expr>, inclusive: bool, id: NodeId },
+    Call { callee: Box<Expr>, args: Vec<Expr>, id: NodeId },
+    Get { object: Box<Expr>, name: Token, id: NodeId },
+    Set { object: Box<Expr>, name: Token, expr: Box<Expr>, id: NodeId },
+    SelfRef { id: NodeId },
+    // `.superSaiyan`, a case of the enum the context expects
+    ImplicitMember { name: Token, id: NodeId },
+    // `fun(x) x * 2 end`, an anonymous function
+    Lambda { params: Vec<Param>, return_type: Option<Token>, body: Vec<Stmt>, id: NodeId },
 }
 
 impl Expr {
+    pub(crate) fn id(&self) -> NodeId {
+        match self {
+            Expr::Binary { id, .. } | Expr::Unary { id, .. } | Expr::Int { id, .. } | Expr::Float { id, .. } |
+            Expr::String { id, .. } | Expr::BigInt { id, .. } | Expr::Interpolation { id, .. } |
+            Expr::Grouping { id, .. } | Expr::Name { id, .. } | Expr::Bool { id, .. } | Expr::Nil { id, .. } |
+            Expr::Assign { id, .. } | Expr::CompoundAssign { id, .. } | Expr::Array { id, .. } | Expr::Map { id, .. } |
+            Expr::Index { id, .. } | Expr::SetIndex { id, .. } | Expr::Range { id, .. } | Expr::Call { id, .. } |
+            Expr::Get { id, .. } | Expr::Set { id, .. } | Expr::SelfRef { id, .. } | Expr::ImplicitMember { id, .. } |
+            Expr::Lambda { id, .. } => *id,
+        }
+    }
+
     pub(crate) fn string(&self) -> String {
         match self {
-            Expr::Binary { left, right, op } => format!("({} {:?} {})", left.string(), op, right.string()),
-            Expr::Unary { right, op } => format!("{:?} ({})", op, right.string()),
-            Expr::Int { val } => val.to_string(),
-            Expr::Float { val } => val.to_string(),
-            Expr::String { val } => val.to_string(),
-            Expr::Grouping { expr } => format!("({})", expr.string()),
-            Expr::Name { val } => val.to_string(),
-            Expr::Bool { val } => val.to_string(),
+            Expr::Binary { left, right, op, .. } => format!("({} {:?} {})", left.string(), op, right.string()),
+            Expr::Unary { right, op, .. } => format!("{:?} ({})", op, right.string()),
+            Expr::Int { val, .. } => val.to_string(),
+            Expr::Float { val, .. } => val.to_string(),
+            Expr::BigInt { val, .. } => val.to_string(),
+            Expr::String { val, .. } => val.to_string(),
+            Expr::Interpolation { parts, .. } => {
+                let parts: Vec<String> = parts.iter().map(|p| p.string()).collect();
+                format!("\"{}\"", parts.join(" "))
+            }
+            Expr::Grouping { expr, .. } => format!("({})", expr.string()),
+            Expr::Name { val, .. } => val.to_string(),
+            Expr::Bool { val, .. } => val.to_string(),
+            Expr::Nil { .. } => "nil".to_string(),
+            Expr::Assign { name, expr, .. } => format!("({:?} = {})", name, expr.string()),
+            Expr::CompoundAssign { target, op, expr, .. } => format!("({} {:?}= {})", target.string(), op, expr.string()),
+            Expr::Array { elements, .. } => {
+                let elements: Vec<String> = elements.iter().map(|e| e.string()).collect();
+                format!("[{}]", elements.join(", "))
+            }
+            Expr::Map { entries, .. } => {
+                let entries: Vec<String> = entries.iter().map(|(k, v)| format!("{}: {}", k.string(), v.string())).collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+            Expr::Index { object, index, .. } => format!("{}[{}]", object.string(), index.string()),
+            Expr::SetIndex { object, index, expr, .. } => format!("({}[{}] = {})", object.string(), index.string(), expr.string()),
+            Expr::Range { start, end, inclusive, .. } => {
+                let op = if *inclusive { "..=" } else { ".." };
+                format!("({}{}{})", start.string(), op, end.string())
+            }
+            Expr::Call { callee, args, .. } => {
+                let args: Vec<String> = args.iter().map(|a| a.string()).collect();
+                format!("{}({})", callee.string(), args.join(", "))
+            }
+            Expr::Get { object, name, .. } => format!("{}.{:?}", object.string(), name),
+            Expr::Set { object, name, expr, .. } => format!("({}.{:?} = {})", object.string(), name, expr.string()),
+            Expr::SelfRef { .. } => "self".to_string(),
+            Expr::ImplicitMember { name, .. } => format!(".{:?}", name),
+            Expr::Lambda { params, body, .. } => {
+                let params: Vec<String> = params.iter().map(|p| format!("{:?}", p.name)).collect();
+                format!("fun({}) <{} stmts> end", params.join(", "), body.len())
+            }
         }
     }
 }
 
 #[derive(Clone, Debug, PartialEq)]
 pub(crate) enum Stmt {
-    FunDeclaration { name: Token, params: Vec<Token>, body: Vec<Stmt> },
-    Block { stmts: Vec<Stmt> },
-    Return { expr: Option<Expr> },
-    Expression { expr: Expr },
-    Let { name: Token, expr: Expr },
-    Print { expr: Expr },
-    If { condition: Expr, consequence: Vec<Stmt>, alternative: Option<Vec<Stmt>> }
+    // `doc` is the text of the `///` comments right above a declaration
+    FunDeclaration { name: Token, params: Vec<Param>, return_type: Option<Token>, body: Vec<Stmt>, doc: Option<String>, id: NodeId },
+    Return { expr: Option<Expr>, id: NodeId },
+    Expression { expr: Expr, id: NodeId },
+    Let { name: Token, type_name: Option<Token>, expr: Expr, id: NodeId },
+    Print { expr: Expr, id: NodeId },
+    If { condition: Expr, consequence: Vec<Stmt>, alternative: Option<Vec<Stmt>>, id: NodeId },
+    // `value` is the second name of `for k, v in m`
+    For { name: Token, value: Option<Token>, iterable: Expr, body: Vec<Stmt>, id: NodeId },
+    While { condition: Expr, body: Vec<Stmt>, id: NodeId },
+    // `protocols` are the `Expr::Name`s of the protocols the struct conforms to
+    Struct { name: Token, protocols: Vec<Expr>, fields: Vec<Field>, methods: Vec<Stmt>, doc: Option<String>, id: NodeId },
+    Protocol { name: Token, methods: Vec<Signature>, doc: Option<String>, id: NodeId },
+    Enum { name: Token, cases: Vec<Token>, doc: Option<String>, id: NodeId },
+    Break { id: NodeId },
+    Continue { id: NodeId },
+}
+
+impl Stmt {
+    pub(crate) fn id(&self) -> NodeId {
+        match self {
+            Stmt::FunDeclaration { id, .. } | Stmt::Return { id, .. } | Stmt::Expression { id, .. } |
+            Stmt::Let { id, .. } | Stmt::Print { id, .. } | Stmt::If { id, .. } | Stmt::For { id, .. } |
+            Stmt::While { id, .. } | Stmt::Struct { id, .. } | Stmt::Protocol { id, .. } | Stmt::Enum { id, .. } |
+            Stmt::Break { id, .. } | Stmt::Continue { id, .. } => *id,
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Field {
+    pub(crate) name: Token,
+    pub(crate) type_name: Token,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Param {
+    pub(crate) name: Token,
+    pub(crate) type_name: Option<Token>,
+}
+
+// a method a protocol requires, without a body
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Signature {
+    pub(crate) name: Token,
+    pub(crate) params: Vec<Param>,
+    pub(crate) return_type: Option<Token>,
+    pub(crate) doc: Option<String>,
 }
@@ -0,0 +1,516 @@
+use std::cmp::Ordering;
+use std::fmt;
+
+// the most bits a power or a left shift may produce, which is around a million decimal digits
+pub(crate) const MAX_BITS: u64 = 1 << 22;
+
+// an integer of any size, stored as a sign and a magnitude of base 2^32 limbs, least significant first.
+// the magnitude never ends in a zero limb and zero is never negative, so equal numbers are represented the same way
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct BigInt {
+    negative: bool,
+    limbs: Vec<u32>,
+}
+
+impl From<i64> for BigInt {
+    fn from(i: i64) -> Self {
+        let m = i.unsigned_abs();
+        BigInt::new(i < 0, vec![m as u32, (m >> 32) as u32])
+    }
+}
+
+impl BigInt {
+    fn new(negative: bool, mut limbs: Vec<u32>) -> Self {
+        while limbs.last() == Some(&0) {
+            limbs.pop();
+        }
+        BigInt { negative: negative && !limbs.is_empty(), limbs }
+    }
+
+    pub(crate) fn is_zero(&self) -> bool {
+        self.limbs.is_empty()
+    }
+
+    pub(crate) fn to_i64(&self) -> Option<i64> {
+        if self.limbs.len() > 2 {
+            return None;
+        }
+        let m = self.limbs.iter().rev().fold(0u64, |m, &l| (m << 32) | l as u64);
+        if self.negative {
+            0i64.checked_sub_unsigned(m)
+        } else {
+            i64::try_from(m).ok()
+        }
+    }
+
+    pub(crate) fn to_f64(&self) -> f64 {
+        let m = self.limbs.iter().rev().fold(0.0, |m, &l| m * 4294967296.0 + l as f64);
+        if self.negative { -m } else { m }
+    }
+
+    // truncates towards zero, or gives none for NaN and infinity
+    pub(crate) fn from_f64(f: f64) -> Option<Self> {
+        if !f.is_finite() {
+            return None;
+        }
+        let t = f.trunc().abs();
+        if t < 18446744073709551616.0 {
+            let m = t as u64;
+            return Some(BigInt::new(f < 0.0, vec![m as u32, (m >> 32) as u32]));
+        }
+        // a float this large is its 53 bit mantissa shifted left by its exponent
+        let bits = t.to_bits();
+        let exponent = ((bits >> 52) & 0x7ff) as usize - 1075;
+        let mantissa = (bits & ((1 << 52) - 1)) | (1 << 52);
+        let limbs = shl(&[mantissa as u32, (mantissa >> 32) as u32], exponent);
+        Some(BigInt::new(f < 0.0, limbs))
+    }
+
+    // parses an optionally signed run of decimal digits
+    pub(crate) fn parse(s: &str) -> Option<Self> {
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s.strip_prefix('+').unwrap_or(s)),
+        };
+        if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let mut limbs = Vec::new();
+        for chunk in digits.as_bytes().chunks(9) {
+            let value = std::str::from_utf8(chunk).ok()?.parse::<u32>().ok()?;
+            limbs = mul_small(&limbs, 10u32.pow(chunk.len() as u32), value);
+        }
+        Some(BigInt::new(negative, limbs))
+    }
+
+    // parses unsigned digits in `radix`, which the lexer uses for hex, binary and octal literals
+    pub(crate) fn parse_radix(digits: &str, radix: u32) -> Option<Self> {
+        if digits.is_empty() {
+            return None;
+        }
+        let mut limbs = Vec::new();
+        for c in digits.chars() {
+            limbs = mul_small(&limbs, radix, c.to_digit(radix)?);
+        }
+        Some(BigInt::new(false, limbs))
+    }
+
+    pub(crate) fn neg(&self) -> Self {
+        BigInt::new(!self.negative, self.limbs.clone())
+    }
+
+    pub(crate) fn abs(&self) -> Self {
+        BigInt::new(false, self.limbs.clone())
+    }
+
+    pub(crate) fn add(&self, other: &BigInt) -> Self {
+        if self.negative == other.negative {
+            return BigInt::new(self.negative, add(&self.limbs, &other.limbs));
+        }
+        // the signs differ, so the result has the sign of whichever is larger
+        match cmp(&self.limbs, &other.limbs) {
+            Ordering::Less => BigInt::new(other.negative, sub(&other.limbs, &self.limbs)),
+            _ => BigInt::new(self.negative, sub(&self.limbs, &other.limbs)),
+        }
+    }
+
+    pub(crate) fn sub(&self, other: &BigInt) -> Self {
+        self.add(&other.neg())
+    }
+
+    pub(crate) fn mul(&self, other: &BigInt) -> Self {
+        let mut limbs = vec![0u32; self.limbs.len() + other.limbs.len()];
+        for (i, &a) in self.limbs.iter().enumerate() {
+            let mut carry = 0u64;
+            for (j, &b) in other.limbs.iter().enumerate() {
+                let t = limbs[i + j] as u64 + a as u64 * b as u64 + carry;
+                limbs[i + j] = t as u32;
+                carry = t >> 32;
+            }
+            limbs[i + other.limbs.len()] = carry as u32;
+        }
+        BigInt::new(self.negative != other.negative, limbs)
+    }
+
+    // the quotient truncated towards zero and a remainder with the sign of the dividend, like rust's ints,
+    // or none when dividing by zero
+    pub(crate) fn div_rem(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        if other.is_zero() {
+            return None;
+        }
+        let (q, r) = div_rem(&self.limbs, &other.limbs);
+        Some((BigInt::new(self.negative != other.negative, q), BigInt::new(self.negative, r)))
+    }
+
+    // the quotient rounded down and a remainder with the sign of the divisor, or none when dividing by zero
+    pub(crate) fn div_mod_floor(&self, other: &BigInt) -> Option<(BigInt, BigInt)> {
+        let (q, r) = self.div_rem(other)?;
+        if !r.is_zero() && r.negative != other.negative {
+            return Some((q.sub(&BigInt::from(1)), r.add(other)));
+        }
+        Some((q, r))
+    }
+
+    // expects a non-negative exponent, and gives none when the result would take more than `MAX_BITS` bits
+    pub(crate) fn pow(&self, exponent: &BigInt) -> Option<Self> {
+        let mut exponent = if self.bits() <= 1 {
+            // powers of 0, 1 and -1 only depend on whether the exponent is 0 and whether it's odd
+            match exponent.limbs.first() {
+                None => 0,
+                Some(l) => 2 - (l & 1) as u64,
+            }
+        } else {
+            let e = exponent.to_i64()? as u64;
+            if self.bits().saturating_mul(e) > MAX_BITS {
+                return None;
+            }
+            e
+        };
+        let mut result = BigInt::from(1);
+        let mut base = self.clone();
+        while exponent > 0 {
+            if exponent & 1 == 1 {
+                result = result.mul(&base);
+            }
+            exponent >>= 1;
+            if exponent > 0 {
+                base = base.mul(&base);
+            }
+        }
+        Some(result)
+    }
+
+    pub(crate) fn shl(&self, bits: u64) -> Self {
+        BigInt::new(self.negative, shl(&self.limbs, bits as usize))
+    }
+
+    // rounds down like shifting a two's complement int does
+    pub(crate) fn shr(&self, bits: u64) -> Self {
+        if !self.negative {
+            return BigInt::new(false, shr(&self.limbs, bits));
+        }
+        // -m >> k is -((m - 1) >> k) - 1
+        let m = BigInt::new(false, sub(&self.limbs, &[1]));
+        BigInt::new(true, shr(&m.limbs, bits)).sub(&BigInt::from(1))
+    }
+
+    // ~x is -x - 1 in two's complement
+    pub(crate) fn not(&self) -> Self {
+        self.neg().sub(&BigInt::from(1))
+    }
+
+    pub(crate) fn and(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |a, b| a & b)
+    }
+
+    pub(crate) fn or(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |a, b| a | b)
+    }
+
+    pub(crate) fn xor(&self, other: &BigInt) -> Self {
+        self.bitwise(other, |a, b| a ^ b)
+    }
+
+    // applies `op` to the two's complement forms, which get a limb more than either magnitude
+    // so that the top limb only holds sign bits
+    fn bitwise(&self, other: &BigInt, op: fn(u32, u32) -> u32) -> Self {
+        let len = self.limbs.len().max(other.limbs.len()) + 1;
+        let a = self.twos_complement(len);
+        let b = other.twos_complement(len);
+        let mut limbs: Vec<u32> = a.iter().zip(&b).map(|(&a, &b)| op(a, b)).collect();
+        let negative = limbs[len - 1] >> 31 == 1;
+        if negative {
+            negate(&mut limbs);
+        }
+        BigInt::new(negative, limbs)
+    }
+
+    fn twos_complement(&self, len: usize) -> Vec<u32> {
+        let mut limbs = self.limbs.clone();
+        limbs.resize(len, 0);
+        if self.negative {
+            negate(&mut limbs);
+        }
+        limbs
+    }
+
+    // how many bits the magnitude takes up
+    pub(crate) fn bits(&self) -> u64 {
+        match self.limbs.last() {
+            Some(&top) => self.limbs.len() as u64 * 32 - top.leading_zeros() as u64,
+            None => 0,
+        }
+    }
+}
+
+impl Ord for BigInt {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.negative, other.negative) {
+            (false, true) => Ordering::Greater,
+            (true, false) => Ordering::Less,
+            (false, false) => cmp(&self.limbs, &other.limbs),
+            (true, true) => cmp(&other.limbs, &self.limbs),
+        }
+    }
+}
+
+impl PartialOrd for BigInt {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl fmt::Display for BigInt {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        // peel off nine decimal digits at a time, least significant first
+        let mut chunks = Vec::new();
+        let mut limbs = self.limbs.clone();
+        while !limbs.is_empty() {
+            let (q, r) = div_small(&limbs, 1_000_000_000);
+            chunks.push(r);
+            limbs = q;
+        }
+        if self.negative {
+            write!(f, "-")?;
+        }
+        write!(f, "{}", chunks.pop().unwrap())?;
+        for chunk in chunks.iter().rev() {
+            write!(f, "{:09}", chunk)?;
+        }
+        Ok(())
+    }
+}
+
+fn trim(mut limbs: Vec<u32>) -> Vec<u32> {
+    while limbs.last() == Some(&0) {
+        limbs.pop();
+    }
+    limbs
+}
+
+fn cmp(a: &[u32], b: &[u32]) -> Ordering {
+    a.len().cmp(&b.len()).then_with(|| a.iter().rev().cmp(b.iter().rev()))
+}
+
+fn add(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (a, b) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+    let mut limbs = Vec::with_capacity(a.len() + 1);
+    let mut carry = 0u64;
+    for (i, &x) in a.iter().enumerate() {
+        let t = x as u64 + *b.get(i).unwrap_or(&0) as u64 + carry;
+        limbs.push(t as u32);
+        carry = t >> 32;
+    }
+    limbs.push(carry as u32);
+    trim(limbs)
+}
+
+// expects a to be at least b
+fn sub(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len());
+    let mut borrow = 0i64;
+    for (i, &x) in a.iter().enumerate() {
+        let mut t = x as i64 - *b.get(i).unwrap_or(&0) as i64 - borrow;
+        borrow = 0;
+        if t < 0 {
+            t += 1 << 32;
+            borrow = 1;
+        }
+        limbs.push(t as u32);
+    }
+    trim(limbs)
+}
+
+// a * factor + addend
+fn mul_small(a: &[u32], factor: u32, addend: u32) -> Vec<u32> {
+    let mut limbs = Vec::with_capacity(a.len() + 1);
+    let mut carry = addend as u64;
+    for &x in a {
+        let t = x as u64 * factor as u64 + carry;
+        limbs.push(t as u32);
+        carry = t >> 32;
+    }
+    limbs.push(carry as u32);
+    trim(limbs)
+}
+
+fn div_small(a: &[u32], divisor: u32) -> (Vec<u32>, u32) {
+    let mut q = vec![0u32; a.len()];
+    let mut r = 0u64;
+    for i in (0..a.len()).rev() {
+        let t = (r << 32) | a[i] as u64;
+        q[i] = (t / divisor as u64) as u32;
+        r = t % divisor as u64;
+    }
+    (trim(q), r as u32)
+}
+
+fn shl(a: &[u32], bits: usize) -> Vec<u32> {
+    let (limbs, bits) = (bits / 32, bits % 32);
+    let mut result = vec![0u32; limbs];
+    let mut carry = 0u32;
+    for &x in a {
+        if bits == 0 {
+            result.push(x);
+        } else {
+            result.push((x << bits) | carry);
+            carry = x >> (32 - bits);
+        }
+    }
+    result.push(carry);
+    trim(result)
+}
+
+fn shr(a: &[u32], bits: u64) -> Vec<u32> {
+    let limbs = (bits / 32) as usize;
+    let bits = bits % 32;
+    if limbs >= a.len() {
+        return Vec::new();
+    }
+    let a = &a[limbs..];
+    let mut result = Vec::with_capacity(a.len());
+    for (i, &x) in a.iter().enumerate() {
+        let high = if bits == 0 { 0 } else { a.get(i + 1).map_or(0, |&h| h << (32 - bits)) };
+        result.push((x >> bits) | high);
+    }
+    trim(result)
+}
+
+// flips a two's complement number's sign in place
+fn negate(limbs: &mut [u32]) {
+    let mut carry = true;
+    for l in limbs {
+        let (v, c) = (!*l).overflowing_add(carry as u32);
+        *l = v;
+        carry = c;
+    }
+}
+
+// schoolbook long division one bit at a time, which is plenty for the sizes scripts work with
+fn div_rem(a: &[u32], b: &[u32]) -> (Vec<u32>, Vec<u32>) {
+    if b.len() == 1 {
+        let (q, r) = div_small(a, b[0]);
+        return (q, trim(vec![r]));
+    }
+    if cmp(a, b) == Ordering::Less {
+        return (Vec::new(), a.to_vec());
+    }
+    let mut q = vec![0u32; a.len()];
+    let mut r: Vec<u32> = Vec::new();
+    for i in (0..a.len() * 32).rev() {
+        r = shl(&r, 1);
+        if (a[i / 32] >> (i % 32)) & 1 == 1 {
+            if r.is_empty() {
+                r.push(1);
+            } else {
+                r[0] |= 1;
+            }
+        }
+        if cmp(&r, b) != Ordering::Less {
+            r = sub(&r, b);
+            q[i / 32] |= 1 << (i % 32);
+        }
+    }
+    (trim(q), r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::BigInt;
+
+    fn big(s: &str) -> BigInt {
+        BigInt::parse(s).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        for s in ["0", "1", "-1", "4294967296", "-9223372036854775808", "123456789012345678901234567890"] {
+            assert_eq!(big(s).to_string(), s);
+        }
+        assert_eq!(big("+42").to_string(), "42");
+        assert_eq!(big("-0").to_string(), "0");
+        assert!(BigInt::parse("12a").is_none());
+        assert!(BigInt::parse("-").is_none());
+        assert_eq!(BigInt::parse_radix("ffffffffffffffffff", 16).unwrap().to_string(), "4722366482869645213695");
+        assert_eq!(BigInt::parse_radix("1".repeat(64).as_str(), 2).unwrap().to_string(), "18446744073709551615");
+        assert!(BigInt::parse_radix("19", 8).is_none());
+    }
+
+    #[test]
+    fn test_i64_conversions() {
+        assert_eq!(BigInt::from(i64::MIN).to_i64(), Some(i64::MIN));
+        assert_eq!(BigInt::from(i64::MAX).to_i64(), Some(i64::MAX));
+        assert_eq!(big("9223372036854775808").to_i64(), None);
+        assert_eq!(big("-9223372036854775809").to_i64(), None);
+        assert_eq!(BigInt::from(-5), big("-5"));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let a = big("123456789012345678901234567890");
+        let b = big("-987654321098765432109876543210");
+        assert_eq!(a.add(&b).to_string(), "-864197532086419753208641975320");
+        assert_eq!(a.sub(&b).to_string(), "1111111110111111111011111111100");
+        assert_eq!(a.mul(&b).to_string(), "-121932631137021795226185032733622923332237463801111263526900");
+        assert_eq!(a.add(&a.neg()), BigInt::from(0));
+        assert_eq!(BigInt::from(2).pow(&BigInt::from(100)).unwrap().to_string(), "1267650600228229401496703205376");
+        assert_eq!(BigInt::from(-1).pow(&big("100000000000000000001")), Some(BigInt::from(-1)));
+        assert_eq!(BigInt::from(0).pow(&BigInt::from(0)), Some(BigInt::from(1)));
+        assert!(BigInt::from(2).pow(&BigInt::from(1 << 22)).is_none());
+    }
+
+    #[test]
+    fn test_div_rem() {
+        let (q, r) = big("-121932631137021795226185032733622923332237463801111263526907")
+            .div_rem(&big("123456789012345678901234567890"))
+            .unwrap();
+        assert_eq!(q.to_string(), "-987654321098765432109876543210");
+        assert_eq!(r.to_string(), "-7");
+        let (q, r) = big("100000000000000000000").div_rem(&BigInt::from(-7)).unwrap();
+        assert_eq!((q.to_string(), r.to_string()), ("-14285714285714285714".to_string(), "2".to_string()));
+        assert!(BigInt::from(1).div_rem(&BigInt::from(0)).is_none());
+    }
+
+    #[test]
+    fn test_div_mod_floor() {
+        for (a, b, q, r) in [(7, 2, 3, 1), (-7, 2, -4, 1), (7, -2, -4, -1), (-7, -2, 3, -1), (6, -3, -2, 0)] {
+            assert_eq!(BigInt::from(a).div_mod_floor(&BigInt::from(b)), Some((BigInt::from(q), BigInt::from(r))));
+        }
+    }
+
+    #[test]
+    fn test_bitwise() {
+        let values = [0i64, 1, -1, 5, -6, 123456789, -987654321, i64::MAX, i64::MIN];
+        for &a in &values {
+            for &b in &values {
+                let (x, y) = (BigInt::from(a), BigInt::from(b));
+                assert_eq!(x.and(&y), BigInt::from(a & b));
+                assert_eq!(x.or(&y), BigInt::from(a | b));
+                assert_eq!(x.xor(&y), BigInt::from(a ^ b));
+            }
+            assert_eq!(BigInt::from(a).not(), BigInt::from(!a));
+            for bits in [0, 1, 5, 31, 32, 33, 63] {
+                assert_eq!(BigInt::from(a).shr(bits), BigInt::from(a >> bits));
+            }
+        }
+        assert_eq!(BigInt::from(-3).shl(100).to_string(), "-3802951800684688204490109616128");
+        assert_eq!(big("-3802951800684688204490109616128").shr(99), BigInt::from(-6));
+        assert_eq!(big("-3802951800684688204490109616129").shr(100), BigInt::from(-4));
+        assert_eq!(BigInt::from(-1).shr(1000), BigInt::from(-1));
+        assert_eq!(big("-36893488147419103232").and(&big("36893488147419103231")), BigInt::from(0));
+    }
+
+    #[test]
+    fn test_ordering_and_floats() {
+        assert!(big("-100000000000000000000") < BigInt::from(-1));
+        assert!(big("100000000000000000000") > big("99999999999999999999"));
+        assert_eq!(big("100000000000000000000").to_f64(), 1e20);
+        assert_eq!(BigInt::from_f64(-1e20).unwrap().to_string(), "-100000000000000000000");
+        assert_eq!(BigInt::from_f64(2.9).unwrap(), BigInt::from(2));
+        assert!(BigInt::from_f64(f64::NAN).is_none());
+        assert_eq!(big("-18446744073709551616").bits(), 65);
+    }
+}
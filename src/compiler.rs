@@ -1,94 +1,899 @@
-use crate::ast::{Expr, Stmt};
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use crate::ast::{Expr, NodeId, Param, Stmt};
+use crate::error::Error;
 use crate::token::Token;
-use crate::vm::VM;
 use crate::parser::Program;
 use crate::opcodes::OpCode;
+use crate::resolver::{self, Binding, Bindings, Captures};
 use crate::visitor::{ExprVisitor, StmtVisitor};
 
+struct Local {
+    name: String,
+    depth: usize,
+    type_name: Option<String>,
+    // captured locals are moved into their upvalue rather than popped when they go out of scope
+    captured: bool,
+}
+
+struct Loop {
+    // where `continue` jumps back to
+    start: usize,
+    // locals deeper than this are popped when leaving the loop early
+    depth: usize,
+    breaks: Vec<usize>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+pub(crate) enum FunctionKind {
+    Script, Function, Method,
+}
+
+// the per-function parts of the compiler, saved while a nested function is compiled
+struct FunctionState {
+    locals: Vec<Local>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    kind: FunctionKind,
+}
+
+// the declared type of each param of a function, `None` when unannotated
+type ParamTypes = Vec<Option<String>>;
+
+// what the compiler knows about a struct: its fields with their types and its methods' param types
+#[derive(Clone)]
+struct StructInfo {
+    fields: Vec<(String, String)>,
+    methods: HashMap<String, ParamTypes>,
+}
+
 struct Compiler {
     code: Vec<u8>,
+    locals: Vec<Local>,
+    scope_depth: usize,
+    loops: Vec<Loop>,
+    kind: FunctionKind,
+    enclosing: Vec<FunctionState>,
+    // the struct whose methods are being compiled, whose members method bodies can use by bare name
+    current_struct: Option<String>,
+    // declared types and signatures, used to resolve `.case` shorthand from the expected type
+    structs: HashMap<String, StructInfo>,
+    enums: HashSet<String>,
+    // the required methods of each protocol with their param types
+    protocols: HashMap<String, Vec<(String, ParamTypes)>>,
+    functions: HashMap<String, ParamTypes>,
+    global_types: HashMap<String, String>,
+    // where each name in the program lives and what each function captures, worked out by the resolver
+    bindings: Bindings,
+    captures: Captures,
+    errors: Vec<Error>,
 }
 
-fn compile(program: Program) -> Vec<u8> {
-    let mut compiler = Compiler::new();
+// `globals` are the names the vm defines before running the program, like registered native functions
+pub(crate) fn compile(program: Program, globals: &[String]) -> Result<Vec<u8>, Vec<Error>> {
+    let (bindings, captures) = resolver::resolve(&program, globals)?;
+    let mut compiler = Compiler::new(bindings, captures);
     compiler.compile(program);
-    compiler.code
+    if compiler.errors.is_empty() {
+        Ok(compiler.code)
+    } else {
+        Err(compiler.errors)
+    }
 }
 
 impl Compiler {
-    fn new() -> Self {
+    fn new(bindings: Bindings, captures: Captures) -> Self {
         Compiler {
             code: vec![],
+            locals: vec![],
+            scope_depth: 0,
+            loops: vec![],
+            kind: FunctionKind::Script,
+            enclosing: vec![],
+            current_struct: None,
+            structs: HashMap::new(),
+            enums: HashSet::new(),
+            protocols: HashMap::new(),
+            functions: HashMap::new(),
+            global_types: HashMap::new(),
+            bindings,
+            captures,
+            errors: vec![],
         }
     }
 
     fn compile(&mut self, program: Program) {
-        for stmt in program {
+        // top level declarations can be used before they appear
+        for stmt in &program {
+            self.declare(stmt);
+        }
+
+        for (i, stmt) in program.iter().enumerate() {
             match stmt {
-                Stmt::Expression { .. } => self.visit_expr_stmt(&stmt),
-                _ => todo!("remove when all stmts compiled"),
+                // the value of a trailing expression is left on the stack as the program's result
+                Stmt::Expression { expr, .. } if i == program.len() - 1 => self.compile_expr(expr),
+                _ => self.compile_stmt(stmt),
+            }
+        }
+    }
+
+    fn compile_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { .. } => self.visit_expr_stmt(stmt),
+            Stmt::Let { .. } => self.visit_let(stmt),
+            Stmt::Print { .. } => self.visit_print(stmt),
+            Stmt::For { .. } => self.visit_for(stmt),
+            Stmt::If { .. } => self.visit_if(stmt),
+            Stmt::While { .. } => self.visit_while(stmt),
+            Stmt::Break { .. } => self.visit_break(stmt),
+            Stmt::Continue { .. } => self.visit_continue(stmt),
+            Stmt::FunDeclaration { .. } => self.visit_fun(stmt),
+            Stmt::Return { .. } => self.visit_return(stmt),
+            Stmt::Struct { .. } => self.visit_struct(stmt),
+            Stmt::Enum { .. } => self.visit_enum(stmt),
+            Stmt::Protocol { .. } => self.visit_protocol(stmt),
+        }
+    }
+
+    // records the types and signatures a declaration introduces
+    fn declare(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FunDeclaration { name, params, .. } => {
+                self.functions.insert(Self::ident(name).to_string(), Self::param_types(params));
+            }
+            Stmt::Struct { name, fields, methods, .. } => {
+                let fields = fields.iter()
+                    .map(|f| (Self::ident(&f.name).to_string(), Self::ident(&f.type_name).to_string()))
+                    .collect();
+                let mut signatures = HashMap::new();
+                for method in methods {
+                    if let Stmt::FunDeclaration { name, params, .. } = method {
+                        signatures.insert(Self::ident(name).to_string(), Self::param_types(params));
+                    }
+                }
+                self.structs.insert(Self::ident(name).to_string(), StructInfo { fields, methods: signatures });
+            }
+            Stmt::Enum { name, .. } => {
+                self.enums.insert(Self::ident(name).to_string());
+            }
+            Stmt::Protocol { name, methods, .. } => {
+                let methods = methods.iter()
+                    .map(|m| (Self::ident(&m.name).to_string(), Self::param_types(&m.params)))
+                    .collect();
+                self.protocols.insert(Self::ident(name).to_string(), methods);
+            }
+            _ => (),
+        }
+    }
+
+    // every method a protocol requires must be implemented with the same number of params
+    fn check_conformance(&mut self, struct_name: &str, protocol: &str) {
+        let required = match self.protocols.get(protocol) {
+            Some(required) => required.clone(),
+            None => {
+                self.errors.push(Error::new(&format!("{} conforms to unknown protocol '{}'", struct_name, protocol)));
+                return;
+            }
+        };
+        let methods = self.structs.get(struct_name).map(|s| s.methods.clone()).unwrap_or_default();
+
+        for (method, params) in required {
+            match methods.get(&method) {
+                None => self.errors.push(Error::new(&format!(
+                    "{} does not implement '{}' required by {}", struct_name, method, protocol
+                ))),
+                Some(implemented) if implemented.len() != params.len() => self.errors.push(Error::new(&format!(
+                    "{}.{} takes {} params but {} requires {}", struct_name, method, implemented.len(), protocol, params.len()
+                ))),
+                Some(_) => (),
+            }
+        }
+    }
+
+    fn param_types(params: &[Param]) -> ParamTypes {
+        params.iter().map(|p| p.type_name.as_ref().map(|t| Self::ident(t).to_string())).collect()
+    }
+
+    fn struct_info(&self) -> Option<&StructInfo> {
+        self.current_struct.as_ref().and_then(|name| self.structs.get(name))
+    }
+
+    // the declared type of an expression, when it can be known without a type checker
+    fn static_type(&self, expr: &Expr) -> Option<String> {
+        match expr {
+            Expr::Name { val, .. } => self.variable_type(val),
+            Expr::SelfRef { .. } => self.current_struct.clone(),
+            Expr::Get { object, name, .. } => {
+                if let Expr::Name { val, .. } = object.as_ref() {
+                    if self.enums.contains(val) && self.resolve_local(val).is_none() {
+                        return Some(val.clone());
+                    }
+                }
+                self.field_type(&self.static_type(object)?, Self::ident(name))
+            }
+            Expr::Call { callee, .. } => match callee.as_ref() {
+                Expr::Name { val, .. } if self.structs.contains_key(val) => Some(val.clone()),
+                _ => None,
+            },
+            Expr::Grouping { expr, .. } => self.static_type(expr),
+            _ => None,
+        }
+    }
+
+    fn variable_type(&self, name: &str) -> Option<String> {
+        match self.resolve_local(name) {
+            Some(slot) => self.locals[slot as usize].type_name.clone(),
+            None if self.is_member(name) => self.field_type(self.current_struct.as_ref()?, name),
+            None => self.global_types.get(name).cloned(),
+        }
+    }
+
+    fn field_type(&self, struct_name: &str, field: &str) -> Option<String> {
+        let info = self.structs.get(struct_name)?;
+        info.fields.iter().find(|(name, _)| name == field).map(|(_, t)| t.clone())
+    }
+
+    // the declared param types of whatever `callee` calls, for struct constructors the field types
+    fn callee_param_types(&self, callee: &Expr) -> Option<ParamTypes> {
+        match callee {
+            Expr::Name { val, .. } if self.resolve_local(val).is_none() => {
+                if self.is_member(val) {
+                    return self.struct_info()?.methods.get(val).cloned();
+                }
+                if let Some(info) = self.structs.get(val) {
+                    return Some(info.fields.iter().map(|(_, t)| Some(t.clone())).collect());
+                }
+                self.functions.get(val).cloned()
+            }
+            Expr::Get { object, name, .. } => {
+                let type_name = self.static_type(object)?;
+                let name = Self::ident(name);
+                if let Some(methods) = self.protocols.get(&type_name) {
+                    return methods.iter().find(|(m, _)| m == name).map(|(_, params)| params.clone());
+                }
+                self.structs.get(&type_name)?.methods.get(name).cloned()
+            }
+            _ => None,
+        }
+    }
+
+    // compiles `expr` where a value of type `expected` is wanted, which is what `.case` shorthand resolves against
+    fn compile_expected(&mut self, expr: &Expr, expected: Option<String>) {
+        match expr {
+            Expr::ImplicitMember { name, .. } => {
+                let case = Self::ident(name);
+                match expected {
+                    Some(type_name) if !self.enums.contains(&type_name) => {
+                        self.errors.push(Error::new(&format!("'.{}' needs an enum but {} is not one", case, type_name)));
+                    }
+                    // types are only declared at the top level, so the enum is a global
+                    Some(enum_name) => {
+                        self.get_variable(&enum_name, Binding::Global);
+                        self.code.push(OpCode::GetField as u8);
+                        self.emit_string(case);
+                    }
+                    None => self.errors.push(Error::new(&format!("cannot infer the enum of '.{}'", case))),
+                }
             }
+            _ => self.compile_expr(expr),
+        }
+    }
+
+    fn compile_block(&mut self, stmts: &[Stmt]) {
+        self.begin_scope();
+        for stmt in stmts {
+            self.compile_stmt(stmt);
         }
+        self.end_scope();
     }
 
     fn compile_expr(&mut self, expr: &Expr) {
         match expr {
             Expr::Binary { .. } => self.visit_binary(expr),
+            Expr::Unary { .. } => self.visit_unary(expr),
             Expr::Int { .. } => self.visit_int(expr),
             Expr::Float { .. } => self.visit_float(expr),
-            _ => (),
+            Expr::BigInt { .. } => self.visit_big_int(expr),
+            Expr::String { .. } => self.visit_string(expr),
+            Expr::Bool { .. } => self.visit_bool(expr),
+            Expr::Nil { .. } => self.visit_nil(expr),
+            Expr::Interpolation { .. } => self.visit_interpolation(expr),
+            Expr::Grouping { .. } => self.visit_grouping(expr),
+            Expr::Name { .. } => self.visit_name(expr),
+            Expr::Assign { .. } => self.visit_assign(expr),
+            Expr::CompoundAssign { .. } => self.visit_compound_assign(expr),
+            Expr::Array { .. } => self.visit_array(expr),
+            Expr::Map { .. } => self.visit_map(expr),
+            Expr::Index { .. } => self.visit_index(expr),
+            Expr::SetIndex { .. } => self.visit_set_index(expr),
+            Expr::Range { .. } => self.visit_range(expr),
+            Expr::Call { .. } => self.visit_call(expr),
+            Expr::Get { .. } => self.visit_get(expr),
+            Expr::Set { .. } => self.visit_set(expr),
+            Expr::SelfRef { .. } => self.visit_self(expr),
+            Expr::ImplicitMember { .. } => self.compile_expected(expr, None),
+            Expr::Lambda { .. } => self.visit_lambda(expr),
+        }
+    }
+
+    // compiles the body out of line behind a jump and leaves the function value on the stack
+    fn function(&mut self, id: NodeId, name: &str, params: &[Param], body: &[Stmt], kind: FunctionKind) {
+        let jump = self.emit_jump(OpCode::Jump);
+        let address = self.code.len();
+
+        self.enclosing.push(FunctionState {
+            locals: mem::take(&mut self.locals),
+            scope_depth: self.scope_depth,
+            loops: mem::take(&mut self.loops),
+            kind: self.kind,
+        });
+        self.scope_depth = 1;
+        self.kind = kind;
+
+        // slot 0 holds the callee, or the receiver for methods
+        self.add_local(if kind == FunctionKind::Method { "self" } else { "" });
+        for param in params {
+            let slot = self.add_local(Self::ident(&param.name));
+            self.locals[slot as usize].type_name = param.type_name.as_ref().map(|t| Self::ident(t).to_string());
+        }
+        for stmt in body {
+            self.compile_stmt(stmt);
+        }
+        self.code.push(OpCode::Nil as u8);
+        self.code.push(OpCode::Return as u8);
+
+        let state = self.enclosing.pop().expect("enclosing function");
+        self.locals = state.locals;
+        self.scope_depth = state.scope_depth;
+        self.loops = state.loops;
+        self.kind = state.kind;
+
+        self.patch_jump(jump);
+        self.code.push(OpCode::Function as u8);
+        self.emit_byte_count(params.len(), &format!("parameters in {}", name));
+        self.emit_u32(address as u32);
+        self.emit_string(name);
+
+        if let Some(upvalues) = self.captures.get(&id).cloned() {
+            self.code.push(OpCode::Closure as u8);
+            self.emit_byte_count(upvalues.len(), &format!("variables captured by {}", name));
+            for upvalue in upvalues {
+                if upvalue.is_local {
+                    self.locals[upvalue.index as usize].captured = true;
+                }
+                self.code.push(upvalue.is_local as u8);
+                self.emit_u16(upvalue.index);
+            }
+        }
+    }
+
+    // binds the value on top of the stack to `name` in the current scope
+    fn define(&mut self, name: &str) {
+        self.define_typed(name, None);
+    }
+
+    fn define_typed(&mut self, name: &str, type_name: Option<String>) {
+        if self.scope_depth == 0 {
+            self.code.push(OpCode::DefineGlobal as u8);
+            self.emit_string(name);
+            match type_name {
+                Some(type_name) => self.global_types.insert(name.to_string(), type_name),
+                None => self.global_types.remove(name),
+            };
+        } else {
+            // the value is left on the stack and becomes the local's slot
+            let slot = self.add_local(name);
+            self.locals[slot as usize].type_name = type_name;
+        }
+    }
+
+    fn binding(&self, id: NodeId) -> Binding {
+        self.bindings[&id]
+    }
+
+    fn get_variable(&mut self, name: &str, binding: Binding) {
+        match binding {
+            Binding::Local(slot) => {
+                self.code.push(OpCode::GetLocal as u8);
+                self.emit_u16(slot);
+            }
+            Binding::Upvalue(index) => {
+                self.code.push(OpCode::GetUpvalue as u8);
+                self.emit_u16(index);
+            }
+            Binding::Member => {
+                self.code.push(OpCode::GetLocal as u8);
+                self.emit_u16(0);
+                self.code.push(OpCode::GetField as u8);
+                self.emit_string(name);
+            }
+            Binding::Global => {
+                self.code.push(OpCode::GetGlobal as u8);
+                self.emit_string(name);
+            }
         }
     }
 
+    // a member is set on `self`, which goes on the stack before the value
+    fn push_receiver(&mut self, binding: Binding) {
+        if binding == Binding::Member {
+            self.code.push(OpCode::GetLocal as u8);
+            self.emit_u16(0);
+        }
+    }
+
+    // stores the value on top of the stack, leaving it there as the assignment's result
+    fn set_variable(&mut self, name: &str, binding: Binding) {
+        match binding {
+            Binding::Local(slot) => {
+                self.code.push(OpCode::SetLocal as u8);
+                self.emit_u16(slot);
+            }
+            Binding::Upvalue(index) => {
+                self.code.push(OpCode::SetUpvalue as u8);
+                self.emit_u16(index);
+            }
+            Binding::Member => {
+                self.code.push(OpCode::SetField as u8);
+                self.emit_string(name);
+            }
+            Binding::Global => {
+                self.code.push(OpCode::SetGlobal as u8);
+                self.emit_string(name);
+            }
+        }
+    }
+
+    fn is_member(&self, name: &str) -> bool {
+        self.kind == FunctionKind::Method && self.struct_info()
+            .is_some_and(|s| s.fields.iter().any(|(f, _)| f == name) || s.methods.contains_key(name))
+    }
+
     fn add_op(&mut self, op: &Token) {
         match op {
             Token::Plus => self.code.push(OpCode::Add as u8),
             Token::Minus => self.code.push(OpCode::Subtract as u8),
             Token::Star => self.code.push(OpCode::Multiply as u8),
             Token::Slash => self.code.push(OpCode::Divide as u8),
+            Token::Percent => self.code.push(OpCode::Modulo as u8),
+            Token::Div => self.code.push(OpCode::FloorDivide as u8),
+            Token::StarStar => self.code.push(OpCode::Power as u8),
+            Token::Amp => self.code.push(OpCode::BitAnd as u8),
+            Token::Pipe => self.code.push(OpCode::BitOr as u8),
+            Token::Caret => self.code.push(OpCode::BitXor as u8),
+            Token::LtLt => self.code.push(OpCode::ShiftLeft as u8),
+            Token::GtGt => self.code.push(OpCode::ShiftRight as u8),
+            Token::EqEq => self.code.push(OpCode::Equal as u8),
+            Token::BangEq => {
+                self.code.push(OpCode::Equal as u8);
+                self.code.push(OpCode::Not as u8);
+            }
+            Token::Gt => self.code.push(OpCode::Greater as u8),
+            Token::GtEq => self.code.push(OpCode::GreaterEqual as u8),
+            Token::Lt => self.code.push(OpCode::Less as u8),
+            Token::Is => self.code.push(OpCode::Is as u8),
+            Token::LtEq => self.code.push(OpCode::LessEqual as u8),
             _ => (),
         }
     }
+
+    fn emit_u16(&mut self, val: u16) {
+        let bytes = val.to_be_bytes();
+        self.code.push(bytes[0]);
+        self.code.push(bytes[1]);
+    }
+
+    fn emit_u32(&mut self, val: u32) {
+        let bytes = val.to_be_bytes();
+        self.code.push(bytes[0]);
+        self.code.push(bytes[1]);
+        self.code.push(bytes[2]);
+        self.code.push(bytes[3]);
+    }
+
+    // a count that doesn't fit its u16 operand would silently wrap, so it's an error instead
+    fn emit_count(&mut self, count: usize, what: &str) {
+        if count > u16::MAX as usize {
+            self.errors.push(Error::new(&format!("too many {} (the most is {})", what, u16::MAX)));
+        }
+        self.emit_u16(count as u16);
+    }
+
+    fn emit_byte_count(&mut self, count: usize, what: &str) {
+        if count > u8::MAX as usize {
+            self.errors.push(Error::new(&format!("too many {} (the most is {})", what, u8::MAX)));
+        }
+        self.code.push(count as u8);
+    }
+
+    // strings and names are stored inline as a 4 byte length followed by their utf-8 bytes
+    fn emit_string(&mut self, val: &str) {
+        self.emit_u32(val.len() as u32);
+        self.code.extend_from_slice(val.as_bytes());
+    }
+
+    // emits a jump with a placeholder offset and returns the placeholder's position for `patch_jump`
+    fn emit_jump(&mut self, op: OpCode) -> usize {
+        self.code.push(op as u8);
+        self.emit_u16(0xFFFF);
+        self.code.len() - 2
+    }
+
+    fn emit_loop(&mut self, loop_start: usize) {
+        self.code.push(OpCode::Loop as u8);
+        let offset = self.code.len() - loop_start + 2;
+        if offset > u16::MAX as usize {
+            self.errors.push(Error::new("loop body is too large"));
+        }
+        self.emit_u16(offset as u16);
+    }
+
+    // overwrites the u16 placeholder at `at` with the distance from the end of the placeholder to here
+    fn patch_jump(&mut self, at: usize) {
+        let offset = self.code.len() - at - 2;
+        if offset > u16::MAX as usize {
+            self.errors.push(Error::new("too much code to jump over"));
+        }
+        let bytes = (offset as u16).to_be_bytes();
+        self.code[at] = bytes[0];
+        self.code[at + 1] = bytes[1];
+    }
+
+    fn begin_scope(&mut self) {
+        self.scope_depth += 1;
+    }
+
+    fn end_scope(&mut self) {
+        self.scope_depth -= 1;
+        while self.locals.last().is_some_and(|l| l.depth > self.scope_depth) {
+            let local = self.locals.pop().expect("local");
+            self.code.push(if local.captured { OpCode::CloseUpvalue } else { OpCode::Pop } as u8);
+        }
+    }
+
+    // pops the locals of scopes being jumped out of without forgetting them at compile time
+    fn pop_locals_above(&mut self, depth: usize) {
+        let ops: Vec<OpCode> = self.locals.iter().rev()
+            .take_while(|l| l.depth > depth)
+            .map(|l| if l.captured { OpCode::CloseUpvalue } else { OpCode::Pop })
+            .collect();
+        for op in ops {
+            self.code.push(op as u8);
+        }
+    }
+
+    fn add_local(&mut self, name: &str) -> u16 {
+        self.locals.push(Local { name: name.to_string(), depth: self.scope_depth, type_name: None, captured: false });
+        (self.locals.len() - 1) as u16
+    }
+
+    fn resolve_local(&self, name: &str) -> Option<u16> {
+        self.locals.iter().rposition(|l| l.name == name).map(|i| i as u16)
+    }
+
+    fn ident(token: &Token) -> &str {
+        match token {
+            Token::Ident(name) => name,
+            _ => unreachable!(),
+        }
+    }
 }
 
 impl StmtVisitor for Compiler {
+    type Output = ();
+
     fn visit_expr_stmt(&mut self, stmt: &Stmt) {
         match stmt {
-            Stmt::Expression { expr } => self.compile_expr(expr),
-            _ => todo!("Error"),
+            Stmt::Expression { expr, .. } => {
+                self.compile_expr(expr);
+                self.code.push(OpCode::Pop as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_let(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, type_name, expr, .. } => {
+                let declared = type_name.as_ref().map(|t| Self::ident(t).to_string());
+                let type_name = declared.clone().or_else(|| self.static_type(expr));
+                self.compile_expected(expr, declared);
+                self.define_typed(Self::ident(name), type_name);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_print(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Print { expr, .. } => {
+                self.compile_expr(expr);
+                self.code.push(OpCode::Print as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_for(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::For { name, value, iterable, body, id } => {
+                self.begin_scope();
+
+                // hidden locals holding the sequence and the position of the next element
+                self.compile_expr(iterable);
+                let seq = self.add_local("for seq");
+                self.compile_expr(&Expr::Int { val: 0, id: *id });
+                self.add_local("for index");
+
+                let loop_start = self.code.len();
+                let op = if value.is_some() { OpCode::ForNextPair } else { OpCode::ForNext };
+                self.code.push(op as u8);
+                self.emit_u16(seq);
+                let exit = self.code.len();
+                self.emit_u16(0xFFFF);
+                self.loops.push(Loop { start: loop_start, depth: self.scope_depth, breaks: vec![] });
+
+                // ForNext pushes the element, giving every iteration a fresh loop variable
+                self.begin_scope();
+                self.add_local(Self::ident(name));
+                if let Some(value) = value {
+                    self.add_local(Self::ident(value));
+                }
+                for stmt in body {
+                    self.compile_stmt(stmt);
+                }
+                self.end_scope();
+
+                self.emit_loop(loop_start);
+                self.patch_jump(exit);
+                let ctx = self.loops.pop().expect("loop context");
+                for jump in ctx.breaks {
+                    self.patch_jump(jump);
+                }
+                self.end_scope();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_if(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::If { condition, consequence, alternative, .. } => {
+                self.compile_expr(condition);
+                let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+                self.code.push(OpCode::Pop as u8);
+                self.compile_block(consequence);
+
+                let else_jump = self.emit_jump(OpCode::Jump);
+                self.patch_jump(then_jump);
+                self.code.push(OpCode::Pop as u8);
+                if let Some(alternative) = alternative {
+                    self.compile_block(alternative);
+                }
+                self.patch_jump(else_jump);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_while(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::While { condition, body, .. } => {
+                let loop_start = self.code.len();
+                self.compile_expr(condition);
+                let exit = self.emit_jump(OpCode::JumpIfFalse);
+                self.code.push(OpCode::Pop as u8);
+
+                self.loops.push(Loop { start: loop_start, depth: self.scope_depth, breaks: vec![] });
+                self.compile_block(body);
+                self.emit_loop(loop_start);
+
+                self.patch_jump(exit);
+                self.code.push(OpCode::Pop as u8);
+                // breaks land after the condition's pop since they leave nothing on the stack
+                let ctx = self.loops.pop().expect("loop context");
+                for jump in ctx.breaks {
+                    self.patch_jump(jump);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_break(&mut self, _stmt: &Stmt) {
+        let depth = match self.loops.last() {
+            Some(ctx) => ctx.depth,
+            None => {
+                self.errors.push(Error::new("'break' outside of a loop"));
+                return;
+            }
+        };
+        self.pop_locals_above(depth);
+        let jump = self.emit_jump(OpCode::Jump);
+        self.loops.last_mut().expect("loop context").breaks.push(jump);
+    }
+
+    fn visit_continue(&mut self, _stmt: &Stmt) {
+        let (start, depth) = match self.loops.last() {
+            Some(ctx) => (ctx.start, ctx.depth),
+            None => {
+                self.errors.push(Error::new("'continue' outside of a loop"));
+                return;
+            }
+        };
+        self.pop_locals_above(depth);
+        self.emit_loop(start);
+    }
+
+    fn visit_fun(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FunDeclaration { name, params, body, id, .. } => {
+                let name = Self::ident(name);
+                if self.scope_depth == 0 {
+                    self.function(*id, name, params, body, FunctionKind::Function);
+                    self.define(name);
+                } else {
+                    // declared first so the function can capture itself and recurse
+                    self.add_local(name);
+                    self.function(*id, name, params, body, FunctionKind::Function);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_return(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return { expr, .. } => {
+                match expr {
+                    Some(expr) => self.compile_expr(expr),
+                    None => self.code.push(OpCode::Nil as u8),
+                }
+                self.code.push(OpCode::Return as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_struct(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Struct { name, protocols, fields, methods, .. } => {
+                self.declare(stmt);
+                let name = Self::ident(name);
+                for protocol in protocols {
+                    self.check_conformance(name, &protocol.string());
+                    self.compile_expr(protocol);
+                }
+                let mut method_names = vec![];
+
+                let enclosing = self.current_struct.replace(name.to_string());
+                for method in methods {
+                    if let Stmt::FunDeclaration { name, params, body, id, .. } = method {
+                        method_names.push(Self::ident(name));
+                        self.function(*id, Self::ident(name), params, body, FunctionKind::Method);
+                    }
+                }
+                self.current_struct = enclosing;
+
+                // the protocols followed by the methods' function values are on the stack in declaration order
+                self.code.push(OpCode::Struct as u8);
+                self.emit_string(name);
+                self.emit_byte_count(fields.len(), &format!("fields in {}", name));
+                for field in fields {
+                    self.emit_string(Self::ident(&field.name));
+                }
+                self.emit_byte_count(method_names.len(), &format!("methods in {}", name));
+                for method in method_names {
+                    self.emit_string(method);
+                }
+                self.emit_byte_count(protocols.len(), &format!("protocols for {}", name));
+                self.define(name);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_protocol(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Protocol { name, .. } => {
+                self.declare(stmt);
+                let name = Self::ident(name);
+                // conformance is checked at compile time so the vm only needs the protocol's identity
+                self.code.push(OpCode::Protocol as u8);
+                self.emit_string(name);
+                self.define(name);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_enum(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Enum { name, cases, .. } => {
+                self.declare(stmt);
+                let name = Self::ident(name);
+                self.code.push(OpCode::Enum as u8);
+                self.emit_string(name);
+                self.emit_byte_count(cases.len(), &format!("cases in {}", name));
+                for case in cases {
+                    self.emit_string(Self::ident(case));
+                }
+                self.define(name);
+            }
+            _ => unreachable!(),
         }
     }
 }
 
 impl ExprVisitor for Compiler {
+    type Output = ();
+
     fn visit_binary(&mut self, expr: &Expr) {
         match expr {
-            Expr::Binary { op, left, right } => {
+            Expr::Binary { op: Token::And, left, right, .. } => {
+                self.compile_expr(left);
+                let end = self.emit_jump(OpCode::JumpIfFalse);
+                self.code.push(OpCode::Pop as u8);
+                self.compile_expr(right);
+                self.patch_jump(end);
+            }
+            Expr::Binary { op: Token::Or, left, right, .. } => {
                 self.compile_expr(left);
+                let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+                let end = self.emit_jump(OpCode::Jump);
+                self.patch_jump(else_jump);
+                self.code.push(OpCode::Pop as u8);
                 self.compile_expr(right);
+                self.patch_jump(end);
+            }
+            Expr::Binary { op, left, right, .. } => {
+                // either side of a comparison can be `.case` shorthand for the other side's enum
+                let left_type = self.static_type(left);
+                let right_type = self.static_type(right);
+                self.compile_expected(left, right_type);
+                self.compile_expected(right, left_type);
                 self.add_op(op)
             }
-            _ => todo!("error")
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_unary(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Unary { op, right, .. } => {
+                self.compile_expr(right);
+                match op {
+                    Token::Minus => self.code.push(OpCode::Negate as u8),
+                    Token::Bang => self.code.push(OpCode::Not as u8),
+                    Token::Tilde => self.code.push(OpCode::BitNot as u8),
+                    _ => unreachable!(),
+                }
+            }
+            _ => unreachable!(),
         }
     }
 
     fn visit_int(&mut self, expr: &Expr) {
         match expr {
-            Expr::Int { val } => {
+            Expr::Int { val, .. } => {
                 self.code.push(OpCode::Int as u8);
                 let bytes = val.to_be_bytes();
                 self.code.push(bytes[0]);
                 self.code.push(bytes[1]);
                 self.code.push(bytes[2]);
                 self.code.push(bytes[3]);
+                self.code.push(bytes[4]);
+                self.code.push(bytes[5]);
+                self.code.push(bytes[6]);
+                self.code.push(bytes[7]);
             }
-            _ => todo!("error"),
+            _ => unreachable!(),
         }
     }
 
     fn visit_float(&mut self, expr: &Expr) {
         match expr {
-            Expr::Float { val } => {
+            Expr::Float { val, .. } => {
                 self.code.push(OpCode::Float as u8);
                 let bytes = val.to_be_bytes();
                 self.code.push(bytes[0]);
@@ -100,33 +905,278 @@ impl ExprVisitor for Compiler {
                 self.code.push(bytes[6]);
                 self.code.push(bytes[7]);
             }
-            _ => todo!(),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_big_int(&mut self, expr: &Expr) {
+        match expr {
+            Expr::BigInt { val, .. } => {
+                self.code.push(OpCode::BigInt as u8);
+                self.emit_string(val);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_string(&mut self, expr: &Expr) {
+        match expr {
+            Expr::String { val, .. } => {
+                self.code.push(OpCode::String as u8);
+                self.emit_string(val);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_bool(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Bool { val: true, .. } => self.code.push(OpCode::True as u8),
+            Expr::Bool { val: false, .. } => self.code.push(OpCode::False as u8),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_nil(&mut self, _expr: &Expr) {
+        self.code.push(OpCode::Nil as u8);
+    }
+
+    // a dedicated opcode rather than a call to `to_string`, which a program could shadow
+    fn visit_interpolation(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Interpolation { parts, .. } => {
+                for (i, part) in parts.iter().enumerate() {
+                    self.compile_expr(part);
+                    if !matches!(part, Expr::String { .. }) {
+                        self.code.push(OpCode::Stringify as u8);
+                    }
+                    if i > 0 {
+                        self.code.push(OpCode::Add as u8);
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Grouping { expr, .. } => self.compile_expr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_name(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Name { val, id } => self.get_variable(val, self.binding(*id)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_assign(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, expr: value, id } => {
+                let name = Self::ident(name);
+                let expected = self.variable_type(name);
+                let binding = self.binding(*id);
+                self.push_receiver(binding);
+                match binding {
+                    Binding::Global => self.compile_expr(value),
+                    _ => self.compile_expected(value, expected),
+                }
+                self.set_variable(name, binding);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // the object and index of the target are evaluated once and kept on the stack for the set
+    fn visit_compound_assign(&mut self, expr: &Expr) {
+        match expr {
+            Expr::CompoundAssign { target, op, expr, .. } => match target.as_ref() {
+                Expr::Name { val, id } => {
+                    let binding = self.binding(*id);
+                    self.push_receiver(binding);
+                    self.get_variable(val, binding);
+                    self.compile_expr(expr);
+                    self.add_op(op);
+                    self.set_variable(val, binding);
+                }
+                Expr::Get { object, name, .. } => {
+                    self.compile_expr(object);
+                    self.code.push(OpCode::Dup as u8);
+                    self.code.push(OpCode::GetField as u8);
+                    self.emit_string(Self::ident(name));
+                    self.compile_expr(expr);
+                    self.add_op(op);
+                    self.code.push(OpCode::SetField as u8);
+                    self.emit_string(Self::ident(name));
+                }
+                Expr::Index { object, index, .. } => {
+                    self.compile_expr(object);
+                    self.compile_expr(index);
+                    self.code.push(OpCode::DupTwo as u8);
+                    self.code.push(OpCode::GetIndex as u8);
+                    self.compile_expr(expr);
+                    self.add_op(op);
+                    self.code.push(OpCode::SetIndex as u8);
+                }
+                _ => unreachable!(),
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_array(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.compile_expr(element);
+                }
+                self.code.push(OpCode::Array as u8);
+                self.emit_count(elements.len(), "elements in an array literal");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_map(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.compile_expr(key);
+                    self.compile_expr(value);
+                }
+                self.code.push(OpCode::Map as u8);
+                self.emit_count(entries.len(), "entries in a map literal");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_index(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Index { object, index, .. } => {
+                self.compile_expr(object);
+                self.compile_expr(index);
+                self.code.push(OpCode::GetIndex as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set_index(&mut self, expr: &Expr) {
+        match expr {
+            Expr::SetIndex { object, index, expr, .. } => {
+                self.compile_expr(object);
+                self.compile_expr(index);
+                self.compile_expr(expr);
+                self.code.push(OpCode::SetIndex as u8);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_range(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Range { start, end, inclusive, .. } => {
+                self.compile_expr(start);
+                self.compile_expr(end);
+                if *inclusive {
+                    self.code.push(OpCode::RangeInclusive as u8);
+                } else {
+                    self.code.push(OpCode::Range as u8);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_call(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Call { callee, args, .. } => {
+                let param_types = self.callee_param_types(callee).unwrap_or_default();
+                self.compile_expr(callee);
+                for (i, arg) in args.iter().enumerate() {
+                    self.compile_expected(arg, param_types.get(i).cloned().flatten());
+                }
+                self.code.push(OpCode::Call as u8);
+                self.emit_byte_count(args.len(), "arguments in a call");
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_get(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Get { object, name, .. } => {
+                self.compile_expr(object);
+                self.code.push(OpCode::GetField as u8);
+                self.emit_string(Self::ident(name));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Set { object, name, expr, id } => {
+                let expected = self.static_type(&Expr::Get { object: object.clone(), name: name.clone(), id: *id });
+                self.compile_expr(object);
+                self.compile_expected(expr, expected);
+                self.code.push(OpCode::SetField as u8);
+                self.emit_string(Self::ident(name));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_self(&mut self, _expr: &Expr) {
+        if self.kind != FunctionKind::Method {
+            self.errors.push(Error::new("'self' outside of a method"));
+            return;
+        }
+        self.code.push(OpCode::GetLocal as u8);
+        self.emit_u16(0);
+    }
+
+    fn visit_lambda(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lambda { params, body, id, .. } => self.function(*id, "lambda", params, body, FunctionKind::Function),
+            _ => unreachable!(),
         }
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::lexer::scan;
+    use crate::fixtures;
     use crate::parser::parse;
     use super::*;
 
     #[test]
     fn test_compile_expression_stmt() {
-        let s = "1 + 2";
-        let t = scan(s);
-        let p = parse(t);
-        let code = compile(p);
+        let s = fixtures::compiler::COMPILE_EXPRESSION_STMT;
+        let p = parse(s).unwrap();
+        let code = compile(p, &[]).unwrap();
         let exp = vec![
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             1,
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             2,
             3, // Add
         ];
@@ -136,17 +1186,24 @@ mod tests {
 
     #[test]
     fn test_compile_big_num() {
-        let s = "2147483647 + 2147483647";
-        let t = scan(s);
-        let p = parse(t);
-        let code = compile(p);
+        let s = fixtures::compiler::COMPILE_BIG_NUM;
+        let p = parse(s).unwrap();
+        let code = compile(p, &[]).unwrap();
         let exp = vec![
             0x01, // Int OpCode
+            0x00,
+            0x00,
+            0x00,
+            0x00,
             0x7F,
             0xFF,
             0xFF,
             0xFF,
             0x01, // Int OpCode
+            0x00,
+            0x00,
+            0x00,
+            0x00,
             0x7F,
             0xFF,
             0xFF,
@@ -157,15 +1214,126 @@ mod tests {
         assert_eq!(exp, code);
     }
 
+    #[test]
+    fn test_compile_for() {
+        let s = fixtures::compiler::COMPILE_FOR;
+        let code = compile(parse(s).unwrap(), &[]).unwrap();
+        let exp = vec![
+            1, 0, 0, 0, 0, 0, 0, 0, 0, // Int 0
+            1, 0, 0, 0, 0, 0, 0, 0, 2, // Int 2
+            22, // Range
+            1, 0, 0, 0, 0, 0, 0, 0, 0, // Int 0, the hidden index
+            24, 0, 0, 0, 4, // ForNext seq slot 0, exit +4
+            12, // Pop loop variable
+            20, 0, 9, // Loop -9
+            12, // Pop index
+            12, // Pop seq
+        ];
+
+        assert_eq!(exp, code);
+    }
+
+    #[test]
+    fn test_break_outside_loop() {
+        let s = fixtures::compiler::BREAK_OUTSIDE_LOOP;
+        let errors = compile(parse(s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("'break' outside of a loop"),
+            Error::new("'continue' outside of a loop"),
+        ]);
+    }
+
+    #[test]
+    fn test_self_outside_method() {
+        let s = fixtures::compiler::SELF_OUTSIDE_METHOD;
+        let errors = compile(parse(s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![Error::new("'self' outside of a method")]);
+    }
+
+    #[test]
+    fn test_uninferable_implicit_member() {
+        let s = fixtures::compiler::UNINFERABLE_IMPLICIT_MEMBER;
+        let errors = compile(parse(s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("cannot infer the enum of '.base'"),
+            Error::new("'.base' needs an enum but int is not one"),
+            Error::new("'.base' needs an enum but int is not one"),
+        ]);
+    }
+
+    #[test]
+    fn test_conformance() {
+        let s = fixtures::compiler::CONFORMANCE;
+        let errors = compile(parse(s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("Saiyan.train takes 0 params but Warrior requires 1"),
+            Error::new("Namekian does not implement 'train' required by Warrior"),
+            Error::new("Android conforms to unknown protocol 'Machine'"),
+        ]);
+    }
+
+    #[test]
+    fn test_oversized_literals() {
+        let elements = vec!["1"; 70000].join(", ");
+        let s = format!("print [{}]\nprint {{{}}}\n", elements, vec!["1: 2"; 70000].join(", "));
+        let errors = compile(parse(&s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("too many elements in an array literal (the most is 65535)"),
+            Error::new("too many entries in a map literal (the most is 65535)"),
+        ]);
+    }
+
+    #[test]
+    fn test_oversized_counts() {
+        let names = |prefix: &str| (0..300).map(|i| format!("{}{}", prefix, i)).collect::<Vec<_>>();
+        let mut s = format!("fun wide({})\nend\n", names("p").join(", "));
+        s += &format!("wide({})\n", vec!["1"; 300].join(", "));
+        s += &format!("fun outer()\n{}\nfun inner()\nreturn [{}]\nend\nend\n",
+            names("v").iter().map(|v| format!("let {} = 0", v)).collect::<Vec<_>>().join("\n"), names("v").join(", "));
+        s += &names("P").iter().map(|p| format!("protocol {}\nend\n", p)).collect::<String>();
+        s += &format!("struct Big: {}\n{}\n{}\nend\n",
+            names("P").join(", "),
+            names("f").iter().map(|f| format!("let {}: int", f)).collect::<Vec<_>>().join("\n"),
+            names("m").iter().map(|m| format!("fun {}()\nend", m)).collect::<Vec<_>>().join("\n"));
+        s += &format!("enum Wide\n{}\nend\n", names("c").join("\n"));
+        let errors = compile(parse(&s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("too many parameters in wide (the most is 255)"),
+            Error::new("too many arguments in a call (the most is 255)"),
+            Error::new("too many variables captured by inner (the most is 255)"),
+            Error::new("too many fields in Big (the most is 255)"),
+            Error::new("too many methods in Big (the most is 255)"),
+            Error::new("too many protocols for Big (the most is 255)"),
+            Error::new("too many cases in Wide (the most is 255)"),
+        ]);
+    }
+
+    #[test]
+    fn test_oversized_jumps() {
+        let body = "print 1\n".repeat(10000);
+        let s = format!("if true\n{}end\nwhile false\n{}end\n", body, body);
+        let errors = compile(parse(&s).unwrap(), &[]).unwrap_err();
+
+        assert_eq!(errors, vec![
+            Error::new("too much code to jump over"),
+            Error::new("loop body is too large"),
+            Error::new("too much code to jump over"),
+        ]);
+    }
+
     #[test]
     fn test_float() {
-        let s = "10.44492";
-        let t = scan(s);
-        println!("{t:?}");
-        let p = parse(t);
+        let s = fixtures::compiler::FLOAT;
+        let p = parse(s).unwrap();
         println!("{p:?}");
         assert_eq!(p.len(), 1);
-        let c = compile(p);
+        let c = compile(p, &[]).unwrap();
         println!("{c:?}");
         assert_eq!(c[0], 7); // Float opcode
         let byte1 = c[1];
@@ -0,0 +1,454 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::compiler;
+use crate::error::Error;
+use crate::linter::{self, Diagnostic, LintConfig};
+use crate::parser;
+use crate::stdlib;
+use crate::typechecker;
+use crate::value::{NativeFn, Obj, Value, ValueType};
+use crate::vm::VM;
+
+// compiles and runs frieza programs for a rust program embedding the language
+pub struct Engine {
+    vm: VM,
+    // the arguments `args()` returns
+    args: Rc<RefCell<Vec<String>>>,
+}
+
+// what programs an engine runs may do outside the vm. nothing by default, so an embedder opts in
+#[derive(Clone, Debug, Default)]
+pub struct Capabilities {
+    // files, stdin, environment variables, script arguments and exiting the process
+    pub io: bool,
+}
+
+// a compiled program, ready to run with `Engine::execute`
+pub struct Script {
+    code: Vec<u8>,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::with_capabilities(Capabilities::default())
+    }
+
+    pub fn with_capabilities(capabilities: Capabilities) -> Self {
+        let args = Rc::new(RefCell::new(vec![]));
+        let mut engine = Engine { vm: VM::new(), args: args.clone() };
+        stdlib::register(&mut engine, &capabilities, args);
+        engine
+    }
+
+    pub fn set_args(&mut self, args: Vec<String>) {
+        *self.args.borrow_mut() = args;
+    }
+
+    // makes a rust function callable from frieza as a global, checking the argument count and types on each call
+    pub fn register_fn<Args, F>(&mut self, name: &str, function: F)
+    where
+        F: NativeFunction<Args> + 'static,
+    {
+        let native = move |vm: &mut VM, args: &[Value]| function.call(vm, args);
+        self.vm.define_native(name, F::arity(), Rc::new(native));
+    }
+
+    // registers a native that works with values directly, for functions no rust signature can describe
+    pub(crate) fn register_native(&mut self, name: &str, arity: u8, function: Rc<NativeFn>) {
+        self.vm.define_native(name, arity, function);
+    }
+
+    pub fn register_value<T: IntoValue>(&mut self, name: &str, value: T) {
+        let value = value.into_value(&mut self.vm);
+        self.vm.define_global(name, value);
+    }
+
+    pub fn compile(&self, source: &str) -> Result<Script, Vec<Error>> {
+        let program = parser::parse(source)?;
+        typechecker::check(&program)?;
+        // natives and the globals of programs run before this one are already defined
+        let code = compiler::compile(program, &self.vm.global_names())?;
+        Ok(Script { code })
+    }
+
+    // checks a program against the lint rules without running it, which needs it to resolve against this engine's globals
+    pub fn lint(&self, source: &str, config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<Error>> {
+        linter::lint(source, &self.vm.global_names(), config)
+    }
+
+    // runs a script, converting the value of its trailing expression
+    pub fn execute<T: FromValue>(&mut self, script: &Script) -> Result<T, Error> {
+        let value = self.vm.interpret(&script.code)?;
+        T::from_value(&value, &self.vm)
+    }
+
+    pub fn eval<T: FromValue>(&mut self, source: &str) -> Result<T, Vec<Error>> {
+        let script = self.compile(source)?;
+        self.execute(&script).map_err(|error| vec![error])
+    }
+
+    pub fn run(&mut self, source: &str) -> Result<(), Vec<Error>> {
+        self.eval(source)
+    }
+
+    // calls a global frieza function defined by a program this engine has run
+    pub fn call_fn<T: FromValue>(&mut self, name: &str, args: impl IntoArgs) -> Result<T, Error> {
+        let callee = self.vm.global(name).ok_or_else(|| Error::new(&format!("Undefined function '{}'", name)))?;
+        let args = args.into_args(&mut self.vm);
+        let value = self.vm.call_value(callee, args)?;
+        T::from_value(&value, &self.vm)
+    }
+
+    // formats a value the way `print` does
+    pub fn stringify(&self, value: &Value) -> String {
+        self.vm.stringify(value)
+    }
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// a rust type a frieza value can be converted to
+pub trait FromValue: Sized {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error>;
+}
+
+// a rust type that can be converted to a frieza value
+pub trait IntoValue {
+    fn into_value(self, vm: &mut VM) -> Value;
+}
+
+// what a native function returns: a value, or a result whose error becomes a runtime error
+pub trait NativeResult {
+    fn into_result(self, vm: &mut VM) -> Result<Value, Error>;
+}
+
+// a rust function or closure whose params and result convert to and from frieza values
+pub trait NativeFunction<Args> {
+    fn arity() -> u8;
+    fn call(&self, vm: &mut VM, args: &[Value]) -> Result<Value, Error>;
+}
+
+// the arguments `Engine::call_fn` passes, as a tuple
+pub trait IntoArgs {
+    fn into_args(self, vm: &mut VM) -> Vec<Value>;
+}
+
+fn mismatch(expected: &str, value: &Value, vm: &VM) -> Error {
+    Error::new(&format!("expected {} but got {}", expected, vm.type_name(value)))
+}
+
+// a string, array or other object is only kept alive by the vm, so it may be collected once the engine runs another program
+impl FromValue for Value {
+    fn from_value(value: &Value, _vm: &VM) -> Result<Self, Error> {
+        Ok(value.clone())
+    }
+}
+
+impl FromValue for () {
+    fn from_value(_value: &Value, _vm: &VM) -> Result<Self, Error> {
+        Ok(())
+    }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match value.value_type {
+            ValueType::Int => Ok(unsafe { value.val.i }),
+            _ => match vm.obj(value) {
+                Some(Obj::BigInt(n)) => Err(Error::new(&format!("{} does not fit in a 64-bit int", n))),
+                _ => Err(mismatch("int", value, vm)),
+            },
+        }
+    }
+}
+
+// ints are 64 bits wide in scripts, so narrowing can fail
+impl FromValue for i32 {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        let i = i64::from_value(value, vm)?;
+        i32::try_from(i).map_err(|_| Error::new(&format!("{} does not fit in a 32-bit int", i)))
+    }
+}
+
+impl FromValue for f64 {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        // ints and big ints convert to floats
+        vm.float(value).ok_or_else(|| mismatch("float", value, vm))
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match value.value_type {
+            ValueType::Bool => Ok(unsafe { value.val.b }),
+            _ => Err(mismatch("bool", value, vm)),
+        }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match vm.obj(value) {
+            Some(Obj::String(string)) => Ok(string.clone()),
+            _ => Err(mismatch("string", value, vm)),
+        }
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match vm.obj(value) {
+            Some(Obj::Array(elements)) => elements.iter().map(|e| T::from_value(e, vm)).collect(),
+            _ => Err(mismatch("array", value, vm)),
+        }
+    }
+}
+
+// nil converts to `None`
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match value.value_type {
+            ValueType::Nil => Ok(None),
+            _ => T::from_value(value, vm).map(Some),
+        }
+    }
+}
+
+impl IntoValue for Value {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        self
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        Value::nil()
+    }
+}
+
+impl IntoValue for i64 {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        Value::int(self)
+    }
+}
+
+impl IntoValue for i32 {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        Value::int(self as i64)
+    }
+}
+
+impl IntoValue for BigInt {
+    fn into_value(self, vm: &mut VM) -> Value {
+        vm.integer(self)
+    }
+}
+
+impl IntoValue for f64 {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        Value::float(self)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self, _vm: &mut VM) -> Value {
+        Value::bool(self)
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self, vm: &mut VM) -> Value {
+        vm.alloc(Obj::String(self))
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self, vm: &mut VM) -> Value {
+        vm.alloc(Obj::String(self.to_string()))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self, vm: &mut VM) -> Value {
+        let elements = self.into_iter().map(|e| e.into_value(vm)).collect();
+        vm.alloc(Obj::Array(elements))
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self, vm: &mut VM) -> Value {
+        match self {
+            Some(value) => value.into_value(vm),
+            None => Value::nil(),
+        }
+    }
+}
+
+impl<T: IntoValue> NativeResult for T {
+    fn into_result(self, vm: &mut VM) -> Result<Value, Error> {
+        Ok(self.into_value(vm))
+    }
+}
+
+impl<T: IntoValue> NativeResult for Result<T, Error> {
+    fn into_result(self, vm: &mut VM) -> Result<Value, Error> {
+        self.map(|value| value.into_value(vm))
+    }
+}
+
+macro_rules! impl_native_function {
+    ($($arg:ident),*) => {
+        impl<F, R, $($arg: FromValue),*> NativeFunction<($($arg,)*)> for F
+        where
+            F: Fn($($arg),*) -> R,
+            R: NativeResult,
+        {
+            fn arity() -> u8 {
+                0 $(+ { let _ = stringify!($arg); 1 })*
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables)]
+            fn call(&self, vm: &mut VM, args: &[Value]) -> Result<Value, Error> {
+                let mut args = args.iter();
+                $(let $arg = $arg::from_value(args.next().expect("arity is checked by the vm"), vm)?;)*
+                self($($arg),*).into_result(vm)
+            }
+        }
+
+        impl<$($arg: IntoValue),*> IntoArgs for ($($arg,)*) {
+            #[allow(non_snake_case, unused_variables)]
+            fn into_args(self, vm: &mut VM) -> Vec<Value> {
+                let ($($arg,)*) = self;
+                vec![$($arg.into_value(vm)),*]
+            }
+        }
+    };
+}
+
+impl_native_function!();
+impl_native_function!(A);
+impl_native_function!(A, B);
+impl_native_function!(A, B, C);
+impl_native_function!(A, B, C, D);
+impl_native_function!(A, B, C, D, E);
+impl_native_function!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+    use super::*;
+
+    fn messages(errors: Vec<Error>) -> Vec<String> {
+        errors.iter().map(|e| e.message().to_string()).collect()
+    }
+
+    #[test]
+    fn test_register_fn() {
+        let mut engine = Engine::new();
+        engine.register_fn("add", |a: i32, b: i32| a + b);
+        engine.register_fn("greet", |name: String| format!("hello {}", name));
+        engine.register_fn("names", || vec!["goku", "vegeta"]);
+        engine.register_fn("half", |x: f64| x / 2.0);
+
+        assert_eq!(engine.eval::<i32>("add(2, 3) + 1\n").unwrap(), 6);
+        assert_eq!(engine.eval::<String>("greet(\"frieza\")\n").unwrap(), "hello frieza");
+        assert_eq!(engine.eval::<Vec<String>>("names()\n").unwrap(), vec!["goku", "vegeta"]);
+        assert_eq!(engine.eval::<f64>("half(3)\n").unwrap(), 1.5);
+    }
+
+    #[test]
+    fn test_native_errors() {
+        let mut engine = Engine::new();
+        engine.register_fn("add", |a: i32, b: i32| a + b);
+        engine.register_fn("check", |n: i32| if n > 0 { Ok(n) } else { Err(Error::new("n must be positive")) });
+
+        assert_eq!(messages(engine.eval::<i32>("add(1)\n").unwrap_err()), vec!["add expects 2 arguments but got 1"]);
+        assert_eq!(messages(engine.eval::<i32>("add(1, \"2\")\n").unwrap_err()), vec!["expected int but got string"]);
+        assert_eq!(engine.eval::<i32>("check(2)\n").unwrap(), 2);
+        assert_eq!(messages(engine.eval::<i32>("check(0)\n").unwrap_err()), vec!["n must be positive"]);
+    }
+
+    #[test]
+    fn test_conversions() {
+        let mut engine = Engine::new();
+        assert_eq!(engine.eval::<Option<i32>>("nil\n").unwrap(), None);
+        assert_eq!(engine.eval::<Option<i32>>("1\n").unwrap(), Some(1));
+        assert!(engine.eval::<bool>("1 < 2\n").unwrap());
+        assert_eq!(messages(engine.eval::<bool>("1\n").unwrap_err()), vec!["expected bool but got int"]);
+    }
+
+    #[test]
+    fn test_call_fn() {
+        let mut engine = Engine::new();
+        engine.run("fun add(a, b)\n  return a + b\nend\n").unwrap();
+
+        assert_eq!(engine.call_fn::<i32>("add", (1, 2)).unwrap(), 3);
+        assert_eq!(engine.call_fn::<String>("add", ("a", "b")).unwrap(), "ab");
+        // globals from earlier programs stay defined
+        assert_eq!(engine.eval::<i32>("add(3, 4)\n").unwrap(), 7);
+        assert_eq!(engine.call_fn::<i32>("sub", ()).unwrap_err().message(), "Undefined function 'sub'");
+    }
+
+    #[test]
+    fn test_repeated_evaluation_frees_garbage() {
+        let mut engine = Engine::new();
+        engine.run("fun greet(name)\n  return \"hi ${name}\"\nend\n").unwrap();
+        let program = fixtures::engine::REPEATED_EVALUATION;
+
+        // each run leaves its own garbage until the next one starts
+        engine.run(program).unwrap();
+        engine.run(program).unwrap();
+        let live = engine.vm.live_objects();
+        for _ in 0..100 {
+            assert_eq!(engine.eval::<String>(program).unwrap(), "hi goku");
+        }
+        assert_eq!(engine.vm.live_objects(), live);
+    }
+
+    #[test]
+    fn test_runtime_errors() {
+        let mut engine = Engine::new();
+        assert!(engine.eval::<i32>("1 + true\n").is_err());
+        // the engine is still usable after an error
+        assert_eq!(engine.eval::<i32>("1 + 2\n").unwrap(), 3);
+    }
+
+    #[test]
+    fn test_readme() {
+        let readme = include_str!("../README.md");
+        let start = readme.find("```\n").unwrap() + 4;
+        let program = &readme[start..start + readme[start..].find("```").unwrap()];
+
+        let mut engine = Engine::new();
+        engine.run(&format!("{}\nlet goku = Saiyan(\"goku\", 9000, .base)\n", program)).unwrap();
+        assert_eq!(engine.eval::<String>("free\n").unwrap(), "freerondonumba9");
+        assert_eq!(engine.eval::<i32>("goku.fight()\n").unwrap(), 500000);
+        assert!(engine.eval::<bool>("goku.currentForm == Form.superSaiyan\n").unwrap());
+    }
+
+    #[test]
+    fn test_syntax_errors() {
+        let engine = Engine::new();
+        let compile = |source| messages(engine.compile(source).err().unwrap());
+        assert_eq!(compile("let x = \n"), vec!["[line 1] expected expression but found NewLine"]);
+        assert_eq!(compile("let = 3\n"), vec!["[line 1] expected variable name after 'let' but found Eq"]);
+        assert_eq!(compile("print \"\\q\"\n"), vec!["[line 1, column 8] unknown escape sequence '\\q'"]);
+        assert_eq!(
+            compile("print 0b12\n"),
+            vec!["[line 1, column 7] invalid digit '2' in binary 0b1"],
+        );
+
+        // each statement that doesn't parse is reported, not just the first
+        assert_eq!(compile("let = 1\nprint 2\nlet x = )\n"), vec![
+            "[line 1] expected variable name after 'let' but found Eq",
+            "[line 3] expected expression but found RParen",
+        ]);
+        let lint = engine.lint("let x = \n", &LintConfig::default()).unwrap_err();
+        assert_eq!(messages(lint), vec!["[line 1] expected expression but found NewLine"]);
+    }
+}
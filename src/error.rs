@@ -0,0 +1,35 @@
+use std::fmt;
+use std::ops::Range;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub(crate) message: String,
+    // the bytes of the source an error reading a program is about
+    pub(crate) span: Option<Range<usize>>,
+}
+
+impl Error {
+    pub fn new(message: &str) -> Self {
+        Error { message: message.to_string(), span: None }
+    }
+
+    pub(crate) fn at(span: Range<usize>, message: &str) -> Self {
+        Error { message: message.to_string(), span: Some(span) }
+    }
+
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+
+    pub fn span(&self) -> Option<Range<usize>> {
+        self.span.clone()
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "error: {}", self.message)
+    }
+}
+
+impl std::error::Error for Error {}
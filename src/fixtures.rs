@@ -0,0 +1,687 @@
+// the programs the parser, compiler and engine tests run, kept in one place so the formatter's tests can
+// check every one of them reformats to the same program
+
+macro_rules! fixtures {
+    ($($module:ident { $($name:ident = $source:expr;)* })*) => {
+        $(pub(crate) mod $module {
+            $(pub(crate) const $name: &str = $source;)*
+        })*
+
+        // every program above
+        pub(crate) const ALL: &[&str] = &[$($($module::$name,)*)*];
+    };
+}
+
+fixtures! {
+    parser {
+        FUN = r#"
+        fun add(x, y)
+            return x + y
+        end
+        "#;
+        LET = r#"
+        let juice = "juice"
+        let wrld = "wrld"
+        let helloWrld = juice + " " + wrld
+        "#;
+        EXPR_STMT = r#"
+        1 + 1
+        x + y
+        x + 1
+        juice + " " + wrld
+        "#;
+        BINARY = r#"
+        1 + 2
+        1 - 2
+        1 * 2
+        1 / 2
+        1 + 2 + 3
+        1 + 2 * 3
+        1 < 2
+        1 > 2
+        1 <= 2
+        1 >= 2
+        "#;
+        PRINT = r#"
+        print "free" + "lil" + "steve"
+        "#;
+        LITERAL = r#"
+        1
+        2
+        10.5
+        35.5353
+        "#;
+        OPERATOR_PRECEDENCE = r#"
+        -2 ** 3 ** 2
+        1 | 2 ^ 3 & 4 << 5 + 6 % 7
+        ~1 div 2 >> 3
+        "#;
+        DOC_COMMENTS = r#"
+        /// a saiyan warrior
+        /// from planet vegeta
+        struct Saiyan
+            let name: string
+            /* not a doc */
+            /// powers up by 1000
+            fun powerUp
+            end
+        end
+        /// forms a saiyan can take
+        enum Form
+            /// the starting form
+            base
+        end
+        /// something that fights
+        protocol Warrior
+            /// fights for a while
+            fun fight()
+            /// dangling
+        end
+        /// lost, since there's nothing to document
+        let x = 1
+        /// also lost past the blank line
+
+        fun undocumented
+            if true
+                /// dangling
+            else
+            end
+            /// dangling
+        end
+        "#;
+        IF = r#"
+        if 1 < 2 
+            print "yah"
+        else 
+            print "nah"
+        end
+        "#;
+        AND = r#"
+        true and false
+        "#;
+        OR = r#"
+        true or false
+        "#;
+        FOR = r#"
+        for a in arr
+            print a
+        end
+        for i in 0..10
+            total = total + i
+        end
+        for i in 0..=n + 1
+        end
+        "#;
+        WHILE = r#"
+        while i < 10
+            if i == 5
+                break
+            end
+            continue
+        end
+        "#;
+        STRUCT = r#"
+        struct Saiyan
+            let name: string
+            let powerLevel: int
+
+            fun powerUp()
+                powerLevel += 1000
+            end
+
+            fun goSuperSaiyan
+                self.powerLevel *= 50
+            end
+        end
+        let goku = Saiyan("goku", 9000)
+        goku.powerUp()
+        "#;
+        ENUM = r#"
+        enum Form
+            base, superSaiyan
+            superSaiyanBlue
+        end
+        fun transform(form: Form)
+            currentForm = .superSaiyan
+        end
+        "#;
+        PROTOCOL = r#"
+        protocol Warrior
+            fun fight()
+            fun train(hours: int)
+        end
+        struct Saiyan: Warrior, Alien
+        end
+        goku is Warrior
+        "#;
+        ARRAY = r#"
+        let arr = ["free", 9, []]
+        "#;
+        LAMBDA = r#"
+        fun(x) x * 2 end(21)
+        "#;
+        MAPS = r#"
+        let m = {
+            "a": 1,
+            2: b
+        }
+        m["a"] = m[2]
+        for k, v in m
+        end
+        "#;
+        INTERPOLATION = r#"
+        "power: ${level * 2}!"
+        "${x}"
+        "#;
+        EQUALITY = r#"
+        true == true
+        5 == 1
+        durk != keef
+        "#;
+        RETURN_BEFORE_END = "let g = fun(x) return x end\n";
+    }
+
+    compiler {
+        COMPILE_EXPRESSION_STMT = "1 + 2";
+        COMPILE_BIG_NUM = "2147483647 + 2147483647";
+        COMPILE_FOR = r#"
+        for i in 0..2
+        end
+        "#;
+        BREAK_OUTSIDE_LOOP = r#"
+        break
+        while true
+            break
+        end
+        continue
+        "#;
+        SELF_OUTSIDE_METHOD = r#"
+        fun notAMethod()
+            return self
+        end
+        "#;
+        UNINFERABLE_IMPLICIT_MEMBER = r#"
+        enum Form
+            base
+        end
+        fun transform(form)
+            return form == .base
+        end
+        let power: int = .base
+        fun train(level: int)
+            return level == .base
+        end
+        "#;
+        CONFORMANCE = r#"
+        protocol Warrior
+            fun fight()
+            fun train(hours)
+        end
+        struct Saiyan: Warrior
+            fun fight()
+            end
+            fun train()
+            end
+        end
+        struct Namekian: Warrior
+            fun fight()
+            end
+        end
+        struct Android: Machine
+        end
+        "#;
+        FLOAT = "10.44492";
+    }
+
+    engine {
+        INTERPRET_SOURCE = r#"
+        10 + 10
+        "#;
+        FOR_RANGE = r#"
+        let sum = 0
+        for i in 0..5
+            sum = sum + i
+        end
+        let inclusive = 0
+        for i in 1..=5
+            inclusive = inclusive + i
+        end
+        [sum, inclusive]
+        "#;
+        FOR_ARRAY = r#"
+        let arr = ["free", "rondo", "numba", "9"]
+
+        let free = ""
+        for a in arr
+            free = free + a
+        end
+        free
+        "#;
+        FOR_STRING = r#"
+        let reversed = ""
+        for c in "hello"
+            reversed = c + reversed
+        end
+        reversed
+        "#;
+        FOR_NESTED_LOCALS = r#"
+        let out = ""
+        for i in 0..2
+            let label = "x"
+            for j in [1, 2]
+                out = out + label
+            end
+        end
+        for i in 5..5
+            out = out + "never"
+        end
+        out
+        "#;
+        WHILE = r#"
+        let i = 0
+        let total = 0
+        while i < 10
+            i = i + 1
+            if i == 3
+                continue
+            end
+            if i > 6
+                break
+            end
+            total = total + i
+        end
+        [i, total]
+        "#;
+        BREAK_POPS_LOCALS = r#"
+        let found = nil
+        for word in ["goku", "vegeta", "frieza", "cell"]
+            let upper = word + "!"
+            if word == "frieza"
+                found = upper
+                break
+            end
+        end
+        let count = 0
+        for i in 0..100
+            let skipped = i
+            while true
+                let inner = skipped
+                break
+            end
+            if i >= 3 and i <= 5
+                continue
+            end
+            count = count + 1
+        end
+        [found, count]
+        "#;
+        LOGIC = r#"
+        [1 < 2, 2 <= 1, 2.5 >= 2, "a" < "b", 1 == 1.0, "a" != "a", nil or "b", false and 1, !nil]
+        "#;
+        NAN_COMPARISONS = r#"
+        let nan = 0.0 / 0.0
+        [nan >= 1, nan <= 1, nan > 1, nan < 1, 1 >= nan, 1 <= nan, nan == nan]
+        "#;
+        FUNCTIONS = r#"
+        fun add(x, y)
+            return x + y
+        end
+
+        fun fib(n)
+            if n < 2
+                return n
+            end
+            return fib(n - 1) + fib(n - 2)
+        end
+
+        fun noop
+        end
+
+        [add(2, 3), fib(15), noop()]
+        "#;
+        INTERPOLATION = r#"
+        fun to_string(value)
+            return "shadowed"
+        end
+        let level = 9000
+        ["${level}", "over ${level + 1}!", "${[1.5, nil]} and ${"inner ${level}"}"]
+        "#;
+        GARBAGE_COLLECTION = r#"
+        struct Node
+            let value: int
+            let next: Node
+        end
+
+        fun counter()
+            let count = 0
+            return fun()
+                count += 1
+                return count
+            end
+        end
+
+        let kept = {}
+        let next = counter()
+        let list = nil
+        for i in 0..5000
+            let garbage = {"i": [i, "${i}", Node(i, nil)]}
+            list = Node(i, list)
+            if i % 1000 == 0
+                kept[i / 1000] = counter()
+            end
+            next()
+        end
+        let total = 0
+        let node = list
+        while node != nil
+            total += node.value
+            node = node.next
+        end
+        kept[4]()
+        [total, next(), kept[2](), kept[4](), kept]
+        "#;
+        DEEP_RECURSION = r#"
+        fun fact(n)
+            if n < 2
+                return 1
+            end
+            return n * fact(n - 1)
+        end
+
+        fun sum(n)
+            if n == 0
+                return 0
+            end
+            return n + sum(n - 1)
+        end
+
+        [fact(100) == fact(99) * 100, sum(70), sum(500)]
+        "#;
+        CLOSURE_COUNTER = r#"
+        fun makeCounter()
+            let count = 0
+            fun increment()
+                count += 1
+                return count
+            end
+            return increment
+        end
+
+        let a = makeCounter()
+        let b = makeCounter()
+        a()
+        a()
+        b()
+        [a(), b()]
+        "#;
+        CLOSURES_SHARE_UPVALUES = r#"
+        let getter = nil
+        let setter = nil
+        fun pair()
+            let value = "before"
+            fun get()
+                return value
+            end
+            fun set(v)
+                value = v
+            end
+            getter = get
+            setter = set
+        end
+
+        pair()
+        setter("after")
+        getter()
+        "#;
+        CLOSURES_IN_LOOPS = r#"
+        let first = nil
+        let second = nil
+        for i in 0..2
+            fun get()
+                return i * 10
+            end
+            if i == 0
+                first = get
+            else
+                second = get
+            end
+        end
+        [first(), second()]
+        "#;
+        LAMBDAS = r#"
+        fun apply(f, x)
+            return f(x)
+        end
+
+        fun adder(n)
+            return fun(x) x + n end
+        end
+
+        let addTen = adder(10)
+        let noisy = fun()
+            print "ignored"
+        end
+        [apply(fun(x) x * 2 end, 21), addTen(5), fun(a, b) a - b end(5, 3), noisy()]
+        "#;
+        CAPTURED_PROTOCOLS = r#"
+        fun outer()
+            protocol Warrior
+                fun fight()
+            end
+            fun inner()
+                struct Saiyan: Warrior
+                    fun fight()
+                        return 1
+                    end
+                end
+                return Saiyan() is Warrior
+            end
+            return inner()
+        end
+        outer()
+        "#;
+        NESTED_CLOSURES = r#"
+        fun outer()
+            let x = "outer"
+            fun middle()
+                fun inner()
+                    return x
+                end
+                return inner
+            end
+            return middle()
+        end
+
+        fun fact(n)
+            fun go(k)
+                if k < 2
+                    return 1
+                end
+                return k * go(k - 1)
+            end
+            return go(n)
+        end
+
+        [outer()(), fact(5)]
+        "#;
+        STRUCT = r#"
+        struct Saiyan
+            let name: string
+            let powerLevel: int
+
+            fun fight()
+                powerUp()
+                goSuperSaiyan()
+                return powerLevel
+            end
+
+            fun powerUp()
+                powerLevel += 1000
+            end
+
+            fun goSuperSaiyan
+                let multiplier = 50
+                self.powerLevel = self.powerLevel * multiplier
+            end
+        end
+
+        let goku = Saiyan("goku", 9000)
+        let power = goku.fight()
+        goku.name = "kakarot"
+        [power, goku.powerLevel, goku]
+        "#;
+        COMPOUND_ASSIGNMENT = r#"
+        struct Box
+            let x: int
+            let items: array
+        end
+        let calls = 0
+        let box = Box(1, [10, 20])
+        fun get()
+            calls += 1
+            return box
+        end
+        fun index()
+            calls += 1
+            return 1
+        end
+        get().x += 5
+        get().items[index()] -= 2
+        let total = 1
+        total *= 6
+        fun halve()
+            total /= 2
+        end
+        halve()
+        box.items[0] += 1
+        [calls, box.x, box.items, total]
+        "#;
+        BOUND_METHOD = r#"
+        struct Counter
+            let count: int
+
+            fun increment()
+                count += 1
+                return self
+            end
+        end
+
+        let counter = Counter(0)
+        let increment = counter.increment
+        increment()
+        increment().increment()
+        counter.count
+        "#;
+        ENUM = r#"
+        enum Form
+            base, superSaiyan
+        end
+
+        struct Saiyan
+            let name: string
+            let powerLevel: int
+            let currentForm: Form
+
+            fun transform(form: Form)
+                currentForm = form
+            end
+
+            fun goSuperSaiyan
+                if currentForm != .superSaiyan
+                    transform(.superSaiyan)
+                    powerLevel *= 50
+                end
+            end
+        end
+
+        fun isBase(form: Form)
+            return form == .base
+        end
+
+        let goku = Saiyan("goku", 9000, .base)
+        goku.goSuperSaiyan()
+        goku.goSuperSaiyan()
+        let vegeta = Saiyan("vegeta", 8000, Form.superSaiyan)
+        vegeta.currentForm = .base
+        let start: Form = .base
+        [goku.currentForm, goku.powerLevel, isBase(vegeta.currentForm), Form.base == Form.superSaiyan, start]
+        "#;
+        PROTOCOL = r#"
+        protocol Warrior
+            fun fight()
+        end
+
+        struct Saiyan: Warrior
+            let powerLevel: int
+
+            fun fight()
+                return powerLevel * 2
+            end
+        end
+
+        struct Namekian: Warrior
+            let powerLevel: int
+
+            fun fight()
+                return powerLevel + 1
+            end
+        end
+
+        struct Human
+        end
+
+        fun brawl(warrior: Warrior)
+            return warrior.fight()
+        end
+
+        let total = 0
+        for w in [Saiyan(10), Namekian(10)]
+            total += brawl(w)
+        end
+        let goku = Saiyan(1)
+        [total, goku is Warrior, goku is Saiyan, goku is Namekian, Human() is Warrior, 5 is Warrior]
+        "#;
+        MAPS = r#"
+        enum Form
+            base, superSaiyan
+        end
+
+        let levels = {
+            "goku": 9000,
+            Form.superSaiyan: 50,
+        }
+        levels["vegeta"] = 8500
+        levels["goku"] += 1
+        levels[Form.base] = 1
+        let total = 0
+        let names = ""
+        for k, v in levels
+            total += v
+        end
+        for k in {"a": 1, "b": 2}
+            names += k
+        end
+        [levels["goku"], levels[Form.superSaiyan], total, names, levels]
+        "#;
+        ARRAY_INDEXING = r#"
+        let a = [1, 2, 3]
+        a[0] = 10
+        a[2] *= 2
+        let sum = 0
+        for i, x in a
+            sum += i * x
+        end
+        [a, a[1], sum]
+        "#;
+        BIG_INTS = r#"
+        fun factorial(n)
+            if n < 2
+                return 1
+            end
+            return n * factorial(n - 1)
+        end
+        factorial(30)
+        "#;
+        REPEATED_EVALUATION = "let names = [\"goku\", \"vegeta\"]\nlet counts = {\"goku\": 1}\nfun() greet(names[0]) end()\n";
+    }
+}
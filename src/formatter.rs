@@ -0,0 +1,354 @@
+use crate::error::Error;
+use crate::parser;
+use crate::syntax::{self, Element, Node, NodeKind, SyntaxToken, TriviaKind};
+use crate::token::Token;
+
+const INDENT: &str = "    ";
+
+// reprints a program with four spaces of indentation per block, one space around binary operators,
+// no more than one blank line in a row and its comments where they were. only the layout of lines
+// changes, so the program parses to the same ast. a program that doesn't parse is left alone
+pub fn format_source(source: &str) -> Result<String, Vec<Error>> {
+    parser::parse(source)?;
+    let root = syntax::parse(source);
+    let mut printer = Printer { lines: vec![], line: None, levels: vec![], previous: None };
+    printer.node(&root);
+    Ok(printer.render())
+}
+
+// how a token moves the indentation
+#[derive(Clone, Copy, PartialEq)]
+enum Role {
+    Open,
+    Close,
+    Plain,
+}
+
+struct Line {
+    indent: usize,
+    text: String,
+    // starts with `end`, `else` or a closing bracket
+    closes: bool,
+}
+
+struct Printer {
+    // a blank line is `None`
+    lines: Vec<Option<Line>>,
+    line: Option<Line>,
+    // the indentation inside each open block or bracket
+    levels: Vec<usize>,
+    // the last token on the current line, and whether it was a unary operator
+    previous: Option<(Token, bool)>,
+}
+
+impl Printer {
+    fn node(&mut self, node: &Node) {
+        for child in &node.children {
+            match child {
+                Element::Node(node) if node.kind == NodeKind::Interpolation => self.template(node),
+                Element::Node(node) => self.node(node),
+                Element::Token(token) => self.token(token, role(node.kind, &token.kind)),
+            }
+        }
+    }
+
+    // the parts of an interpolated string are kept as they are, expressions and all
+    fn template(&mut self, node: &Node) {
+        let tokens = node.tokens();
+        self.comments(tokens[0]);
+        let text = node.text();
+        let leading: usize = tokens[0].leading.iter().map(|t| t.text.len()).sum();
+        self.word(&Token::String(String::new()), &text[leading..], Role::Plain);
+    }
+
+    fn token(&mut self, token: &SyntaxToken, role: Role) {
+        self.comments(token);
+        match token.kind {
+            Token::NewLine => self.end_line(),
+            Token::Eof => {
+                if self.line.is_some() {
+                    self.end_line();
+                }
+            }
+            _ => self.word(&token.kind, &token.text, role),
+        }
+    }
+
+    // the comments before a token, and any characters the lexer skipped, go where they were
+    fn comments(&mut self, token: &SyntaxToken) {
+        for trivia in &token.leading {
+            if trivia.kind != TriviaKind::Whitespace {
+                self.start_line(false);
+                self.append(trivia.text.trim_end(), true);
+                self.previous = None;
+            }
+        }
+    }
+
+    fn word(&mut self, kind: &Token, text: &str, role: Role) {
+        let closes = role == Role::Close || *kind == Token::Else;
+        let started = self.line.is_none();
+        if role == Role::Close {
+            let level = self.levels.pop().unwrap_or(1);
+            if started {
+                self.start_line(true);
+                self.line.as_mut().unwrap().indent = level - 1;
+            }
+        }
+        self.start_line(closes);
+        if *kind == Token::Else && started {
+            self.line.as_mut().unwrap().indent = self.levels.last().map_or(0, |level| level - 1);
+        }
+
+        let unary = match kind {
+            Token::Bang | Token::Tilde => true,
+            Token::Minus => !self.previous.as_ref().is_some_and(|(previous, _)| ends_operand(previous)),
+            _ => false,
+        };
+        let space = match &self.previous {
+            Some((previous, previous_unary)) => !previous_unary && spaced(previous, kind),
+            None => true,
+        };
+        self.append(text, space);
+        self.previous = Some((kind.clone(), unary));
+
+        if role == Role::Open {
+            let indent = self.line.as_ref().unwrap().indent;
+            self.levels.push(indent + 1);
+        }
+    }
+
+    fn start_line(&mut self, closes: bool) {
+        if self.line.is_none() {
+            let indent = self.levels.last().copied().unwrap_or(0);
+            self.line = Some(Line { indent, text: String::new(), closes });
+        }
+    }
+
+    fn append(&mut self, text: &str, space: bool) {
+        let line = self.line.as_mut().unwrap();
+        if space && !line.text.is_empty() {
+            line.text.push(' ');
+        }
+        line.text.push_str(text);
+    }
+
+    fn end_line(&mut self) {
+        self.lines.push(self.line.take());
+        self.previous = None;
+    }
+
+    // drops blank lines at the start and end, at the start and end of blocks, and after another blank line
+    fn render(self) -> String {
+        let mut out = String::new();
+        let mut previous: Option<&Line> = None;
+        let mut blank = false;
+        for line in &self.lines {
+            let Some(line) = line else {
+                blank = true;
+                continue;
+            };
+            if let Some(previous) = previous {
+                if blank && !line.closes && line.indent <= previous.indent {
+                    out.push('\n');
+                }
+            }
+            for _ in 0..line.indent {
+                out.push_str(INDENT);
+            }
+            out.push_str(&line.text);
+            out.push('\n');
+            previous = Some(line);
+            blank = false;
+        }
+        out
+    }
+}
+
+// a block indents from its keyword to its `end`, and brackets from one to the other. the `fun`s of a
+// protocol's methods are in the protocol's node, since they have no bodies
+fn role(node: NodeKind, token: &Token) -> Role {
+    match (node, token) {
+        (_, Token::LParen | Token::LBracket | Token::LBrace) => Role::Open,
+        (_, Token::RParen | Token::RBracket | Token::RBrace) => Role::Close,
+        (NodeKind::Fun | NodeKind::Lambda, Token::Fun) | (NodeKind::If, Token::If) | (NodeKind::For, Token::For)
+            | (NodeKind::While, Token::While) | (NodeKind::Struct, Token::Struct)
+            | (NodeKind::Protocol, Token::Protocol) | (NodeKind::Enum, Token::Enum) => Role::Open,
+        (_, Token::End) => Role::Close,
+        _ => Role::Plain,
+    }
+}
+
+// whether a token can end an operand, which makes a `-` after it binary, a `(` a call and a `[` an index
+fn ends_operand(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Ident(_) | Token::Int(_) | Token::BigInt(_) | Token::Float(_) | Token::String(_) | Token::True | Token::False
+            | Token::Nil | Token::SelfKw | Token::RParen | Token::RBracket | Token::RBrace | Token::End
+    )
+}
+
+// whether a space goes between two tokens on the same line
+fn spaced(previous: &Token, next: &Token) -> bool {
+    match (previous, next) {
+        (Token::LParen | Token::LBracket | Token::LBrace | Token::Dot, _) => false,
+        (_, Token::RParen | Token::RBracket | Token::RBrace | Token::Comma | Token::Colon | Token::Semicolon) => false,
+        (Token::DotDot | Token::DotDotEq, _) | (_, Token::DotDot | Token::DotDotEq) => false,
+        // `fun(x)` starts a lambda
+        (Token::Fun, Token::LParen) => false,
+        (previous, Token::Dot | Token::LParen | Token::LBracket) => !ends_operand(previous),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::fixtures;
+    use crate::parser::parse;
+    use super::*;
+
+    #[test]
+    fn test_indentation() {
+        let source = r#"
+struct Saiyan: Warrior
+let name: string
+  fun fight()
+if powerLevel>9000
+print "over"
+      else
+  print "under"
+end
+  end
+end
+"#;
+        let expected = r#"struct Saiyan: Warrior
+    let name: string
+    fun fight()
+        if powerLevel > 9000
+            print "over"
+        else
+            print "under"
+        end
+    end
+end
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_protocol_methods_have_no_bodies() {
+        let source = "protocol Warrior\nfun fight()\n  fun rest(hours: int)\nend\nprint 1\n";
+        let expected = "protocol Warrior\n    fun fight()\n    fun rest(hours: int)\nend\nprint 1\n";
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_spacing() {
+        let source = r#"let x=-1+ 2*(3 -4)**-2
+let y = ! true and x>=0 or ~ x<<1==0
+let a=[ 1,2 , 3 ] [0]
+let m = { "a" :1 }
+print add (x , y)
+print foo . bar(.base) [ 0 ]
+for i , v in 0 .. 10
+x += y div 2 % 3
+end
+fun add(x:int,y : int)->int
+return x-y
+end
+let f = fun (x) x*2 end
+print "a ${x+1}  b"
+"#;
+        let expected = r#"let x = -1 + 2 * (3 - 4) ** -2
+let y = !true and x >= 0 or ~x << 1 == 0
+let a = [1, 2, 3][0]
+let m = {"a": 1}
+print add(x, y)
+print foo.bar(.base)[0]
+for i, v in 0..10
+    x += y div 2 % 3
+end
+fun add(x: int, y: int) -> int
+    return x - y
+end
+let f = fun(x) x * 2 end
+print "a ${x+1}  b"
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_blank_lines() {
+        let source = "\n\n/// adds\nfun add(x, y)\n\n    let z = x\n\n\n\n    return z + y\n\nend\n\n\n\nprint add(1, 2)\n\n\n";
+        let expected = "/// adds\nfun add(x, y)\n    let z = x\n\n    return z + y\nend\n\nprint add(1, 2)\n";
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_comments() {
+        let source = r#"// the start
+fun f()   // trailing
+      /* before */ let x = 1
+  /* a
+     b */
+    return x // done
+end
+////    not a doc comment
+   // the end"#;
+        let expected = r#"// the start
+fun f() // trailing
+    /* before */ let x = 1
+    /* a
+     b */
+    return x // done
+end
+////    not a doc comment
+// the end
+"#;
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_multiline_brackets() {
+        let source = "let m = {\n\"a\": 1,\n  \"b\": [1, 2],\n}\nprint map(arr, fun(x)\nreturn x * 2\nend)\n";
+        let expected = "let m = {\n    \"a\": 1,\n    \"b\": [1, 2],\n}\nprint map(arr, fun(x)\n    return x * 2\nend)\n";
+        assert_eq!(format_source(source).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_strings_keep_their_text() {
+        let source = "print \"\"\"\n  a  b\n\"\"\" + r\"x  \\n\"\nlet s = \"${ {\"k\": [1,2]}[\"k\"] }\"\n";
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+
+    #[test]
+    fn test_unparseable() {
+        let errors = format_source("let x = )").unwrap_err();
+        assert_eq!(errors, vec![Error::at(8..9, "[line 1] expected expression but found RParen")]);
+    }
+
+    // flush-left programs and literals the other tests' programs don't spell, on top of every one of those
+    const UNFORMATTED: &[&str] = &[
+        "let x = 1\nlet y: float = 2.5\nprint x + y * 3 - 4 / 2\nprint -x ** 2\nprint !true\n",
+        "let a = [1, 2, 3]\na[0] = a[1] + a[2]\nlet m = {\"a\": 1, \"b\": [true, nil]}\nm[\"c\"] = 0x1f + 0b101 + 0o17\nprint m\n",
+        "fun fib(n: int) -> int\nif n < 2\nreturn n\nend\nreturn fib(n - 1) + fib(n - 2)\nend\nprint fib(20)\n",
+        "let total = 0\nfor i in 0..10\nif i % 2 == 0 and i != 4 or i >= 9\ncontinue\nend\ntotal += i\nend\nfor i in 1..=3\ntotal -= i\nend\nprint total\n",
+        "let n = 0\nwhile true\nn += 1\nif n > 3\nbreak\nend\nend\nfor k, v in {\"a\": 1}\nprint \"${k}=${v + n}\"\nend\n",
+        "fun counter()\nlet count = 0\nreturn fun() -> int\ncount += 1\nreturn count\nend\nend\nlet c = counter()\nc()\nprint c()\nlet double = fun(x) x * 2 end\nprint double(4)\n",
+        "enum Form\nbase, superSaiyan, ultraInstinct\nend\nprotocol Warrior\nfun fight() -> int\nfun rest(hours: int)\nend\n/// a saiyan\nstruct Saiyan: Warrior\nlet name: string\nlet power: int\nlet form: Form\nfun fight() -> int\nif form == .base\npower *= 2\nelse\npower /= 2\nend\nreturn power\nend\nfun rest(hours: int)\nself.power -= hours\nend\nend\nlet goku = Saiyan(\"goku\", 9000, .base)\nprint [goku.fight(), goku is Saiyan]\n",
+        "// a comment\nlet x = 7 div 2 & 3 | 4 ^ ~5 << 1 >> 2 /* inline */\nprint \"\"\"\n  triple ${x}\n\"\"\"\nprint r\"raw \\n\" + \"\\t\\u{1F600}\"\nprint 9223372036854775808 - 1\n",
+    ];
+
+    #[test]
+    fn test_fixtures() {
+        let readme = include_str!("../README.md");
+        let start = readme.find("```\n").unwrap() + 4;
+        let fixtures = fixtures::ALL.iter().chain(UNFORMATTED).copied().chain([&readme[start..start + readme[start..].find("```").unwrap()]]);
+        for fixture in fixtures {
+            let program = parse(fixture).unwrap();
+            let once = format_source(fixture).unwrap();
+            assert_eq!(format_source(&once).unwrap(), once, "formatting is idempotent for\n{}", fixture);
+            assert_eq!(parse(&once).unwrap(), program, "formatting keeps the ast of\n{}", fixture);
+        }
+    }
+}
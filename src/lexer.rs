@@ -1,15 +1,40 @@
+use std::ops::Range;
+use crate::bigint::BigInt;
+use crate::error::Error;
 use crate::token::Token;
+// `start` and `current` are byte offsets into `source`
 struct Lexer {
     source: String,
     current: usize,
     start: usize,
-    line: u32,
+    // the open `${`s of interpolated strings, with the braces opened inside each and where it started
+    interpolations: Vec<Interpolation>,
+    spans: Spans,
+    // scanning carries on past a mistake, so one pass reports all of them
+    errors: Vec<Error>,
 }
 
-pub(crate) fn scan(source: &str) -> Vec<Token> {
+// the byte range of the source each token was scanned from, which leaves out whitespace and comments
+pub(crate) type Spans = Vec<Range<usize>>;
+
+struct Interpolation {
+    depth: usize,
+    // the offset of the `${`
+    start: usize,
+}
+
+// the tokens along with the byte range each one was scanned from
+pub(crate) fn scan(source: &str) -> Result<(Vec<Token>, Spans), Vec<Error>> {
     let mut lexer = Lexer::new(source);
     let tokens = lexer.scan();
-    tokens
+    if lexer.errors.is_empty() { Ok((tokens, lexer.spans)) } else { Err(lexer.errors) }
+}
+
+// the tokens and spans of any source, even one with errors, for tools that work on the source as it is
+pub(crate) fn scan_lenient(source: &str) -> (Vec<Token>, Spans) {
+    let mut lexer = Lexer::new(source);
+    let tokens = lexer.scan();
+    (tokens, lexer.spans)
 }
 
 impl Lexer {
@@ -18,7 +43,9 @@ impl Lexer {
             source: source.to_string(),
             current: 0,
             start: 0,
-            line: 1,
+            interpolations: vec![],
+            spans: vec![],
+            errors: vec![],
         }
     }
 
@@ -26,23 +53,107 @@ impl Lexer {
         let mut tokens = vec![];
 
         while !self.is_at_end() {
+            self.record_span(tokens.len());
             self.skip_whitespace();
             self.start = self.current;
             let c = self.advance();
 
+            if c == 'r' && self.peek() == '"' {
+                self.advance();
+                tokens.push(self.raw_string());
+                continue;
+            }
             if is_alpha(c) { tokens.push(self.ident()) }
             if is_digit(c) { tokens.push(self.num()) }
 
             match c {
                 '(' => tokens.push(Token::LParen),
                 ')' => tokens.push(Token::RParen),
+                '[' => tokens.push(Token::LBracket),
+                ']' => tokens.push(Token::RBracket),
+                '{' => {
+                    if let Some(interpolation) = self.interpolations.last_mut() {
+                        interpolation.depth += 1;
+                    }
+                    tokens.push(Token::LBrace);
+                }
+                '}' => match self.interpolations.last_mut() {
+                    // the end of an interpolated expression, so the string continues
+                    Some(Interpolation { depth: 0, .. }) => {
+                        self.interpolations.pop();
+                        tokens.push(self.string(true));
+                    }
+                    Some(interpolation) => {
+                        interpolation.depth -= 1;
+                        tokens.push(Token::RBrace);
+                    }
+                    None => tokens.push(Token::RBrace),
+                },
                 ';' => tokens.push(Token::Semicolon),
                 ',' => tokens.push(Token::Comma),
-                '+' => tokens.push(Token::Plus),
-                '-' => tokens.push(Token::Minus),
-                '*' => tokens.push(Token::Star),
-                '/' => tokens.push(Token::Slash),
+                '+' => {
+                    if self.peek() == '=' {
+                        tokens.push(Token::PlusEq);
+                        self.advance();
+                    } else {
+                        tokens.push(Token::Plus);
+                    }
+                }
+                '-' => {
+                    if self.peek() == '=' {
+                        tokens.push(Token::MinusEq);
+                        self.advance();
+                    } else if self.peek() == '>' {
+                        tokens.push(Token::Arrow);
+                        self.advance();
+                    } else {
+                        tokens.push(Token::Minus);
+                    }
+                }
+                '*' => {
+                    if self.peek() == '=' {
+                        tokens.push(Token::StarEq);
+                        self.advance();
+                    } else if self.peek() == '*' {
+                        tokens.push(Token::StarStar);
+                        self.advance();
+                    } else {
+                        tokens.push(Token::Star);
+                    }
+                }
+                '/' if self.peek() == '/' => tokens.push(self.doc_comment()),
+                '/' => {
+                    if self.peek() == '=' {
+                        tokens.push(Token::SlashEq);
+                        self.advance();
+                    } else {
+                        tokens.push(Token::Slash);
+                    }
+                }
+                '%' => tokens.push(Token::Percent),
+                '&' => tokens.push(Token::Amp),
+                '|' => tokens.push(Token::Pipe),
+                '^' => tokens.push(Token::Caret),
+                '~' => tokens.push(Token::Tilde),
                 ':' => tokens.push(Token::Colon),
+                '.' => {
+                    if self.peek() == '.' {
+                        self.advance();
+                        if self.peek() == '=' {
+                            tokens.push(Token::DotDotEq);
+                            self.advance();
+                        } else {
+                            tokens.push(Token::DotDot);
+                        }
+                    } else if is_digit(self.peek()) {
+                        let start = self.current;
+                        while is_digit(self.peek()) { self.advance(); }
+                        let message = format!("a number cannot start with '.', write 0.{} instead", &self.source[start..self.current]);
+                        self.error(self.start..self.current, &message);
+                    } else {
+                        tokens.push(Token::Dot);
+                    }
+                }
                 '=' => {
                     if self.peek() == '=' {
                         tokens.push(Token::EqEq);
@@ -63,6 +174,9 @@ impl Lexer {
                     if self.peek() == '=' {
                         tokens.push(Token::LtEq);
                         self.advance();
+                    } else if self.peek() == '<' {
+                        tokens.push(Token::LtLt);
+                        self.advance();
                     } else {
                         tokens.push(Token::Lt);
                     }
@@ -71,23 +185,51 @@ impl Lexer {
                     if self.peek() == '=' {
                         tokens.push(Token::GtEq);
                         self.advance();
+                    } else if self.peek() == '>' {
+                        tokens.push(Token::GtGt);
+                        self.advance();
                     } else {
                         tokens.push(Token::Gt);
                     }
                 }
-                '"' => { tokens.push(self.string()); }
-                '\n' => {
-                    self.line += 1;
-                    tokens.push(Token::NewLine);
+                '"' if self.peek() == '"' && self.peek_next() == '"' => {
+                    self.advance();
+                    self.advance();
+                    tokens.push(self.triple_string());
                 }
+                '"' => { tokens.push(self.string(false)); }
+                '\n' => tokens.push(Token::NewLine),
                 _ => (),
             }
         }
 
+        if let Some(interpolation) = self.interpolations.pop() {
+            self.error(interpolation.start..self.source.len(), "unterminated string interpolation");
+        }
+
+        self.record_span(tokens.len());
         tokens.push(Token::Eof);
+        self.spans.push(self.source.len()..self.source.len());
         tokens
     }
 
+    // each pass of the scanning loop makes at most one token, out of the text from `start`
+    fn record_span(&mut self, tokens: usize) {
+        if tokens > self.spans.len() {
+            self.spans.push(self.start..self.current.min(self.source.len()));
+        }
+    }
+
+    // the message leads with the line and column the span starts at
+    fn error(&mut self, span: Range<usize>, message: &str) {
+        let len = self.source.len();
+        let span = span.start.min(len)..span.end.min(len);
+        let before = &self.source[..span.start];
+        let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+        let (line, column) = (before.matches('\n').count() + 1, before[line_start..].chars().count() + 1);
+        self.errors.push(Error::at(span, &format!("[line {}, column {}] {}", line, column, message)));
+    }
+
     //Helpers
     fn is_at_end(&self) -> bool {
         self.current >= self.source.len()
@@ -95,13 +237,13 @@ impl Lexer {
 
     fn advance(&mut self) -> char {
         let current = self.char_at(self.current);
-        self.current += 1;
+        self.current += current.len_utf8();
         current
     }
 
+    // the char starting at byte `i`, or `\0` past the end
     fn char_at(&self, i: usize) -> char {
-        if i >= self.source.len() { return '\0' }
-        self.source.chars().nth(i).expect("could not get char")
+        self.source.get(i..).and_then(|s| s.chars().next()).unwrap_or('\0')
     }
 
     fn peek(&self) -> char {
@@ -109,25 +251,61 @@ impl Lexer {
     }
 
     fn peek_next(&self) -> char {
-        self.char_at(self.current + 1)
+        self.char_at(self.current + self.peek().len_utf8())
     }
 
     fn skip_whitespace(&mut self) {
-        let c = self.peek();
-
-        match c {
-            ' ' | '\t' | '\r' => { self.advance(); }
-            '/' => {
-                if self.peek_next() == '/' {
+        loop {
+            match self.peek() {
+                ' ' | '\t' | '\r' => { self.advance(); }
+                '/' if self.peek_next() == '/' => {
+                    // `///` starts a doc comment, which is kept as a token, but `////` is an ordinary comment
+                    if self.char_at(self.current + 2) == '/' && self.char_at(self.current + 3) != '/' { return }
                     while self.peek() != '\n' && !self.is_at_end() { self.advance(); }
                 }
+                '/' if self.peek_next() == '*' => self.block_comment(),
+                _ => return,
+            }
+        }
+    }
+
+    // block comments nest, so code that already has one can be commented out
+    fn block_comment(&mut self) {
+        let start = self.current;
+        self.advance();
+        self.advance();
+        let mut depth = 1;
+        while depth > 0 {
+            if self.is_at_end() {
+                self.error(start..self.current, "unterminated block comment");
+                return;
+            }
+            match self.advance() {
+                '/' if self.peek() == '*' => {
+                    self.advance();
+                    depth += 1;
+                }
+                '*' if self.peek() == '/' => {
+                    self.advance();
+                    depth -= 1;
+                }
+                _ => (),
             }
-            _ => return,
         }
     }
 
+    // the first `/` is already consumed
+    fn doc_comment(&mut self) -> Token {
+        self.advance();
+        self.advance();
+        let start = self.current;
+        while self.peek() != '\n' && !self.is_at_end() { self.advance(); }
+        let text = self.source[start..self.current].trim_end_matches('\r');
+        Token::Doc(text.strip_prefix(' ').unwrap_or(text).to_string())
+    }
+
     fn ident(&mut self) -> Token {
-        while is_alpha(self.peek()) || is_digit(self.peek()) {
+        while is_alpha(self.peek()) || self.peek().is_alphanumeric() {
             self.advance();
         }
 
@@ -147,37 +325,55 @@ impl Lexer {
         match c {
             'l' => { return self.check_keyword("et", 1, 2, Token::Let); }
             'r' => { return self.check_keyword("eturn", 1, 5, Token::Return); }
-            'i' => {
-                if self.current - self.start > 1 && self.current_string().len() == 2 {
-                    match self.char_at(self.start + 1) {
-                        'f' => { return Token::If; }
-                        'n' => { return Token::In; }
-                        _ => (),
-                    }
+            'i' if self.current - self.start > 1 && self.current_string().len() == 2 => {
+                match self.char_at(self.start + 1) {
+                    'f' => { return Token::If; }
+                    'n' => { return Token::In; }
+                    's' => { return Token::Is; }
+                    _ => (),
                 }
             }
             'n' => { return self.check_keyword("il", 1 , 2, Token::Nil); }
             't' => { return self.check_keyword("rue", 1, 3, Token::True); }
-            'p' => { return self.check_keyword("rint", 1, 4, Token::Print); }
-            'e' => {
-                if self.current - self.start > 1 {
-                    match self.char_at(self.start + 1) {
-                        'n' => { return self.check_keyword("d", 2, 1, Token::End); }
-                        'l' => { return self.check_keyword("se", 2, 2, Token::Else); }
-                        _ => (),
-                    }
+            'p' if self.current - self.start > 2 => {
+                match self.char_at(self.start + 2) {
+                    'i' => { return self.check_keyword("rint", 1, 4, Token::Print); }
+                    'o' => { return self.check_keyword("rotocol", 1, 7, Token::Protocol); }
+                    _ => (),
                 }
             }
-            'f' => {
-                if self.current - self.start > 1 {
-                    match self.char_at(self.start + 1) {
-                        'u' => { return self.check_keyword("n", 2, 1, Token::Fun); }
-                        'a' => { return self.check_keyword("lse", 2, 3, Token::False); }
-                        'o' => { return self.check_keyword("r", 2, 1, Token::For); }
-                        _ => (),
+            'e' if self.current - self.start > 1 => {
+                match self.char_at(self.start + 1) {
+                    'n' if self.current - self.start > 2 => {
+                        match self.char_at(self.start + 2) {
+                            'd' => { return self.check_keyword("", 3, 0, Token::End); }
+                            'u' => { return self.check_keyword("m", 3, 1, Token::Enum); }
+                            _ => (),
+                        }
                     }
+                    'l' => { return self.check_keyword("se", 2, 2, Token::Else); }
+                    _ => (),
+                }
+            }
+            'f' if self.current - self.start > 1 => {
+                match self.char_at(self.start + 1) {
+                    'u' => { return self.check_keyword("n", 2, 1, Token::Fun); }
+                    'a' => { return self.check_keyword("lse", 2, 3, Token::False); }
+                    'o' => { return self.check_keyword("r", 2, 1, Token::For); }
+                    _ => (),
                 }
             }
+            'w' => { return self.check_keyword("hile", 1, 4, Token::While); }
+            's' if self.current - self.start > 1 => {
+                match self.char_at(self.start + 1) {
+                    't' => { return self.check_keyword("ruct", 2, 4, Token::Struct); }
+                    'e' => { return self.check_keyword("lf", 2, 2, Token::SelfKw); }
+                    _ => (),
+                }
+            }
+            'b' => { return self.check_keyword("reak", 1, 4, Token::Break); }
+            'd' => { return self.check_keyword("iv", 1, 2, Token::Div); }
+            'c' => { return self.check_keyword("ontinue", 1, 7, Token::Continue); }
             'a' => { return self.check_keyword("nd", 1, 2, Token::And); }
             'o' => { return self.check_keyword("r", 1, 1, Token::Or); }
             _ => (),
@@ -188,60 +384,258 @@ impl Lexer {
 
     fn check_keyword(&self, rest: &str, start: usize, len: usize, token: Token) -> Token {
         if self.current - self.start == start + len {
-            let slice = &self.source[self.start+start..self.start+start+len];
-            if rest == slice {
+            let slice = self.source.get(self.start+start..self.start+start+len);
+            if slice == Some(rest) {
                 return token;
             }
         }
         Token::Ident(self.current_string())
     }
 
+    // decimal ints and floats with an optional exponent, or ints in hex, binary or octal.
+    // any of them may separate digits with `_`
     fn num(&mut self) -> Token {
-        while is_digit(self.peek()) { self.advance(); }
+        if self.char_at(self.start) == '0' && matches!(self.peek(), 'x' | 'b' | 'o') {
+            let (radix, name) = match self.advance() {
+                'x' => (16, "hex"),
+                'b' => (2, "binary"),
+                _ => (8, "octal"),
+            };
+            let digits = self.digits(radix);
+            if digits.is_empty() {
+                self.error(self.start..self.current, &format!("expected digits after '{}'", self.current_string()));
+                return Token::Int(0);
+            }
+            self.check_number_end(name);
+            return match i64::from_str_radix(&digits, radix) {
+                Ok(i) => Token::Int(i),
+                // big ints are carried as decimal text, however they were written
+                Err(_) => Token::BigInt(BigInt::parse_radix(&digits, radix).expect("digits in radix").to_string()),
+            };
+        }
+
+        let mut text = self.char_at(self.start).to_string();
+        text.push_str(&self.digits(10));
+        let mut float = false;
 
-        if self.peek() == '.' {
+        // `0..10` is a range and `5.abs()` a call, but `10.` is a float
+        if self.peek() == '.' && self.peek_next() != '.' && !is_alpha(self.peek_next()) {
             self.advance();
-            while is_digit(self.peek()) { self.advance(); }
-            if is_alpha(self.peek()) {
-                todo!("handle error");
+            text.push('.');
+            text.push_str(&self.digits(10));
+            float = true;
+        }
+        if matches!(self.peek(), 'e' | 'E') {
+            let sign = matches!(self.peek_next(), '+' | '-');
+            let digit = if sign { self.char_at(self.current + 2) } else { self.peek_next() };
+            if !is_digit(digit) {
+                self.error(self.start..self.current, &format!("expected digits in the exponent of {}", self.current_string()));
+                return Token::Float(0.0);
+            }
+            text.push(self.advance());
+            if sign { text.push(self.advance()); }
+            text.push_str(&self.digits(10));
+            float = true;
+        }
+        self.check_number_end("number");
+
+        if float {
+            match text.parse::<f64>() {
+                Ok(f) if f.is_finite() => Token::Float(f),
+                _ => {
+                    self.error(self.start..self.current, &format!("float literal {} is out of range", self.current_string()));
+                    Token::Float(0.0)
+                }
             }
-            let string = self.current_string();
-            return Token::Float(string.parse().expect("cannot parse float"))
         } else {
-            let string = self.current_string();
-            return Token::Int(string.parse().expect("cannot parse int"))
+            match text.parse::<i64>() {
+                Ok(i) => Token::Int(i),
+                Err(_) => Token::BigInt(text),
+            }
         }
     }
 
-    fn string(&mut self) -> Token {
-        while self.peek() != '"' && !self.is_at_end() {
-            self.advance();
+    // reads digits in `radix` and the `_`s between them, returning the digits alone
+    fn digits(&mut self, radix: u32) -> String {
+        let mut digits = String::new();
+        while self.peek().is_digit(radix) || self.peek() == '_' {
+            let c = self.advance();
+            if c != '_' {
+                digits.push(c);
+            } else if !self.peek().is_digit(radix) && self.peek() != '_' {
+                self.error(self.start..self.current, &format!("{} cannot end with '_'", self.current_string()));
+            }
+        }
+        digits
+    }
 
-            if self.peek() == '\n' { self.line += 1; }
+    // `12ab` and `0xFG` are mistakes rather than a number followed by a name, so the rest of the word goes too
+    fn check_number_end(&mut self, name: &str) {
+        let c = self.peek();
+        if c.is_alphanumeric() || c == '_' {
+            self.error(self.start..self.current, &format!("invalid digit '{}' in {} {}", c, name, self.current_string()));
+            while self.peek().is_alphanumeric() || self.peek() == '_' { self.advance(); }
         }
+    }
 
-        self.advance();
+    // reads up to the closing quote, or up to a `${` starting an interpolated expression. `rest` is set
+    // for the part of an interpolated string after a `}`
+    fn string(&mut self, rest: bool) -> Token {
+        // the opening quote or the `}`
+        let start = self.current - 1;
+        let mut val = String::new();
+        loop {
+            if self.is_at_end() {
+                self.error(start..self.current, "unterminated string");
+                break;
+            }
+            match self.advance() {
+                '"' => break,
+                '\\' => {
+                    let escape_start = self.current - 1;
+                    match escape(&mut || self.advance()) {
+                        Ok(c) => val.push(c),
+                        Err(message) => self.error(escape_start..self.current, &message),
+                    }
+                }
+                '$' if self.peek() == '{' => {
+                    self.advance();
+                    self.interpolations.push(Interpolation { depth: 0, start: self.current - 2 });
+                    return if rest { Token::InterpolationMiddle(val) } else { Token::Interpolation(val) };
+                }
+                c => val.push(c),
+            }
+        }
+        if rest { Token::InterpolationEnd(val) } else { Token::String(val) }
+    }
 
-        if self.is_at_end() {
-            todo!("error")
+    // `r"..."` takes everything up to the next quote as it is, without escapes or interpolation
+    fn raw_string(&mut self) -> Token {
+        let mut val = String::new();
+        loop {
+            if self.is_at_end() {
+                self.error(self.start..self.current, "unterminated string");
+                return Token::String(val);
+            }
+            match self.advance() {
+                '"' => return Token::String(val),
+                c => val.push(c),
+            }
         }
+    }
 
-        return Token::String(self.get_string(self.start+1, self.current-1))
+    // `"""` strings may span lines, dropping the line breaks next to the quotes and the indentation
+    // every line shares. they take escapes but not interpolation
+    fn triple_string(&mut self) -> Token {
+        let mut raw = String::new();
+        loop {
+            if self.is_at_end() {
+                self.error(self.start..self.current, "unterminated string");
+                break;
+            }
+            if self.peek() == '"' && self.peek_next() == '"' && self.char_at(self.current + 2) == '"' {
+                self.current += 3;
+                break;
+            }
+            match self.advance() {
+                // escapes are checked here, where their place in the source is known, and kept as they are
+                // until the indentation is gone. this also keeps escaped quotes from closing the string
+                '\\' => {
+                    let escape_start = self.current - 1;
+                    let escaped = escape(&mut || self.advance());
+                    let end = self.current.min(self.source.len());
+                    raw.push_str(&self.source[escape_start..end]);
+                    if let Err(message) = escaped {
+                        self.error(escape_start..end, &message);
+                    }
+                }
+                c => raw.push(c),
+            }
+        }
+
+        let dedented = dedent(&raw);
+        let mut chars = dedented.chars();
+        let mut val = String::new();
+        while let Some(c) = chars.next() {
+            if c != '\\' {
+                val.push(c);
+                continue;
+            }
+            // a bad escape was reported while scanning
+            if let Ok(c) = escape(&mut || chars.next().unwrap_or('\0')) {
+                val.push(c);
+            }
+        }
+        Token::String(val)
     }
 }
 
+// the character an escape sequence stands for, reading what follows the backslash from `next`
+fn escape(next: &mut dyn FnMut() -> char) -> Result<char, String> {
+    match next() {
+        'n' => Ok('\n'),
+        't' => Ok('\t'),
+        'r' => Ok('\r'),
+        '0' => Ok('\0'),
+        '\\' => Ok('\\'),
+        '"' => Ok('"'),
+        '$' => Ok('$'),
+        'u' => {
+            if next() != '{' {
+                return Err("expected '{' after '\\u'".to_string());
+            }
+            let mut hex = String::new();
+            loop {
+                match next() {
+                    '}' => break,
+                    c if c.is_ascii_hexdigit() && hex.len() < 6 => hex.push(c),
+                    _ => return Err("invalid unicode escape".to_string()),
+                }
+            }
+            u32::from_str_radix(&hex, 16).ok()
+                .and_then(char::from_u32)
+                .ok_or_else(|| format!("invalid unicode escape '\\u{{{}}}'", hex))
+        }
+        '\0' => Err("unterminated escape sequence".to_string()),
+        c => Err(format!("unknown escape sequence '\\{}'", c)),
+    }
+}
+
+// drops the line break after the opening quotes, the line holding only the closing quotes,
+// and the indentation shared by every line with text on it
+fn dedent(raw: &str) -> String {
+    let raw = raw.strip_prefix('\n').unwrap_or(raw);
+    let mut lines: Vec<&str> = raw.split('\n').collect();
+    if lines.len() > 1 && lines.last().is_some_and(|l| l.trim().is_empty()) {
+        lines.pop();
+    }
+
+    let indent = lines.iter()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| l.len() - l.trim_start_matches([' ', '\t']).len())
+        .min()
+        .unwrap_or(0);
+    let lines: Vec<&str> = lines.iter().map(|l| l.get(indent..).unwrap_or("")).collect();
+    lines.join("\n")
+}
+
 pub(crate) fn is_digit(c: char) -> bool {
-    '0' <= c && c <= '9'
+    c.is_ascii_digit()
 }
 
+// letters of any script start identifiers. std has no XID_Start table, and Alphabetic is its close superset
 pub(crate) fn is_alpha(c: char) -> bool {
-    'a' <= c && c <= 'z' || 'A' <= c && c <= 'Z' || c == '_'
+    c.is_alphabetic() || c == '_'
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
 
+    fn errors(source: &str) -> Vec<std::string::String> {
+        scan(source).unwrap_err().iter().map(|error| error.message().to_string()).collect()
+    }
+
     #[test]
     fn test_lexer() {
         let s = r#"let num1 = 5
@@ -253,7 +647,7 @@ mod test {
             return x + y
         end
 
-        !-/*<> <= >= == !=
+        !-/ *<> <= >= == !=
 
         if true
             return nil
@@ -371,7 +765,7 @@ mod test {
             Token::Eof
         ];
 
-        let tokens = scan(s);
+        let tokens = scan(s).unwrap().0;
 
         for (i, t) in tokens.iter().enumerate() {
             let e = &exp[i];
@@ -380,4 +774,391 @@ mod test {
         }
         assert_eq!(tokens.len(), exp.len());
     }
+
+    #[test]
+    fn test_loop_keywords() {
+        let tokens = scan("while break continue whiles breaker").unwrap().0;
+        let exp = vec![
+            Token::While,
+            Token::Break,
+            Token::Continue,
+            Token::Ident("whiles".to_string()),
+            Token::Ident("breaker".to_string()),
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens, exp);
+    }
+
+    #[test]
+    fn test_struct_tokens() {
+        let tokens = scan("protocol is enum struct self selfish x += 1 -= *= /= ->").unwrap().0;
+        let exp = vec![
+            Token::Protocol,
+            Token::Is,
+            Token::Enum,
+            Token::Struct,
+            Token::SelfKw,
+            Token::Ident("selfish".to_string()),
+            Token::Ident("x".to_string()),
+            Token::PlusEq,
+            Token::Int(1),
+            Token::MinusEq,
+            Token::StarEq,
+            Token::SlashEq,
+            Token::Arrow,
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens, exp);
+    }
+
+    #[test]
+    fn test_ranges() {
+        let tokens = scan("0..10 1..=n [x.y]").unwrap().0;
+        let exp = vec![
+            Token::Int(0),
+            Token::DotDot,
+            Token::Int(10),
+            Token::Int(1),
+            Token::DotDotEq,
+            Token::Ident("n".to_string()),
+            Token::LBracket,
+            Token::Ident("x".to_string()),
+            Token::Dot,
+            Token::Ident("y".to_string()),
+            Token::RBracket,
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens, exp);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let tokens = scan(r#""power: ${level + 1}!" "${m["a"]} \${x} ${ {"k": "${v}"} }""#).unwrap().0;
+        let exp = vec![
+            Token::Interpolation("power: ".to_string()),
+            Token::Ident("level".to_string()),
+            Token::Plus,
+            Token::Int(1),
+            Token::InterpolationEnd("!".to_string()),
+            Token::Interpolation("".to_string()),
+            Token::Ident("m".to_string()),
+            Token::LBracket,
+            Token::String("a".to_string()),
+            Token::RBracket,
+            Token::InterpolationMiddle(" ${x} ".to_string()),
+            Token::LBrace,
+            Token::String("k".to_string()),
+            Token::Colon,
+            Token::Interpolation("".to_string()),
+            Token::Ident("v".to_string()),
+            Token::InterpolationEnd("".to_string()),
+            Token::RBrace,
+            Token::InterpolationEnd("".to_string()),
+            Token::Eof,
+        ];
+
+        assert_eq!(tokens, exp);
+    }
+
+    #[test]
+    fn test_string_at_end_of_input() {
+        assert_eq!(scan("\"end\"").unwrap().0, vec![Token::String("end".to_string()), Token::Eof]);
+    }
+
+    #[test]
+    fn test_unterminated_interpolation() {
+        assert_eq!(errors("let a = 1\nprint \"a: ${a\n"), vec!["[line 2, column 11] unterminated string interpolation"]);
+    }
+
+    #[test]
+    fn test_error_spans() {
+        // from the `${` to the end, from the backslash to the end of the escape, and the whole number
+        let source = "print \"a ${ \"\\q\" 0x\n";
+        let spans: Vec<_> = scan(source).unwrap_err().iter().map(|error| error.span().unwrap()).collect();
+        assert_eq!(spans, vec![13..15, 17..19, 9..source.len()]);
+    }
+
+    #[test]
+    fn test_unterminated_string() {
+        assert_eq!(errors("print \"abc"), vec!["[line 1, column 7] unterminated string"]);
+    }
+
+    #[test]
+    fn test_escapes() {
+        let tokens = scan(r#""a\n\tb\\ \"q\" \$ \r\0 \u{1F600}\u{e9}""#).unwrap().0;
+        assert_eq!(tokens, vec![Token::String("a\n\tb\\ \"q\" $ \r\0 😀é".to_string()), Token::Eof]);
+    }
+
+    #[test]
+    fn test_unknown_escape() {
+        assert_eq!(errors(r#""ab\q""#), vec!["[line 1, column 4] unknown escape sequence '\\q'"]);
+    }
+
+    #[test]
+    fn test_invalid_unicode_escape() {
+        assert_eq!(errors(r#""\u{110000}""#), vec!["[line 1, column 2] invalid unicode escape '\\u{110000}'"]);
+    }
+
+    #[test]
+    fn test_surrogate_escape() {
+        // surrogates are code points but not chars
+        assert_eq!(errors(r#"print "ok\u{D800}""#), vec!["[line 1, column 10] invalid unicode escape '\\u{D800}'"]);
+    }
+
+    #[test]
+    fn test_triple_quoted_escape_columns() {
+        let s = "let s = \"\"\"\n    a \\q\n    \\u{DFFF}\n    \"\"\"\n";
+        assert_eq!(errors(s), vec![
+            "[line 2, column 7] unknown escape sequence '\\q'",
+            "[line 3, column 5] invalid unicode escape '\\u{DFFF}'",
+        ]);
+    }
+
+    #[test]
+    fn test_unclosed_unicode_escape() {
+        // the quote is taken as part of the escape, so the string doesn't end either
+        assert_eq!(errors(r#""\u{41""#), vec![
+            "[line 1, column 2] invalid unicode escape",
+            "[line 1, column 1] unterminated string",
+        ]);
+    }
+
+    #[test]
+    fn test_operators() {
+        let tokens = scan("a % b ** c div d & e | f ^ ~g << h >> i <= j divide").unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::Ident("a".to_string()),
+            Token::Percent,
+            Token::Ident("b".to_string()),
+            Token::StarStar,
+            Token::Ident("c".to_string()),
+            Token::Div,
+            Token::Ident("d".to_string()),
+            Token::Amp,
+            Token::Ident("e".to_string()),
+            Token::Pipe,
+            Token::Ident("f".to_string()),
+            Token::Caret,
+            Token::Tilde,
+            Token::Ident("g".to_string()),
+            Token::LtLt,
+            Token::Ident("h".to_string()),
+            Token::GtGt,
+            Token::Ident("i".to_string()),
+            Token::LtEq,
+            Token::Ident("j".to_string()),
+            Token::Ident("divide".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_comments() {
+        let s = "1 /* a /* nested */ comment\n */ 2\n/// adds two\n///ints\n//// not a doc\n// nor this\n/**/3 /// trailing";
+        assert_eq!(scan(s).unwrap().0, vec![
+            Token::Int(1),
+            Token::Int(2),
+            Token::NewLine,
+            Token::Doc("adds two".to_string()),
+            Token::NewLine,
+            Token::Doc("ints".to_string()),
+            Token::NewLine,
+            Token::NewLine,
+            Token::NewLine,
+            Token::Int(3),
+            Token::Doc("trailing".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_unterminated_block_comment() {
+        assert_eq!(errors("1\n1 /* /* */\n"), vec!["[line 2, column 3] unterminated block comment"]);
+    }
+
+    #[test]
+    fn test_block_comment_lines() {
+        assert_eq!(errors("/*\n\n*/ \"a"), vec!["[line 3, column 4] unterminated string"]);
+    }
+
+    #[test]
+    fn test_raw_strings() {
+        let tokens = scan("r\"C:\\path\\${x}\" r \"\"").unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::String(r"C:\path\${x}".to_string()),
+            Token::Ident("r".to_string()),
+            Token::String("".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_triple_quoted_strings() {
+        let s = "let s = \"\"\"\n        goku\n          \"kakarot\"\\t\n\n        ${x}\n        \"\"\"\nprint \"\"\"one line\"\"\" \"\"";
+        let tokens = scan(s).unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::Let,
+            Token::Ident("s".to_string()),
+            Token::Eq,
+            Token::String("goku\n  \"kakarot\"\t\n\n${x}".to_string()),
+            Token::NewLine,
+            Token::Print,
+            Token::String("one line".to_string()),
+            Token::String("".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_unterminated_triple_quoted_string() {
+        assert_eq!(errors("let s = \"\"\"\nabc\"\"\n"), vec!["[line 1, column 9] unterminated string"]);
+    }
+
+    #[test]
+    fn test_unicode() {
+        let tokens = scan("let größe = \"héllo 日本\" // ünïcode comment\nprint größe2 // é\n\"😀\"").unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::Let,
+            Token::Ident("größe".to_string()),
+            Token::Eq,
+            Token::String("héllo 日本".to_string()),
+            Token::NewLine,
+            Token::Print,
+            Token::Ident("größe2".to_string()),
+            Token::NewLine,
+            Token::String("😀".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_comments_after_code() {
+        let tokens = scan("x   // comment\ny\t// another").unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::Ident("x".to_string()),
+            Token::NewLine,
+            Token::Ident("y".to_string()),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_unicode_columns() {
+        assert_eq!(errors("\"日本語 \\x\""), vec!["[line 1, column 6] unknown escape sequence '\\x'"]);
+    }
+
+    #[test]
+    fn test_numeric_literals() {
+        let tokens = scan("0xFF 0x7fff_ffff 0b1010 0o17 1_000_000 6.02e23 1E-3 2.5e+2 10. 0..2 0.5").unwrap().0;
+        assert_eq!(tokens, vec![
+            Token::Int(255),
+            Token::Int(i32::MAX as i64),
+            Token::Int(10),
+            Token::Int(15),
+            Token::Int(1_000_000),
+            Token::Float(6.02e23),
+            Token::Float(1e-3),
+            Token::Float(250.0),
+            Token::Float(10.0),
+            Token::Int(0),
+            Token::DotDot,
+            Token::Int(2),
+            Token::Float(0.5),
+            Token::Eof,
+        ]);
+    }
+
+    #[test]
+    fn test_leading_dot() {
+        assert_eq!(errors("let x = .5"), vec!["[line 1, column 9] a number cannot start with '.', write 0.5 instead"]);
+    }
+
+    #[test]
+    fn test_big_int() {
+        let (tokens, _) = scan("9223372036854775807 9_223_372_036_854_775_808").unwrap();
+        assert_eq!(tokens[..2], [Token::Int(i64::MAX), Token::BigInt("9223372036854775808".to_string())]);
+    }
+
+    #[test]
+    fn test_radix_big_int() {
+        let (tokens, _) = scan("0x1_0000_0000_0000_0000 0b1000000000000000000000000000000000000000000000000000000000000000 0o7777777777777777777777").unwrap();
+        assert_eq!(tokens[..3], [
+            Token::BigInt("18446744073709551616".to_string()),
+            Token::BigInt("9223372036854775808".to_string()),
+            Token::BigInt("73786976294838206463".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_float_overflow() {
+        assert_eq!(errors("1e400"), vec!["[line 1, column 1] float literal 1e400 is out of range"]);
+    }
+
+    #[test]
+    fn test_invalid_digit() {
+        assert_eq!(errors("x 0b12"), vec!["[line 1, column 3] invalid digit '2' in binary 0b1"]);
+    }
+
+    #[test]
+    fn test_letters_after_number() {
+        assert_eq!(errors("12ab"), vec!["[line 1, column 1] invalid digit 'a' in number 12"]);
+    }
+
+    #[test]
+    fn test_trailing_separator() {
+        assert_eq!(errors("1_000_ + 1"), vec!["[line 1, column 1] 1_000_ cannot end with '_'"]);
+    }
+
+    #[test]
+    fn test_empty_exponent() {
+        assert_eq!(errors("1e+"), vec!["[line 1, column 1] expected digits in the exponent of 1"]);
+    }
+
+    #[test]
+    fn test_empty_hex() {
+        assert_eq!(errors("0x"), vec!["[line 1, column 1] expected digits after '0x'"]);
+    }
+
+    #[test]
+    fn test_number_errors_in_statements() {
+        // every bad literal is reported with where it starts, and scanning carries on after each
+        let s = "print 0x\nprint 1_\nprint 1e400\nprint .5\n";
+        assert_eq!(errors(s), vec![
+            "[line 1, column 7] expected digits after '0x'",
+            "[line 2, column 7] 1_ cannot end with '_'",
+            "[line 3, column 7] float literal 1e400 is out of range",
+            "[line 4, column 7] a number cannot start with '.', write 0.5 instead",
+        ]);
+    }
+
+    fn large_source(lines: usize) -> String {
+        "let größe = \"héllo ${x + 1}\" // コメント\nfor i in 0..10\n    total += i * 2.5\nend\n".repeat(lines)
+    }
+
+    // would take hours if lexing were quadratic
+    #[test]
+    fn test_large_input() {
+        let tokens = scan(&large_source(20_000)).unwrap().0;
+        assert_eq!(tokens.len(), 20_000 * 24 + 1);
+    }
+
+    // cargo test --release bench_lexer -- --ignored --nocapture
+    #[test]
+    #[ignore]
+    fn bench_lexer() {
+        let source = large_source(100_000);
+        let start = std::time::Instant::now();
+        let tokens = scan(&source).unwrap().0;
+        let elapsed = start.elapsed();
+        println!(
+            "lexed {:.1} MB into {} tokens in {:?} ({:.1} MB/s)",
+            source.len() as f64 / 1e6,
+            tokens.len(),
+            elapsed,
+            source.len() as f64 / 1e6 / elapsed.as_secs_f64(),
+        );
+    }
 }
@@ -0,0 +1,28 @@
+mod token;
+mod lexer;
+mod parser;
+mod ast;
+mod compiler;
+mod vm;
+mod opcodes;
+mod visitor;
+mod value;
+mod error;
+mod typechecker;
+mod resolver;
+mod engine;
+mod stdlib;
+mod bigint;
+pub mod syntax;
+mod formatter;
+mod linter;
+#[cfg(test)]
+mod fixtures;
+
+pub use engine::{Capabilities, Engine, FromValue, IntoArgs, IntoValue, NativeFunction, NativeResult, Script};
+pub use error::Error;
+pub use formatter::format_source;
+pub use linter::{Diagnostic, Level, LintConfig};
+pub use token::Token;
+pub use value::Value;
+pub use vm::VM;
@@ -0,0 +1,553 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::{Expr, NodeId, Param, Stmt};
+use crate::error::Error;
+use crate::parser;
+use crate::resolver::{self, DeclarationId, DeclarationKind, Declarations};
+use crate::syntax::{self, TriviaKind};
+use crate::token::Token;
+
+// every rule with the level it has unless configured otherwise
+const RULES: [(&str, Level); 10] = [
+    ("unused-variable", Level::Warn),
+    ("unused-parameter", Level::Warn),
+    ("unused-function", Level::Warn),
+    ("shadowed-binding", Level::Warn),
+    ("unreachable-code", Level::Warn),
+    ("self-comparison", Level::Deny),
+    ("constant-condition", Level::Warn),
+    ("empty-block", Level::Warn),
+    ("function-name", Level::Warn),
+    ("type-name", Level::Warn),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Level {
+    // the rule is off
+    Allow,
+    Warn,
+    // the program fails the lint
+    Deny,
+}
+
+// the level of each rule
+#[derive(Clone, Debug)]
+pub struct LintConfig {
+    levels: HashMap<&'static str, Level>,
+}
+
+impl Default for LintConfig {
+    fn default() -> Self {
+        LintConfig { levels: RULES.into_iter().collect() }
+    }
+}
+
+impl LintConfig {
+    pub fn set(&mut self, rule: &str, level: Level) -> Result<(), Error> {
+        match self.levels.keys().find(|id| **id == rule) {
+            Some(id) => {
+                self.levels.insert(id, level);
+                Ok(())
+            }
+            None => Err(Error::new(&format!("unknown lint rule '{}'", rule))),
+        }
+    }
+
+    pub fn level(&self, rule: &str) -> Option<Level> {
+        self.levels.get(rule).copied()
+    }
+}
+
+// a problem a rule found on a line, which `// lint: allow(rule)` on that line or the one above silences
+#[derive(Clone, Debug, PartialEq)]
+pub struct Diagnostic {
+    pub rule: &'static str,
+    pub level: Level,
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let level = if self.level == Level::Deny { "error" } else { "warning" };
+        write!(f, "[line {}] {}: {} ({})", self.line, level, self.message, self.rule)
+    }
+}
+
+// the diagnostics for a program in line order, or the errors that stop it from parsing or resolving.
+// `globals` are the names defined before the program, like natives
+pub(crate) fn lint(source: &str, globals: &[String], config: &LintConfig) -> Result<Vec<Diagnostic>, Vec<Error>> {
+    let (program, lines) = parser::parse_with_lines(source)?;
+    let declarations = resolver::declarations(&program, globals)?;
+    let (starts, allowed) = read_source(source);
+
+    let mut linter = Linter { config, declarations, lines: lines.into_iter(), diagnostics: vec![] };
+    linter.block(&program);
+
+    let mut diagnostics: Vec<Diagnostic> = linter.diagnostics.into_iter()
+        .map(|diagnostic| Diagnostic { line: starts[diagnostic.line - 1], ..diagnostic })
+        .filter(|diagnostic| {
+            let allows = |line: usize| allowed.get(&line).is_some_and(|rules| rules.iter().any(|r| r == diagnostic.rule));
+            !allows(diagnostic.line) && !allows(diagnostic.line - 1)
+        })
+        .collect();
+    diagnostics.sort_by_key(|diagnostic| diagnostic.line);
+    Ok(diagnostics)
+}
+
+// the source line each of the parser's lines starts on, and the rules the comments on each line allow
+fn read_source(source: &str) -> (Vec<usize>, HashMap<usize, Vec<String>>) {
+    let mut starts = vec![];
+    let mut allowed: HashMap<usize, Vec<String>> = HashMap::new();
+    let mut line = 1;
+    let mut starting = true;
+    for token in syntax::tokens(source) {
+        for trivia in &token.leading {
+            if trivia.kind == TriviaKind::LineComment {
+                if let Some(rules) = allows(&trivia.text) {
+                    allowed.entry(line).or_default().extend(rules);
+                }
+            }
+            line += trivia.text.matches('\n').count();
+        }
+        if starting {
+            starts.push(line);
+        }
+        line += token.text.matches('\n').count();
+        starting = token.kind == Token::NewLine;
+    }
+    (starts, allowed)
+}
+
+// the rules a `// lint: allow(a, b)` comment names
+fn allows(comment: &str) -> Option<Vec<String>> {
+    let rules = comment.trim_start_matches('/').trim().strip_prefix("lint: allow(")?.strip_suffix(')')?;
+    Some(rules.split(',').map(|rule| rule.trim().to_string()).collect())
+}
+
+struct Linter<'a> {
+    config: &'a LintConfig,
+    declarations: Declarations,
+    // the parser's line for each statement, in the order the walk reaches them
+    lines: std::vec::IntoIter<usize>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Linter<'_> {
+    fn report(&mut self, rule: &'static str, line: usize, message: String) {
+        let level = self.config.level(rule).expect("rule");
+        if level != Level::Allow {
+            self.diagnostics.push(Diagnostic { rule, level, line, message });
+        }
+    }
+
+    fn block(&mut self, stmts: &[Stmt]) {
+        let mut exited = false;
+        let mut reported = false;
+        for stmt in stmts {
+            let line = self.lines.next().expect("line");
+            if exited && !reported {
+                self.report("unreachable-code", line, "code after a return, break or continue never runs".to_string());
+                reported = true;
+            }
+            self.stmt(stmt, line);
+            exited |= matches!(stmt, Stmt::Return { .. } | Stmt::Break { .. } | Stmt::Continue { .. });
+        }
+    }
+
+    // `what` names the block for the message, like "function 'f'"
+    fn body(&mut self, stmts: &[Stmt], what: &str, line: usize) {
+        if stmts.is_empty() {
+            self.report("empty-block", line, format!("{} has an empty body", what));
+        }
+        self.block(stmts);
+    }
+
+    fn stmt(&mut self, stmt: &Stmt, line: usize) {
+        match stmt {
+            Stmt::Let { name, expr, id, .. } => {
+                self.expr(expr, line);
+                self.declaration(name, (*id, 0), line);
+            }
+            Stmt::FunDeclaration { name, params, body, id, .. } => {
+                let function = ident(name);
+                if !is_camel_case(function) {
+                    self.report("function-name", line, format!("function '{}' should be named in camelCase", function));
+                }
+                self.declaration(name, (*id, 0), line);
+                self.params(params, *id, line);
+                self.body(body, &format!("function '{}'", function), line);
+            }
+            Stmt::If { condition, consequence, alternative, .. } => {
+                self.expr(condition, line);
+                if is_constant(condition) {
+                    self.report("constant-condition", line, "the condition of this 'if' never changes".to_string());
+                }
+                self.body(consequence, "'if'", line);
+                if let Some(alternative) = alternative {
+                    self.body(alternative, "'else'", line);
+                }
+            }
+            Stmt::For { name, value, iterable, body, id } => {
+                self.expr(iterable, line);
+                self.declaration(name, (*id, 0), line);
+                if let Some(value) = value {
+                    self.declaration(value, (*id, 1), line);
+                }
+                self.body(body, "'for'", line);
+            }
+            Stmt::While { condition, body, .. } => {
+                self.expr(condition, line);
+                self.body(body, "'while'", line);
+            }
+            Stmt::Struct { name, methods, .. } => {
+                self.type_name(name, "struct", line);
+                for method in methods {
+                    let line = self.lines.next().expect("line");
+                    self.stmt(method, line);
+                }
+            }
+            Stmt::Enum { name, .. } => self.type_name(name, "enum", line),
+            Stmt::Protocol { name, .. } => self.type_name(name, "protocol", line),
+            Stmt::Expression { expr, .. } | Stmt::Print { expr, .. } | Stmt::Return { expr: Some(expr), .. } => self.expr(expr, line),
+            Stmt::Return { expr: None, .. } | Stmt::Break { .. } | Stmt::Continue { .. } => (),
+        }
+    }
+
+    // the parameters of the function or lambda `function`
+    fn params(&mut self, params: &[Param], function: NodeId, line: usize) {
+        for (i, param) in params.iter().enumerate() {
+            self.declaration(&param.name, (function, i + 1), line);
+        }
+    }
+
+    fn declaration(&mut self, name: &Token, id: DeclarationId, line: usize) {
+        let Some(declaration) = self.declarations.get(&id).cloned() else { return };
+        let name = ident(name);
+        if declaration.shadows {
+            self.report("shadowed-binding", line, format!("'{}' shadows an earlier declaration", name));
+        }
+        // a leading underscore marks a name as unused on purpose
+        if declaration.used || name.starts_with('_') {
+            return;
+        }
+        match declaration.kind {
+            DeclarationKind::Local => self.report("unused-variable", line, format!("variable '{}' is never used", name)),
+            DeclarationKind::Parameter => self.report("unused-parameter", line, format!("parameter '{}' is never used", name)),
+            DeclarationKind::Function => self.report("unused-function", line, format!("function '{}' is never used", name)),
+            DeclarationKind::Global => (),
+        }
+    }
+
+    fn type_name(&mut self, name: &Token, what: &str, line: usize) {
+        let name = ident(name);
+        if !is_pascal_case(name) {
+            self.report("type-name", line, format!("{} '{}' should be named in PascalCase", what, name));
+        }
+    }
+
+    // the statements of lambdas are numbered where the parser reached them, so children go in source order
+    fn expr(&mut self, expr: &Expr, line: usize) {
+        match expr {
+            Expr::Binary { op, left, right, .. } => {
+                let comparison = matches!(op, Token::EqEq | Token::BangEq | Token::Lt | Token::LtEq | Token::Gt | Token::GtEq);
+                if comparison && same(left, right) && !calls(left) {
+                    self.report("self-comparison", line, format!("'{}' is compared to itself", left.string()));
+                }
+                self.expr(left, line);
+                self.expr(right, line);
+            }
+            Expr::Unary { right: expr, .. } | Expr::Grouping { expr, .. } | Expr::Assign { expr, .. } | Expr::Get { object: expr, .. } => {
+                self.expr(expr, line);
+            }
+            Expr::Array { elements, .. } | Expr::Interpolation { parts: elements, .. } => {
+                for element in elements {
+                    self.expr(element, line);
+                }
+            }
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.expr(key, line);
+                    self.expr(value, line);
+                }
+            }
+            Expr::Index { object, index, .. } => {
+                self.expr(object, line);
+                self.expr(index, line);
+            }
+            Expr::Set { object, expr, .. } => {
+                self.expr(object, line);
+                self.expr(expr, line);
+            }
+            Expr::SetIndex { object, index, expr, .. } => {
+                self.expr(object, line);
+                self.expr(index, line);
+                self.expr(expr, line);
+            }
+            Expr::CompoundAssign { target, expr, .. } => {
+                self.expr(target, line);
+                self.expr(expr, line);
+            }
+            Expr::Range { start, end, .. } => {
+                self.expr(start, line);
+                self.expr(end, line);
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee, line);
+                for arg in args {
+                    self.expr(arg, line);
+                }
+            }
+            Expr::Lambda { params, body, id, .. } => {
+                self.params(params, *id, line);
+                self.body(body, "lambda", line);
+            }
+            Expr::Int { .. } | Expr::BigInt { .. } | Expr::Float { .. } | Expr::String { .. } | Expr::Bool { .. } | Expr::Nil { .. }
+            | Expr::Name { .. } | Expr::SelfRef { .. } | Expr::ImplicitMember { .. } => (),
+        }
+    }
+}
+
+fn ident(token: &Token) -> &str {
+    match token {
+        Token::Ident(name) => name,
+        _ => unreachable!(),
+    }
+}
+
+// whether two expressions are written the same way, whatever ids the parser gave them
+fn same(a: &Expr, b: &Expr) -> bool {
+    match (a, b) {
+        (Expr::Name { val: a, .. }, Expr::Name { val: b, .. }) | (Expr::String { val: a, .. }, Expr::String { val: b, .. })
+            | (Expr::BigInt { val: a, .. }, Expr::BigInt { val: b, .. }) => a == b,
+        (Expr::Int { val: a, .. }, Expr::Int { val: b, .. }) => a == b,
+        (Expr::Float { val: a, .. }, Expr::Float { val: b, .. }) => a == b,
+        (Expr::Bool { val: a, .. }, Expr::Bool { val: b, .. }) => a == b,
+        (Expr::Nil { .. }, Expr::Nil { .. }) | (Expr::SelfRef { .. }, Expr::SelfRef { .. }) => true,
+        (Expr::ImplicitMember { name, .. }, Expr::ImplicitMember { name: name2, .. }) => name == name2,
+        (Expr::Binary { op, left, right, .. }, Expr::Binary { op: op2, left: left2, right: right2, .. }) => {
+            op == op2 && same(left, left2) && same(right, right2)
+        }
+        (Expr::Unary { op, right, .. }, Expr::Unary { op: op2, right: right2, .. }) => op == op2 && same(right, right2),
+        (Expr::Grouping { expr, .. }, Expr::Grouping { expr: expr2, .. }) => same(expr, expr2),
+        (Expr::Get { object, name, .. }, Expr::Get { object: object2, name: name2, .. }) => name == name2 && same(object, object2),
+        (Expr::Index { object, index, .. }, Expr::Index { object: object2, index: index2, .. }) => {
+            same(object, object2) && same(index, index2)
+        }
+        (Expr::Array { elements, .. }, Expr::Array { elements: elements2, .. }) => {
+            elements.len() == elements2.len() && elements.iter().zip(elements2).all(|(a, b)| same(a, b))
+        }
+        _ => false,
+    }
+}
+
+// whether evaluating an expression twice could give different values
+fn calls(expr: &Expr) -> bool {
+    match expr {
+        Expr::Call { .. } | Expr::Lambda { .. } => true,
+        Expr::Binary { left, right, .. } => calls(left) || calls(right),
+        Expr::Unary { right, .. } => calls(right),
+        Expr::Grouping { expr, .. } | Expr::Get { object: expr, .. } => calls(expr),
+        Expr::Index { object, index, .. } => calls(object) || calls(index),
+        Expr::Interpolation { parts, .. } => parts.iter().any(calls),
+        _ => false,
+    }
+}
+
+// made of literals alone, so it's the same every time
+fn is_constant(expr: &Expr) -> bool {
+    match expr {
+        Expr::Int { .. } | Expr::BigInt { .. } | Expr::Float { .. } | Expr::String { .. } | Expr::Bool { .. } | Expr::Nil { .. } => true,
+        Expr::Grouping { expr, .. } | Expr::Unary { right: expr, .. } => is_constant(expr),
+        Expr::Binary { left, right, .. } => is_constant(left) && is_constant(right),
+        _ => false,
+    }
+}
+
+// leading underscores aside, camelCase starts lowercase and PascalCase uppercase, and neither has underscores
+fn is_camel_case(name: &str) -> bool {
+    let name = name.trim_start_matches('_');
+    !name.contains('_') && !name.starts_with(char::is_uppercase)
+}
+
+fn is_pascal_case(name: &str) -> bool {
+    !name.contains('_') && name.starts_with(char::is_uppercase)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(source: &str) -> Vec<(usize, &'static str)> {
+        lint(source, &["len".to_string()], &LintConfig::default())
+            .unwrap()
+            .into_iter()
+            .map(|diagnostic| (diagnostic.line, diagnostic.rule))
+            .collect()
+    }
+
+    #[test]
+    fn test_unused() {
+        let source = r#"
+fun used(a, b, _c)
+    let x = a
+    let y = 1
+    y = 2
+    for i, v in [1, 2]
+        print v
+    end
+    return x
+end
+fun unused()
+    fun helper() end
+end
+let global = used(1, 2, 3)
+let f = fun(z) 1 end
+print f(1)
+"#;
+        assert_eq!(check(source), vec![
+            (2, "unused-parameter"),
+            (4, "unused-variable"),
+            (6, "unused-variable"),
+            (11, "unused-function"),
+            (12, "unused-function"),
+            (12, "empty-block"),
+            (15, "unused-parameter"),
+        ]);
+    }
+
+    #[test]
+    fn test_shadowing() {
+        let source = r#"
+let name = "goku"
+fun greet(name)
+    let level = 1
+    if level > 0
+        let level = 2
+        print level
+    end
+    print name + show(level)
+end
+fun show(x)
+    return fun(x) x end(x)
+end
+print greet
+"#;
+        assert_eq!(check(source), vec![(3, "shadowed-binding"), (6, "shadowed-binding"), (12, "shadowed-binding")]);
+    }
+
+    #[test]
+    fn test_unreachable_code() {
+        let source = r#"
+fun f(x)
+    while x
+        break
+        print 1
+        print 2
+    end
+    return x
+    print x
+end
+print f(1)
+"#;
+        assert_eq!(check(source), vec![(5, "unreachable-code"), (9, "unreachable-code")]);
+    }
+
+    #[test]
+    fn test_comparisons_and_conditions() {
+        let source = r#"
+let a = [1]
+print a[0] == a[0]
+print len(a) == len(a)
+if (1 + 2 > 3)
+    print a != a
+else
+end
+if !false
+    print 1
+end
+while true
+    print 2
+end
+"#;
+        assert_eq!(check(source), vec![
+            (3, "self-comparison"),
+            (5, "constant-condition"),
+            (5, "empty-block"),
+            (6, "self-comparison"),
+            (9, "constant-condition"),
+        ]);
+    }
+
+    #[test]
+    fn test_naming() {
+        let source = r#"
+struct saiyan
+    fun Fight() end
+end
+enum Power_Level
+    low, high
+end
+protocol Warrior
+end
+fun power_up() end
+fun _private() end
+print saiyan
+print power_up
+print _private
+"#;
+        assert_eq!(check(source), vec![
+            (2, "type-name"),
+            (3, "function-name"),
+            (3, "empty-block"),
+            (5, "type-name"),
+            (10, "function-name"),
+            (10, "empty-block"),
+            (11, "empty-block"),
+        ]);
+    }
+
+    #[test]
+    fn test_lines_follow_the_source() {
+        // multiline strings and comments don't make newline tokens, but the reported lines still match the source
+        let source = "let s = \"\"\"\na\nb\"\"\"\n/* c\n */\nfun f()\n    let x = 1\nend\nprint f\n";
+        assert_eq!(check(source), vec![(7, "unused-variable")]);
+    }
+
+    #[test]
+    fn test_suppression() {
+        let source = r#"
+fun f()
+    let a = 1 // lint: allow(unused-variable)
+    // lint: allow(shadowed-binding, unused-variable)
+    let f = 2
+    let b = 3 // lint: allow(empty-block)
+end
+print f
+"#;
+        assert_eq!(check(source), vec![(6, "unused-variable")]);
+    }
+
+    #[test]
+    fn test_config() {
+        let source = "fun f(a)\nend\nprint f\n";
+        let mut config = LintConfig::default();
+        config.set("empty-block", Level::Allow).unwrap();
+        config.set("unused-parameter", Level::Deny).unwrap();
+        let diagnostics = lint(source, &[], &config).unwrap();
+        assert_eq!(diagnostics, vec![Diagnostic {
+            rule: "unused-parameter",
+            level: Level::Deny,
+            line: 1,
+            message: "parameter 'a' is never used".to_string(),
+        }]);
+        assert_eq!(diagnostics[0].to_string(), "[line 1] error: parameter 'a' is never used (unused-parameter)");
+        assert_eq!(config.set("no-such-rule", Level::Warn), Err(Error::new("unknown lint rule 'no-such-rule'")));
+    }
+
+    #[test]
+    fn test_unresolved() {
+        let errors = lint("print nope\n", &[], &LintConfig::default()).unwrap_err();
+        assert_eq!(errors, vec![Error::new("undefined variable 'nope'")]);
+    }
+}
@@ -1,13 +1,140 @@
-mod token;
-mod lexer;
-mod parser;
-mod ast;
-mod compiler;
-mod vm;
-mod opcodes;
-mod visitor;
-mod value;
+use std::env;
+use std::fs;
+use std::process;
+use frieza_compiler::{format_source, Capabilities, Engine, Error, Level, LintConfig};
 
 fn main() {
-    println!("Hello, world!");
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("run") => match args.get(2) {
+            Some(path) => run_file(path, script_args(&args[3..])),
+            None => usage(),
+        },
+        Some("fmt") => format_files(&args[2..]),
+        Some("lint") => lint_files(&args[2..]),
+        Some(path) => run_file(path, script_args(&args[2..])),
+        None => usage(),
+    }
+}
+
+fn usage() -> ! {
+    eprintln!("usage: frieza [run] <file> [-- args...]");
+    eprintln!("       frieza fmt [--check] <file>...");
+    eprintln!("       frieza lint [--allow|--warn|--deny <rule>]... <file>...");
+    process::exit(64);
+}
+
+// the arguments after `--`, which the script reads with `args()`
+fn script_args(rest: &[String]) -> Vec<String> {
+    match rest.split_first() {
+        None => vec![],
+        Some((dashes, args)) if dashes == "--" => args.to_vec(),
+        Some(_) => usage(),
+    }
+}
+
+fn read_file(path: &str) -> String {
+    fs::read_to_string(path).unwrap_or_else(|e| {
+        eprintln!("could not read {}: {}", path, e);
+        process::exit(74);
+    })
+}
+
+fn run_file(path: &str, args: Vec<String>) {
+    let source = read_file(path);
+
+    // scripts run from the command line can use files, stdin and the environment
+    let mut engine = Engine::with_capabilities(Capabilities { io: true });
+    engine.set_args(args);
+    let script = engine.compile(&source).unwrap_or_else(|errors| report(errors));
+    if let Err(error) = engine.execute::<()>(&script) {
+        eprintln!("{}", error);
+        process::exit(70);
+    }
+}
+
+// rewrites each file in place, or with `--check` lists the files that aren't formatted and fails if there are any
+fn format_files(args: &[String]) {
+    let check = args.first().is_some_and(|arg| arg == "--check");
+    let paths = if check { &args[1..] } else { args };
+    if paths.is_empty() {
+        usage();
+    }
+
+    // every file is formatted before any is written, so a file that doesn't parse leaves them all alone
+    let mut changed = vec![];
+    let mut unparseable = false;
+    for path in paths {
+        let source = read_file(path);
+        match format_source(&source) {
+            Ok(formatted) if formatted != source => changed.push((path, formatted)),
+            Ok(_) => (),
+            Err(errors) => {
+                for error in errors {
+                    eprintln!("{}: {}", path, error);
+                }
+                unparseable = true;
+            }
+        }
+    }
+    if unparseable {
+        process::exit(65);
+    }
+
+    let mut unformatted = false;
+    for (path, formatted) in changed {
+        if check {
+            println!("{}", path);
+            unformatted = true;
+        } else if let Err(e) = fs::write(path, formatted) {
+            eprintln!("could not write {}: {}", path, e);
+            process::exit(74);
+        }
+    }
+    if unformatted {
+        process::exit(1);
+    }
+}
+
+// prints what the lint rules find in each file, and fails if a denied rule found anything
+fn lint_files(args: &[String]) {
+    let mut config = LintConfig::default();
+    let mut rest = args;
+    while let [flag, rule, tail @ ..] = rest {
+        let level = match flag.as_str() {
+            "--allow" => Level::Allow,
+            "--warn" => Level::Warn,
+            "--deny" => Level::Deny,
+            _ => break,
+        };
+        if let Err(error) = config.set(rule, level) {
+            eprintln!("{}", error);
+            usage();
+        }
+        rest = tail;
+    }
+    if rest.is_empty() || rest[0].starts_with("--") {
+        usage();
+    }
+
+    let engine = Engine::new();
+    let mut denied = false;
+    for path in rest {
+        let source = read_file(path);
+        let diagnostics = engine.lint(&source, &config).unwrap_or_else(|errors| report(errors));
+        for diagnostic in diagnostics {
+            println!("{}: {}", path, diagnostic);
+            denied |= diagnostic.level == Level::Deny;
+        }
+    }
+    if denied {
+        process::exit(1);
+    }
+}
+
+fn report(errors: Vec<Error>) -> ! {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    process::exit(65);
 }
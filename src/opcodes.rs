@@ -7,6 +7,61 @@ pub(crate) enum OpCode {
     Multiply,
     Divide,
     Float,
+    Nil,
+    True,
+    False,
+    String,
+    Pop,
+    Print,
+    DefineGlobal,
+    GetGlobal,
+    SetGlobal,
+    GetLocal,
+    SetLocal,
+    Not,
+    Loop,
+    Array,
+    Range,
+    RangeInclusive,
+    ForNext,
+    Jump,
+    JumpIfFalse,
+    Equal,
+    Greater,
+    Less,
+    Function,
+    Call,
+    Struct,
+    GetField,
+    SetField,
+    Enum,
+    Protocol,
+    Is,
+    Closure,
+    GetUpvalue,
+    SetUpvalue,
+    CloseUpvalue,
+    Map,
+    GetIndex,
+    SetIndex,
+    ForNextPair,
+    Modulo,
+    Power,
+    FloorDivide,
+    BitAnd,
+    BitOr,
+    BitXor,
+    BitNot,
+    ShiftLeft,
+    ShiftRight,
+    GreaterEqual,
+    LessEqual,
+    BigInt,
+    // copies the value on top of the stack, or the top two values
+    Dup,
+    DupTwo,
+    // replaces the value on top of the stack with its string, for string interpolation
+    Stringify,
 }
 
 impl TryInto<OpCode> for u8 {
@@ -22,7 +77,60 @@ impl TryInto<OpCode> for u8 {
             5 => Ok(OpCode::Multiply),
             6 => Ok(OpCode::Divide),
             7 => Ok(OpCode::Float),
+            8 => Ok(OpCode::Nil),
+            9 => Ok(OpCode::True),
+            10 => Ok(OpCode::False),
+            11 => Ok(OpCode::String),
+            12 => Ok(OpCode::Pop),
+            13 => Ok(OpCode::Print),
+            14 => Ok(OpCode::DefineGlobal),
+            15 => Ok(OpCode::GetGlobal),
+            16 => Ok(OpCode::SetGlobal),
+            17 => Ok(OpCode::GetLocal),
+            18 => Ok(OpCode::SetLocal),
+            19 => Ok(OpCode::Not),
+            20 => Ok(OpCode::Loop),
+            21 => Ok(OpCode::Array),
+            22 => Ok(OpCode::Range),
+            23 => Ok(OpCode::RangeInclusive),
+            24 => Ok(OpCode::ForNext),
+            25 => Ok(OpCode::Jump),
+            26 => Ok(OpCode::JumpIfFalse),
+            27 => Ok(OpCode::Equal),
+            28 => Ok(OpCode::Greater),
+            29 => Ok(OpCode::Less),
+            30 => Ok(OpCode::Function),
+            31 => Ok(OpCode::Call),
+            32 => Ok(OpCode::Struct),
+            33 => Ok(OpCode::GetField),
+            34 => Ok(OpCode::SetField),
+            35 => Ok(OpCode::Enum),
+            36 => Ok(OpCode::Protocol),
+            37 => Ok(OpCode::Is),
+            38 => Ok(OpCode::Closure),
+            39 => Ok(OpCode::GetUpvalue),
+            40 => Ok(OpCode::SetUpvalue),
+            41 => Ok(OpCode::CloseUpvalue),
+            42 => Ok(OpCode::Map),
+            43 => Ok(OpCode::GetIndex),
+            44 => Ok(OpCode::SetIndex),
+            45 => Ok(OpCode::ForNextPair),
+            46 => Ok(OpCode::Modulo),
+            47 => Ok(OpCode::Power),
+            48 => Ok(OpCode::FloorDivide),
+            49 => Ok(OpCode::BitAnd),
+            50 => Ok(OpCode::BitOr),
+            51 => Ok(OpCode::BitXor),
+            52 => Ok(OpCode::BitNot),
+            53 => Ok(OpCode::ShiftLeft),
+            54 => Ok(OpCode::ShiftRight),
+            55 => Ok(OpCode::GreaterEqual),
+            56 => Ok(OpCode::LessEqual),
+            57 => Ok(OpCode::BigInt),
+            58 => Ok(OpCode::Dup),
+            59 => Ok(OpCode::DupTwo),
+            60 => Ok(OpCode::Stringify),
             _ => Err(())
         }
     }
-}
\ No newline at end of file
+}
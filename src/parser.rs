@@ -1,34 +1,83 @@
+use crate::error::Error;
+use crate::lexer::{self, Spans};
 use crate::token::Token;
-use crate::ast::Expr;
+use crate::ast::{Expr, Field, NodeId, Param, Signature};
 use crate::ast::Expr::*;
 use crate::ast::Stmt;
 use crate::ast::Stmt::*;
 
 pub(crate) type Program = Vec<Stmt>;
 
+// the statements of a program that parse, along with where its nodes and its mistakes are in its tokens
+pub(crate) struct Parsed {
+    pub(crate) program: Program,
+    // the tokens each node was parsed from, indexed by its id
+    pub(crate) nodes: Vec<std::ops::Range<usize>>,
+    // the tokens of each statement that didn't parse, up to where parsing picked up again
+    pub(crate) skipped: Vec<std::ops::Range<usize>>,
+}
+
 struct Parser {
     tokens: Vec<Token>,
+    // the source text of each token, when the parser has it, so errors can point at the token they're about
+    spans: Spans,
     current: usize,
-    program: Program,
     line: usize,
+    // the line each statement starts on, in the order the statements start
+    lines: Vec<usize>,
+    // a statement that doesn't parse is skipped to the end of its line, so one pass reports every mistake
+    errors: Vec<Error>,
+    // the tokens each node was parsed from, indexed by its id
+    nodes: Vec<std::ops::Range<usize>>,
+    // the tokens of each statement that didn't parse, up to where parsing picked up again
+    skipped: Vec<std::ops::Range<usize>>,
 }
 
-pub(crate) fn parse(tokens: Vec<Token>) -> Program {
-    let mut parser = Parser::new(tokens);
+// scans and parses a program, with the errors of either giving the source they're about
+pub(crate) fn parse(source: &str) -> Result<Program, Vec<Error>> {
+    let (tokens, spans) = lexer::scan(source)?;
+    let mut parser = Parser::new(tokens, spans);
     parser.parse()
 }
 
+// the program and the line of each statement, for walking the program in the same order. lines count
+// newline tokens, so a multiline string or comment isn't counted
+pub(crate) fn parse_with_lines(source: &str) -> Result<(Program, Vec<usize>), Vec<Error>> {
+    let (tokens, spans) = lexer::scan(source)?;
+    let mut parser = Parser::new(tokens, spans);
+    let program = parser.parse()?;
+    Ok((program, parser.lines))
+}
+
+// parses tokens from `lexer::scan_lenient`, keeping whatever parses rather than stopping at errors, for tools
+// that work on the source as it is
+pub(crate) fn parse_lenient(tokens: Vec<Token>, spans: Spans) -> Parsed {
+    let mut parser = Parser::new(tokens, spans);
+    let program = parser.program();
+    Parsed { program, nodes: parser.nodes, skipped: parser.skipped }
+}
+
 impl Parser {
-    fn new(tokens: Vec<Token>) -> Self {
+    fn new(tokens: Vec<Token>, spans: Spans) -> Self {
         Parser {
             tokens,
-            program: vec![],
+            spans,
             current: 0,
             line: 1,
+            lines: vec![],
+            errors: vec![],
+            nodes: vec![],
+            skipped: vec![],
         }
     }
 
-    fn parse(&mut self) -> Program {
+    fn parse(&mut self) -> Result<Program, Vec<Error>> {
+        let program = self.program();
+        if self.errors.is_empty() { Ok(program) } else { Err(std::mem::take(&mut self.errors)) }
+    }
+
+    // the statements that parse, with their nodes numbered again in the order they start in the source
+    fn program(&mut self) -> Program {
         let mut program = vec![];
 
         while !self.is_at_end() {
@@ -37,53 +86,223 @@ impl Parser {
             }
         }
 
+        let mut numbering = Numbering { parsed: std::mem::take(&mut self.nodes), nodes: vec![] };
+        for stmt in &mut program {
+            numbering.stmt(stmt);
+        }
+        self.nodes = numbering.nodes;
         program
     }
 
     fn declaration(&mut self) -> Option<Stmt> {
         if self.check(vec![Token::NewLine, Token::Eof]) { return None }
-        if self.check(vec![Token::Fun]) { return Some(self.fun()); }
-        if self.check(vec![Token::Let]) { return Some(self.let_declaration()); }
-        Some(self.statement())
+        // a declaration's node starts at its doc comment, and a doc comment above anything else is ignored
+        let start = self.current;
+        let doc = self.doc();
+        match self.numbered(|parser| parser.declaration_with_doc(doc, start)) {
+            Ok(stmt) => stmt,
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                self.skipped.push(start..self.current);
+                None
+            }
+        }
     }
 
-    fn statement(&mut self) -> Stmt {
-        if self.check(vec![Token::Return]) { return self.return_stmt(); }
-        if self.check(vec![Token::Print]) { return self.print_stmt(); }
-        if self.check(vec![Token::If]) { return self.if_stmt(); }
-        self.expr_statement()
+    // the id of a node parsed from the tokens between `start` and the current token, leaving out a newline
+    // that ends a statement
+    fn id(&mut self, start: usize) -> NodeId {
+        let mut end = self.current;
+        while end > start + 1 && self.tokens[end - 1] == Token::NewLine {
+            end -= 1;
+        }
+        self.nodes.push(start..end);
+        self.nodes.len() - 1
     }
 
-    fn fun(&mut self) -> Stmt {
-        let token = self.advance();
-        match token {
-            Token::Ident(_) => {
-                let params = self.params();
-                let body = self.block();
+    // skips past the end of the line the error is on
+    fn synchronize(&mut self) {
+        while !self.is_at_end() && self.advance() != Token::NewLine {}
+    }
 
-                self.consume(Token::End);
-                return FunDeclaration { name: token, params, body }
-            }
-            _ => {
-                todo!("error")
-            },
+    // numbers a statement ahead of the statements inside it
+    fn numbered(&mut self, parse: impl FnOnce(&mut Self) -> Result<Option<Stmt>, Error>) -> Result<Option<Stmt>, Error> {
+        let (index, line) = (self.lines.len(), self.line);
+        let Some(stmt) = parse(self)? else { return Ok(None) };
+        self.lines.insert(index, line);
+        Ok(Some(stmt))
+    }
+
+    fn declaration_with_doc(&mut self, doc: Option<std::string::String>, start: usize) -> Result<Option<Stmt>, Error> {
+        // `fun(` starts an anonymous function rather than a declaration
+        if self.peek() == Token::Fun && self.peek_next() != Token::LParen {
+            self.advance();
+            return self.fun(doc, start).map(Some);
+        }
+        if self.check(vec![Token::Let]) { return self.let_declaration(start).map(Some); }
+        if self.check(vec![Token::Struct]) { return self.struct_declaration(doc, start).map(Some); }
+        if self.check(vec![Token::Enum]) { return self.enum_declaration(doc, start).map(Some); }
+        if self.check(vec![Token::Protocol]) { return self.protocol_declaration(doc, start).map(Some); }
+        // a doc comment followed by a blank line or at the end of a block. a stray `end` or `else` with no
+        // doc above it is an error instead, or parsing would never get past it
+        if matches!(self.peek(), Token::NewLine | Token::Eof) { return Ok(None) }
+        if doc.is_some() && matches!(self.peek(), Token::End | Token::Else) { return Ok(None) }
+        self.statement(start).map(Some)
+    }
+
+    // the lines of the `///` comments at the current token, joined with newlines
+    fn doc(&mut self) -> Option<std::string::String> {
+        let mut lines = vec![];
+        while let Token::Doc(line) = self.peek() {
+            self.advance();
+            self.check(vec![Token::NewLine]);
+            lines.push(line);
         }
+        if lines.is_empty() { None } else { Some(lines.join("\n")) }
     }
 
-    fn params(&mut self) -> Vec<Token> {
-        self.consume(Token::LParen);
+    fn statement(&mut self, start: usize) -> Result<Stmt, Error> {
+        if self.check(vec![Token::Return]) { return self.return_stmt(start); }
+        if self.check(vec![Token::Print]) { return self.print_stmt(start); }
+        if self.check(vec![Token::If]) { return self.if_stmt(start); }
+        if self.check(vec![Token::For]) { return self.for_stmt(start); }
+        if self.check(vec![Token::While]) { return self.while_stmt(start); }
+        if self.check(vec![Token::Break]) { return Ok(Break { id: self.id(start) }); }
+        if self.check(vec![Token::Continue]) { return Ok(Continue { id: self.id(start) }); }
+        self.expr_statement(start)
+    }
+
+    fn fun(&mut self, doc: Option<std::string::String>, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("function name")?;
+        let params = self.params()?;
+        let return_type = self.return_type()?;
+        let body = self.block();
+
+        self.consume(Token::End)?;
+        Ok(FunDeclaration { name, params, return_type, body, doc, id: self.id(start) })
+    }
+
+    fn lambda(&mut self, start: usize) -> Result<Expr, Error> {
+        let params = self.params()?;
+        let return_type = self.return_type()?;
+        let mut body = self.block();
+        self.consume(Token::End)?;
+
+        // the value of a trailing expression is the lambda's result, in the statement's place
+        if let Some(Expression { expr, id }) = body.last() {
+            let (expr, id) = (expr.clone(), *id);
+            body.pop();
+            body.push(Return { expr: Some(expr), id });
+        }
+        Ok(Expr::Lambda { params, return_type, body, id: self.id(start) })
+    }
+
+    fn params(&mut self) -> Result<Vec<Param>, Error> {
+        // `fun goSuperSaiyan` may leave off the parens when there are no params
+        if self.peek() == Token::NewLine || self.peek() == Token::Arrow { return Ok(vec![]) }
+        self.consume(Token::LParen)?;
         let mut params = vec![];
         while self.peek() != Token::RParen {
-            let token = self.advance();
-            match token {
-                Token::Ident(_) => params.push(token.clone()),
-                Token::Comma => (),
-                _ => todo!("error"),
+            if self.check(vec![Token::Comma]) { continue }
+            let name = self.ident("parameter name")?;
+            let type_name = if self.check(vec![Token::Colon]) { Some(self.type_name()?) } else { None };
+            params.push(Param { name, type_name })
+        }
+        self.consume(Token::RParen)?;
+
+        Ok(params)
+    }
+
+    fn return_type(&mut self) -> Result<Option<Token>, Error> {
+        if self.check(vec![Token::Arrow]) { Ok(Some(self.type_name()?)) } else { Ok(None) }
+    }
+
+    fn struct_declaration(&mut self, doc: Option<std::string::String>, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("struct name")?;
+
+        let mut protocols = vec![];
+        if self.check(vec![Token::Colon]) {
+            protocols.push(self.protocol_name()?);
+            while self.check(vec![Token::Comma]) {
+                protocols.push(self.protocol_name()?);
             }
         }
-        self.consume(Token::RParen);
 
-        params
+        let mut fields = vec![];
+        let mut methods = vec![];
+        loop {
+            let method_start = self.current;
+            let method_doc = self.doc();
+            if self.check(vec![Token::NewLine]) { continue }
+            if self.check(vec![Token::End]) { break }
+            if self.check(vec![Token::Let]) {
+                fields.push(self.field()?);
+            } else if self.check(vec![Token::Fun]) {
+                let method = self.numbered(|parser| parser.fun(method_doc, method_start).map(Some))?;
+                methods.extend(method);
+            } else {
+                return Err(self.error(&format!("expected field or method in struct but found {:?}", self.peek())));
+            }
+        }
+
+        Ok(Stmt::Struct { name, protocols, fields, methods, doc, id: self.id(start) })
+    }
+
+    fn protocol_declaration(&mut self, doc: Option<std::string::String>, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("protocol name")?;
+
+        let mut methods = vec![];
+        loop {
+            let method_doc = self.doc();
+            if self.check(vec![Token::NewLine]) { continue }
+            if self.check(vec![Token::End]) { break }
+            self.consume(Token::Fun)?;
+            let name = self.ident("method name")?;
+            let params = self.params()?;
+            let return_type = self.return_type()?;
+            methods.push(Signature { name, params, return_type, doc: method_doc });
+        }
+
+        Ok(Stmt::Protocol { name, methods, doc, id: self.id(start) })
+    }
+
+    fn field(&mut self) -> Result<Field, Error> {
+        let name = self.ident("field name")?;
+        self.consume(Token::Colon)?;
+        let type_name = self.type_name()?;
+
+        Ok(Field { name, type_name })
+    }
+
+    fn type_name(&mut self) -> Result<Token, Error> {
+        self.ident("type")
+    }
+
+    // a protocol a struct conforms to is a name like any other, since its value is looked up at runtime
+    fn protocol_name(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        match self.type_name()? {
+            Token::Ident(val) => Ok(Name { val, id: self.id(start) }),
+            _ => unreachable!(),
+        }
+    }
+
+    fn enum_declaration(&mut self, doc: Option<std::string::String>, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("enum name")?;
+
+        // cases are separated by commas, newlines or both
+        let mut cases = vec![];
+        while !self.check(vec![Token::End]) {
+            match self.peek() {
+                Token::Ident(_) => cases.push(self.advance()),
+                // cases can't carry docs of their own
+                Token::Comma | Token::NewLine | Token::Doc(_) => { self.advance(); }
+                token => return Err(self.error(&format!("expected enum case but found {:?}", token))),
+            }
+        }
+
+        Ok(Stmt::Enum { name, cases, doc, id: self.id(start) })
     }
 
     fn block(&mut self) -> Vec<Stmt> {
@@ -96,191 +315,479 @@ impl Parser {
         stmts
     }
 
-    fn return_stmt(&mut self) -> Stmt {
-        let expr: Option<Expr>;
-        if self.peek() != Token::NewLine && !self.is_at_end() {
-            expr = Some(self.expr());
+    fn return_stmt(&mut self, start: usize) -> Result<Stmt, Error> {
+        let expr = if self.peek() != Token::NewLine && !self.is_at_end() {
+            Some(self.expr()?)
         } else {
-            expr = None;
-        }
-        self.advance();
-        if self.peek() == Token::NewLine {
-            self.advance();
-        }
+            None
+        };
+        // `fun(x) return x end` puts the end on the same line
+        self.check(vec![Token::NewLine]);
 
-        return Return { expr }
+        Ok(Return { expr, id: self.id(start) })
     }
 
-    fn if_stmt(&mut self) -> Stmt {
-        let condition = self.expr();
-        self.consume(Token::NewLine);
+    fn if_stmt(&mut self, start: usize) -> Result<Stmt, Error> {
+        let condition = self.expr()?;
+        self.consume(Token::NewLine)?;
         let consequence = self.block();
 
-        let alternative: Option<Vec<Stmt>>;
-        
-        if self.check(vec![Token::Else]) {
-            alternative = Some(self.block());
+        let alternative = if self.check(vec![Token::Else]) {
+            Some(self.block())
         } else {
-            alternative = None;
-        }
-        self.consume(Token::End);
+            None
+        };
+        self.consume(Token::End)?;
 
-        If { condition, consequence, alternative }
-            
+        Ok(If { condition, consequence, alternative, id: self.id(start) })
     }
 
-    fn print_stmt(&mut self) -> Stmt {
-        let expr = self.expr();
-        self.consume(Token::NewLine);
-        return Stmt::Print { expr }
+    fn for_stmt(&mut self, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("loop variable after 'for'")?;
+        let value = if self.check(vec![Token::Comma]) {
+            Some(self.ident("loop variable after ','")?)
+        } else {
+            None
+        };
+        self.consume(Token::In)?;
+        let iterable = self.expr()?;
+        self.consume(Token::NewLine)?;
+        let body = self.block();
+        self.consume(Token::End)?;
+
+        Ok(For { name, value, iterable, body, id: self.id(start) })
     }
 
-    fn let_declaration(&mut self) -> Stmt {
-        match self.peek() {
-            Token::Ident(_) => {
-                let name = self.advance();
-                self.consume(Token::Eq);
-                let expr = self.expr();
-                return Let { name, expr };
-            }
-            _ => todo!("error"),
-        }
+    fn while_stmt(&mut self, start: usize) -> Result<Stmt, Error> {
+        let condition = self.expr()?;
+        self.consume(Token::NewLine)?;
+        let body = self.block();
+        self.consume(Token::End)?;
+
+        Ok(While { condition, body, id: self.id(start) })
+    }
+
+    fn print_stmt(&mut self, start: usize) -> Result<Stmt, Error> {
+        let expr = self.expr()?;
+        self.consume(Token::NewLine)?;
+        Ok(Stmt::Print { expr, id: self.id(start) })
+    }
+
+    fn let_declaration(&mut self, start: usize) -> Result<Stmt, Error> {
+        let name = self.ident("variable name after 'let'")?;
+        let type_name = if self.check(vec![Token::Colon]) { Some(self.type_name()?) } else { None };
+        self.consume(Token::Eq)?;
+        let expr = self.expr()?;
+        Ok(Let { name, type_name, expr, id: self.id(start) })
+    }
+
+    fn expr_statement(&mut self, start: usize) -> Result<Stmt, Error> {
+        let expr = self.expr()?;
+        Ok(Expression { expr, id: self.id(start) })
     }
 
-    fn expr_statement(&mut self) -> Stmt {
-        let expr = self.expr();
-        Expression { expr }
+    fn expr(&mut self) -> Result<Expr, Error> {
+        self.assignment()
     }
 
-    fn expr(&mut self) -> Expr {
-        self.equality()
+    fn assignment(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let target = self.equality()?;
+
+        if self.check(vec![Token::Eq, Token::PlusEq, Token::MinusEq, Token::StarEq, Token::SlashEq]) {
+            let op = match self.previous() {
+                Token::PlusEq => Some(Token::Plus),
+                Token::MinusEq => Some(Token::Minus),
+                Token::StarEq => Some(Token::Star),
+                Token::SlashEq => Some(Token::Slash),
+                _ => None,
+            };
+            let expr = self.assignment()?;
+
+            if let Some(op) = op {
+                return match target {
+                    Name { .. } | Get { .. } | Index { .. } => {
+                        Ok(CompoundAssign { target: Box::new(target), op, expr: Box::new(expr), id: self.id(start) })
+                    }
+                    _ => Err(self.error(&format!("invalid assignment target {}", target.string()))),
+                };
+            }
+            return match target {
+                Name { val, .. } => Ok(Assign { name: Token::Ident(val), expr: Box::new(expr), id: self.id(start) }),
+                Get { object, name, .. } => Ok(Set { object, name, expr: Box::new(expr), id: self.id(start) }),
+                Index { object, index, .. } => Ok(SetIndex { object, index, expr: Box::new(expr), id: self.id(start) }),
+                _ => Err(self.error(&format!("invalid assignment target {}", target.string()))),
+            }
+        }
+
+        Ok(target)
     }
 
-    fn equality(&mut self) -> Expr {
-        let mut left = self.or();
+    fn equality(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.or()?;
 
         while self.check(vec![Token::EqEq, Token::BangEq]) {
             let op = self.previous();
-            let right = self.or();
-            left = Binary { left: Box::new(left), op, right: Box::new(right) };
+            let right = self.or()?;
+            left = Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) };
         }
 
-        left
+        Ok(left)
     }
 
-    fn or(&mut self) -> Expr {
-        let mut left = self.and();
+    fn or(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.and()?;
 
         while self.check(vec![Token::Or]) {
             let op = self.previous();
-            let right = self.and();
-            left = Binary { left: Box::new(left), op, right: Box::new(right)};
+            let right = self.and()?;
+            left = Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) };
         }
 
-        left
+        Ok(left)
     }
 
-    fn and(&mut self) -> Expr {
-        let mut left = self.comparison();
+    fn and(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.comparison()?;
 
         while self.check(vec![Token::And]) {
             let op = self.previous();
-            let right = self.comparison();
-            left = Binary { left: Box::new(left), op, right: Box::new(right) };
+            let right = self.comparison()?;
+            left = Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) };
+        }
+
+        Ok(left)
+    }
+
+    fn comparison(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.range()?;
+
+        while self.check(vec![Token::Gt, Token::GtEq, Token::Lt, Token::LtEq, Token::Is]) {
+            let op = self.previous();
+            let right = self.range()?;
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
+        }
+
+        Ok(left)
+    }
+
+    fn range(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let left = self.bit_or()?;
+
+        if self.check(vec![Token::DotDot, Token::DotDotEq]) {
+            let inclusive = self.previous() == Token::DotDotEq;
+            let end = self.bit_or()?;
+            return Ok(Range { start: Box::new(left), end: Box::new(end), inclusive, id: self.id(start) })
+        }
+
+        Ok(left)
+    }
+
+    fn bit_or(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.bit_xor()?;
+
+        while self.check(vec![Token::Pipe]) {
+            let op = self.previous();
+            let right = self.bit_xor()?;
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
         }
+        Ok(left)
+    }
+
+    fn bit_xor(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.bit_and()?;
 
-        left
+        while self.check(vec![Token::Caret]) {
+            let op = self.previous();
+            let right = self.bit_and()?;
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
+        }
+        Ok(left)
     }
 
-    fn comparison(&mut self) -> Expr {
-        let mut left = self.term();
+    fn bit_and(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.shift()?;
 
-        while self.check(vec![Token::Gt, Token::GtEq, Token::Lt, Token::LtEq]) {
+        while self.check(vec![Token::Amp]) {
             let op = self.previous();
-            let right = self.term();
-            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+            let right = self.shift()?;
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
         }
+        Ok(left)
+    }
+
+    fn shift(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.term()?;
 
-        left
+        while self.check(vec![Token::LtLt, Token::GtGt]) {
+            let op = self.previous();
+            let right = self.term()?;
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
+        }
+        Ok(left)
     }
 
-    fn term(&mut self) -> Expr {
-        let mut left = self.factor();
+    fn term(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.factor()?;
 
         while self.check(vec![Token::Plus, Token::Minus]) {
             let op = self.previous();
-            let right = self.factor();
+            let right = self.factor()?;
 
-            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
         }
-        return left
+        Ok(left)
     }
 
-    fn factor(&mut self) -> Expr {
-        let mut left = self.primary();
+    fn factor(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut left = self.unary()?;
 
-        while self.check(vec![Token::Star, Token::Slash]) {
+        while self.check(vec![Token::Star, Token::Slash, Token::Percent, Token::Div]) {
             let op = self.previous();
-            let right = self.primary();
+            let right = self.unary()?;
 
-            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right) }
+            left = Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) }
         }
-        return left
+        Ok(left)
     }
 
-    fn primary(&mut self) -> Expr {
-        match self.peek() {
+    fn unary(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        if self.check(vec![Token::Bang, Token::Minus, Token::Tilde]) {
+            let op = self.previous();
+            let right = self.unary()?;
+            return Ok(Unary { op, right: Box::new(right), id: self.id(start) })
+        }
+
+        self.power()
+    }
+
+    // binds tighter than a unary operator on its left, so `-2 ** 2` is -4, and groups to the right
+    fn power(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let left = self.call()?;
+
+        if self.check(vec![Token::StarStar]) {
+            let op = self.previous();
+            let right = self.unary()?;
+            return Ok(Expr::Binary { left: Box::new(left), op, right: Box::new(right), id: self.id(start) })
+        }
+        Ok(left)
+    }
+
+    fn call(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let mut expr = self.primary()?;
+
+        loop {
+            if self.check(vec![Token::LParen]) {
+                let mut args = vec![];
+                while self.peek() != Token::RParen && !self.is_at_end() {
+                    args.push(self.expr()?);
+                    if !self.check(vec![Token::Comma]) { break }
+                }
+                self.consume(Token::RParen)?;
+                expr = Call { callee: Box::new(expr), args, id: self.id(start) };
+            } else if self.check(vec![Token::Dot]) {
+                let name = self.ident("property name after '.'")?;
+                expr = Get { object: Box::new(expr), name, id: self.id(start) };
+            } else if self.check(vec![Token::LBracket]) {
+                let index = self.expr()?;
+                self.consume(Token::RBracket)?;
+                expr = Index { object: Box::new(expr), index: Box::new(index), id: self.id(start) };
+            } else {
+                break
+            }
+        }
+
+        Ok(expr)
+    }
+
+    fn primary(&mut self) -> Result<Expr, Error> {
+        let start = self.current;
+        let expr = match self.peek() {
             Token::Int(i) => {
                 self.advance();
-                Expr::Int { val: i }
+                Expr::Int { val: i, id: self.id(start) }
             },
             Token::Ident(s) => {
                 self.advance();
-                Name { val: s }
+                Name { val: s, id: self.id(start) }
             },
             Token::String(s) => {
                 self.advance();
-                String { val: s }
+                String { val: s, id: self.id(start) }
             },
+            Token::Interpolation(s) => {
+                self.advance();
+                self.interpolation(s, start)?
+            }
             Token::Float(f) => {
                 self.advance();
-                Float { val: f }
+                Float { val: f, id: self.id(start) }
+            }
+            Token::BigInt(digits) => {
+                self.advance();
+                BigInt { val: digits, id: self.id(start) }
             }
             Token::True => {
                 self.advance();
-                Bool { val: true }
+                Bool { val: true, id: self.id(start) }
             }
             Token::False => {
                 self.advance();
-                Bool { val: false}
+                Bool { val: false, id: self.id(start) }
+            }
+            Token::Nil => {
+                self.advance();
+                Nil { id: self.id(start) }
+            }
+            Token::SelfKw => {
+                self.advance();
+                SelfRef { id: self.id(start) }
+            }
+            Token::Dot => {
+                self.advance();
+                let name = self.ident("enum case after '.'")?;
+                ImplicitMember { name, id: self.id(start) }
+            }
+            Token::LParen => {
+                self.advance();
+                let expr = self.expr()?;
+                self.consume(Token::RParen)?;
+                Grouping { expr: Box::new(expr), id: self.id(start) }
+            }
+            Token::LBracket => {
+                self.advance();
+                self.array(start)?
             }
-            _ => {
-                println!("{:?}", self.peek());
-                todo!("error");
+            Token::LBrace => {
+                self.advance();
+                self.map(start)?
+            }
+            Token::Fun => {
+                self.advance();
+                self.lambda(start)?
             }
+            token => return Err(self.error(&format!("expected expression but found {:?}", token))),
+        };
+        Ok(expr)
+    }
+
+    fn array(&mut self, start: usize) -> Result<Expr, Error> {
+        let mut elements = vec![];
+        while self.peek() != Token::RBracket && !self.is_at_end() {
+            elements.push(self.expr()?);
+            if !self.check(vec![Token::Comma]) { break }
+        }
+        self.consume(Token::RBracket)?;
+
+        Ok(Array { elements, id: self.id(start) })
+    }
+
+    // `"a ${x} b"` is the parts `"a "`, `x` and `" b"`, with the empty text between two `${}`s left out
+    fn interpolation(&mut self, first: std::string::String, start: usize) -> Result<Expr, Error> {
+        let mut parts = vec![];
+        let mut text = first;
+        loop {
+            // each piece of text is the token just taken
+            if !text.is_empty() {
+                parts.push(String { val: text, id: self.id(self.current - 1) });
+            }
+            let expr = self.expr()?;
+            parts.push(expr);
+            match self.peek() {
+                Token::InterpolationMiddle(s) => text = s,
+                Token::InterpolationEnd(s) => {
+                    self.advance();
+                    if !s.is_empty() {
+                        parts.push(String { val: s, id: self.id(self.current - 1) });
+                    }
+                    break
+                }
+                token => return Err(self.error(&format!("expected '}}' after interpolated expression but found {:?}", token))),
+            }
+            self.advance();
+        }
+
+        Ok(Interpolation { parts, id: self.id(start) })
+    }
+
+    fn map(&mut self, start: usize) -> Result<Expr, Error> {
+        let mut entries = vec![];
+        self.skip_newlines();
+        while self.peek() != Token::RBrace && !self.is_at_end() {
+            let key = self.expr()?;
+            self.consume(Token::Colon)?;
+            let value = self.expr()?;
+            entries.push((key, value));
+            let more = self.check(vec![Token::Comma]);
+            self.skip_newlines();
+            if !more { break }
+        }
+        self.consume(Token::RBrace)?;
+
+        Ok(Map { entries, id: self.id(start) })
+    }
+
+    // entries of a map literal may go on their own lines
+    fn skip_newlines(&mut self) {
+        while self.check(vec![Token::NewLine]) {}
+    }
+
+    // an error about the current token
+    fn error(&self, message: &str) -> Error {
+        let message = format!("[line {}] {}", self.line, message);
+        match self.spans.get(self.current) {
+            Some(span) => Error::at(span.clone(), &message),
+            None => Error::new(&message),
+        }
+    }
+
+    // an identifier, such as the name a declaration introduces
+    fn ident(&mut self, what: &str) -> Result<Token, Error> {
+        match self.peek() {
+            Token::Ident(_) => Ok(self.advance()),
+            token => Err(self.error(&format!("expected {} but found {:?}", what, token))),
         }
     }
 
     fn is_at_end(&self) -> bool {
-        self.current >= self.tokens.len()
+        self.peek() == Token::Eof
     }
 
-    fn consume(&mut self, token: Token) -> Token {
+    fn consume(&mut self, token: Token) -> Result<Token, Error> {
         if token == self.peek() {
-            return self.advance();
+            return Ok(self.advance());
         }
-                println!("{:?}", token);
-                println!("{:?}", self.peek());
-        todo!("error")
+        Err(self.error(&format!("expected {:?} but found {:?}", token, self.peek())))
     }
 
+    // stays at the final Eof rather than running off the end
     fn advance(&mut self) -> Token {
+        let token = self.peek();
         if !self.is_at_end() { self.current += 1; }
-        self.previous()
+        if token == Token::NewLine { self.line += 1; }
+        token
     }
 
     fn peek(&self) -> Token {
-        self.tokens[self.current].clone()
+        self.tokens.get(self.current).cloned().unwrap_or(Token::Eof)
+    }
+
+    fn peek_next(&self) -> Token {
+        self.tokens.get(self.current + 1).cloned().unwrap_or(Token::Eof)
     }
 
     fn previous(&self) -> Token {
@@ -294,7 +801,120 @@ impl Parser {
                 return true
             }
         }
-        return false
+        false
+    }
+}
+
+// nodes are numbered as they finish parsing, which puts `a` ahead of `a + b` and leaves gaps for the targets
+// an assignment replaces. numbering them again, a node before the nodes inside it, gives ids in source order
+struct Numbering {
+    parsed: Vec<std::ops::Range<usize>>,
+    nodes: Vec<std::ops::Range<usize>>,
+}
+
+impl Numbering {
+    fn number(&mut self, id: &mut NodeId) {
+        self.nodes.push(self.parsed[*id].clone());
+        *id = self.nodes.len() - 1;
+    }
+
+    fn stmts(&mut self, stmts: &mut [Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &mut Stmt) {
+        match stmt {
+            FunDeclaration { body, id, .. } => {
+                self.number(id);
+                self.stmts(body);
+            }
+            Return { expr, id } => {
+                self.number(id);
+                if let Some(expr) = expr { self.expr(expr) }
+            }
+            Expression { expr, id } | Let { expr, id, .. } | Stmt::Print { expr, id } => {
+                self.number(id);
+                self.expr(expr);
+            }
+            If { condition, consequence, alternative, id } => {
+                self.number(id);
+                self.expr(condition);
+                self.stmts(consequence);
+                if let Some(alternative) = alternative { self.stmts(alternative) }
+            }
+            For { iterable, body, id, .. } => {
+                self.number(id);
+                self.expr(iterable);
+                self.stmts(body);
+            }
+            While { condition, body, id } => {
+                self.number(id);
+                self.expr(condition);
+                self.stmts(body);
+            }
+            Stmt::Struct { protocols, methods, id, .. } => {
+                self.number(id);
+                for protocol in protocols {
+                    self.expr(protocol);
+                }
+                self.stmts(methods);
+            }
+            Stmt::Protocol { id, .. } | Stmt::Enum { id, .. } | Break { id } | Continue { id } => self.number(id),
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match expr {
+            Binary { left, right, id, .. } => {
+                self.number(id);
+                self.expr(left);
+                self.expr(right);
+            }
+            Unary { right: expr, id, .. } | Grouping { expr, id } | Assign { expr, id, .. } | Get { object: expr, id, .. } => {
+                self.number(id);
+                self.expr(expr);
+            }
+            Expr::Int { id, .. } | Float { id, .. } | String { id, .. } | BigInt { id, .. } | Name { id, .. }
+                | Bool { id, .. } | Nil { id } | SelfRef { id } | ImplicitMember { id, .. } => self.number(id),
+            Interpolation { parts: exprs, id } | Array { elements: exprs, id } => {
+                self.number(id);
+                for expr in exprs {
+                    self.expr(expr);
+                }
+            }
+            CompoundAssign { target: left, expr: right, id, .. } | Index { object: left, index: right, id }
+                | Range { start: left, end: right, id, .. } | Set { object: left, expr: right, id, .. } => {
+                self.number(id);
+                self.expr(left);
+                self.expr(right);
+            }
+            Map { entries, id } => {
+                self.number(id);
+                for (key, value) in entries {
+                    self.expr(key);
+                    self.expr(value);
+                }
+            }
+            SetIndex { object, index, expr, id } => {
+                self.number(id);
+                self.expr(object);
+                self.expr(index);
+                self.expr(expr);
+            }
+            Call { callee, args, id } => {
+                self.number(id);
+                self.expr(callee);
+                for arg in args {
+                    self.expr(arg);
+                }
+            }
+            Lambda { body, id, .. } => {
+                self.number(id);
+                self.stmts(body);
+            }
+        }
     }
 }
 
@@ -302,33 +922,41 @@ impl Parser {
 mod tests {
     use crate::ast::Expr::{Binary, Int};
     use super::*;
+    use crate::fixtures;
     use crate::parser::parse;
-    use crate::lexer::scan;
+
+    fn errors(source: &str) -> Vec<std::string::String> {
+        parse(source).unwrap_err().iter().map(|error| error.message().to_string()).collect()
+    }
 
     #[test]
     fn test_fun() {
-        let s = r#"
-        fun add(x, y)
-            return x + y
-        end
-        "#;
-
-        let t = scan(s);
-        let p = parse(t);
+        let s = fixtures::parser::FUN;
+
+        let p = parse(s).unwrap();
         assert_eq!(p.len(), 1);
 
         let function = Stmt::FunDeclaration {
             name: Token::Ident("add".to_string()),
-            params: vec![Token::Ident("x".to_string()), Token::Ident("y".to_string())],
+            params: vec![
+                Param { name: Token::Ident("x".to_string()), type_name: None },
+                Param { name: Token::Ident("y".to_string()), type_name: None },
+            ],
+
+            return_type: None,
             body: vec![
                 Stmt::Return {
                     expr: Some(Expr::Binary {
-                        left: Box::new(Expr::Name { val: "x".to_string() }),
-                        right: Box::new(Expr::Name { val: "y".to_string() }),
+                        left: Box::new(Expr::Name { val: "x".to_string(), id: 3 }),
+                        right: Box::new(Expr::Name { val: "y".to_string(), id: 4 }),
                         op: Token::Plus,
-                    })
+                        id: 2,
+                    }),
+                    id: 1,
                 }
-            ]
+            ],
+            doc: None,
+            id: 0,
         };
 
         assert_eq!(p[0], function);
@@ -336,30 +964,29 @@ mod tests {
 
     #[test]
     fn test_let() {
-        let s = r#"
-        let juice = "juice"
-        let wrld = "wrld"
-        let helloWrld = juice + " " + wrld
-        "#;
+        let s = fixtures::parser::LET;
 
-        let t = scan(s);
-        let p = parse(t);
+        let p = parse(s).unwrap();
 
         assert_eq!(p.len(), 3);
 
-        let exp1 = Stmt::Let { name: Token::Ident("juice".to_string()), expr: String {val: "juice".to_string()}};
-        let exp2 = Stmt::Let { name: Token::Ident("wrld".to_string()), expr: String {val: "wrld".to_string()}};
+        let exp1 = Stmt::Let { name: Token::Ident("juice".to_string()), type_name: None, expr: String {val: "juice".to_string(), id: 1}, id: 0};
+        let exp2 = Stmt::Let { name: Token::Ident("wrld".to_string()), type_name: None, expr: String {val: "wrld".to_string(), id: 3}, id: 2};
         let exp3 = Stmt::Let {
             name: Token::Ident("helloWrld".to_string()),
+            type_name: None,
             expr: Binary {
                 left: Box::new(Binary {
-                    left: Box::new(Expr::Name { val: "juice".to_string() }),
+                    left: Box::new(Expr::Name { val: "juice".to_string(), id: 7 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::String { val: " ".to_string() }),
+                    right: Box::new(Expr::String { val: " ".to_string(), id: 8 }),
+                    id: 6,
                 }),
                 op: Token::Plus,
-                right: Box::new(Expr::Name { val: "wrld".to_string() })
-            }
+                right: Box::new(Expr::Name { val: "wrld".to_string(), id: 9 }),
+                id: 5,
+            },
+            id: 4,
         };
 
         assert_eq!(exp1, p[0]);
@@ -369,45 +996,49 @@ mod tests {
 
     #[test]
     fn test_expr_stmt() {
-        let s = r#"
-        1 + 1
-        x + y
-        x + 1
-        juice + " " + wrld
-        "#;
+        let s = fixtures::parser::EXPR_STMT;
 
         let exp = vec![
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 2 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Int { val: 1 }),
-                }
+                    right: Box::new(Expr::Int { val: 1, id: 3 }),
+                    id: 1,
+                },
+                id: 0,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Name { val: "x".to_string() }),
+                    left: Box::new(Expr::Name { val: "x".to_string(), id: 6 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Name { val: "y".to_string() }),
-                }
+                    right: Box::new(Expr::Name { val: "y".to_string(), id: 7 }),
+                    id: 5,
+                },
+                id: 4,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Name { val: "x".to_string() }),
+                    left: Box::new(Expr::Name { val: "x".to_string(), id: 10 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Int { val: 1 }),
-                }
+                    right: Box::new(Expr::Int { val: 1, id: 11 }),
+                    id: 9,
+                },
+                id: 8,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
                     left: Box::new(Binary {
-                        left: Box::new(Expr::Name { val: "juice".to_string() }),
+                        left: Box::new(Expr::Name { val: "juice".to_string(), id: 15 }),
                         op: Token::Plus,
-                        right: Box::new(Expr::String { val: " ".to_string() }),
+                        right: Box::new(Expr::String { val: " ".to_string(), id: 16 }),
+                        id: 14,
                     }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Name { val: "wrld".to_string() })
-                }
+                    right: Box::new(Expr::Name { val: "wrld".to_string(), id: 17 }),
+                    id: 13,
+                },
+                id: 12,
             },
         ];
 
@@ -416,97 +1047,108 @@ mod tests {
 
     #[test]
     fn test_binary() {
-        let s = r#"
-        1 + 2
-        1 - 2
-        1 * 2
-        1 / 2
-        1 + 2 + 3
-        1 + 2 * 3
-        1 < 2
-        1 > 2
-        1 <= 2
-        1 >= 2
-        "#;
+        let s = fixtures::parser::BINARY;
 
         let exp = vec![
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 2 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 3 }),
+                    id: 1,
+                },
+                id: 0,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 6 }),
                     op: Token::Minus,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 7 }),
+                    id: 5,
+                },
+                id: 4,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 10 }),
                     op: Token::Star,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 11 }),
+                    id: 9,
+                },
+                id: 8,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 14 }),
                     op: Token::Slash,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 15 }),
+                    id: 13,
+                },
+                id: 12,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
                     left: Box::new(Binary {
-                        left: Box::new(Expr::Int { val: 1 }),
+                        left: Box::new(Expr::Int { val: 1, id: 19 }),
                         op: Token::Plus,
-                        right: Box::new(Expr::Int { val: 2 }),
+                        right: Box::new(Expr::Int { val: 2, id: 20 }),
+                        id: 18,
                     }),
                     op: Token::Plus,
-                    right: Box::new(Expr::Int { val: 3 }),
-                }
+                    right: Box::new(Expr::Int { val: 3, id: 21 }),
+                    id: 17,
+                },
+                id: 16,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 24 }),
                     op: Token::Plus,
                     right: Box::new(Binary {
-                        left: Box::new(Expr::Int { val: 2 }),
+                        left: Box::new(Expr::Int { val: 2, id: 26 }),
                         op: Token::Star,
-                        right: Box::new(Expr::Int { val: 3 }),
+                        right: Box::new(Expr::Int { val: 3, id: 27 }),
+                        id: 25,
                     }),
-                }
+                    id: 23,
+                },
+                id: 22,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 30 }),
                     op: Token::Lt,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 31 }),
+                    id: 29,
+                },
+                id: 28,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 34 }),
                     op: Token::Gt,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 35 }),
+                    id: 33,
+                },
+                id: 32,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 38 }),
                     op: Token::LtEq,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 39 }),
+                    id: 37,
+                },
+                id: 36,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 1 }),
+                    left: Box::new(Expr::Int { val: 1, id: 42 }),
                     op: Token::GtEq,
-                    right: Box::new(Expr::Int { val: 2 }),
-                }
+                    right: Box::new(Expr::Int { val: 2, id: 43 }),
+                    id: 41,
+                },
+                id: 40,
             },
       ];
 
@@ -515,20 +1157,21 @@ mod tests {
 
     #[test]
     fn test_print() {
-        let s = r#"
-        print "free" + "lil" + "steve"
-        "#;
+        let s = fixtures::parser::PRINT;
 
         let exp = vec![Stmt::Print {
             expr: Expr::Binary {
                 left: Box::new(Expr::Binary {
-                    left: Box::new(Expr::String { val: "free".to_string() }),
+                    left: Box::new(Expr::String { val: "free".to_string(), id: 3 }),
                     op: Token::Plus,
-                    right: Box::new(Expr::String { val: "lil".to_string() }),
+                    right: Box::new(Expr::String { val: "lil".to_string(), id: 4 }),
+                    id: 2,
                 }),
                 op: Token::Plus,
-                right: Box::new(Expr::String { val: "steve".to_string() }),
-            }
+                right: Box::new(Expr::String { val: "steve".to_string(), id: 5 }),
+                id: 1,
+            },
+            id: 0,
         }];
 
         check_stmt(s, exp);
@@ -536,26 +1179,100 @@ mod tests {
 
     #[test]
     fn test_literal() {
-        let s = r#"
-        1
-        2
-        10.5
-        35.5353
-        "#;
+        let s = fixtures::parser::LITERAL;
 
         let exp = vec![
-            Expression {expr: Int {val: 1}},
-            Expression {expr: Int {val: 2}},
-            Expression {expr: Float {val: 10.5}},
-            Expression {expr: Float {val: 35.5353}}
+            Expression {expr: Int {val: 1, id: 1}, id: 0},
+            Expression {expr: Int {val: 2, id: 3}, id: 2},
+            Expression {expr: Float {val: 10.5, id: 5}, id: 4},
+            Expression {expr: Float {val: 35.5353, id: 7}, id: 6}
         ];
 
         check_stmt(s, exp);
     }
 
+    #[test]
+    fn test_operator_precedence() {
+        fn bin(left: Expr, op: Token, right: Expr, id: NodeId) -> Expr {
+            Binary { left: Box::new(left), op, right: Box::new(right), id }
+        }
+        let s = fixtures::parser::OPERATOR_PRECEDENCE;
+
+        check_stmt(s, vec![
+            Stmt::Expression {
+                expr: Expr::Unary {
+                    op: Token::Minus,
+                    right: Box::new(bin(Int { val: 2, id: 3 }, Token::StarStar, bin(Int { val: 3, id: 5 }, Token::StarStar, Int { val: 2, id: 6 }, 4), 2)),
+                    id: 1,
+                },
+                id: 0,
+            },
+            Stmt::Expression {
+                expr: bin(
+                    Int { val: 1, id: 9 },
+                    Token::Pipe,
+                    bin(
+                        Int { val: 2, id: 11 },
+                        Token::Caret,
+                        bin(
+                            Int { val: 3, id: 13 },
+                            Token::Amp,
+                            bin(
+                                Int { val: 4, id: 15 },
+                                Token::LtLt,
+                                bin(Int { val: 5, id: 17 }, Token::Plus, bin(Int { val: 6, id: 19 }, Token::Percent, Int { val: 7, id: 20 }, 18), 16),
+                                14,
+                            ),
+                            12,
+                        ),
+                        10,
+                    ),
+                    8,
+                ),
+                id: 7,
+            },
+            Stmt::Expression {
+                expr: bin(
+                    bin(Expr::Unary { op: Token::Tilde, right: Box::new(Int { val: 1, id: 25 }), id: 24 }, Token::Div, Int { val: 2, id: 26 }, 23),
+                    Token::GtGt,
+                    Int { val: 3, id: 27 },
+                    22,
+                ),
+                id: 21,
+            },
+        ]);
+    }
+
+    #[test]
+    fn test_doc_comments() {
+        let s = fixtures::parser::DOC_COMMENTS;
+
+        let docs: Vec<Option<std::string::String>> = parse(s).unwrap().into_iter().flat_map(|stmt| match stmt {
+            Stmt::Struct { methods, doc, .. } => {
+                let mut docs = vec![doc];
+                docs.extend(methods.into_iter().map(|m| match m {
+                    Stmt::FunDeclaration { doc, .. } => doc,
+                    _ => unreachable!(),
+                }));
+                docs
+            }
+            Stmt::Enum { doc, .. } | Stmt::FunDeclaration { doc, .. } => vec![doc],
+            Stmt::Protocol { methods, doc, .. } => vec![doc, methods[0].doc.clone()],
+            _ => vec![],
+        }).collect();
+
+        assert_eq!(docs, vec![
+            Some("a saiyan warrior\nfrom planet vegeta".to_string()),
+            Some("powers up by 1000".to_string()),
+            Some("forms a saiyan can take".to_string()),
+            Some("something that fights".to_string()),
+            Some("fights for a while".to_string()),
+            None,
+        ]);
+    }
+
     fn check_stmt(s: &str, exp: Vec<Stmt>) {
-        let t = scan(s);
-        let p = parse(t);
+        let p = parse(s).unwrap();
 
         assert_eq!(p.len(), exp.len());
 
@@ -566,23 +1283,19 @@ mod tests {
 
     #[test]
     fn test_if() {
-        let s = r#"
-        if 1 < 2 
-            print "yah"
-        else 
-            print "nah"
-        end
-        "#;
+        let s = fixtures::parser::IF;
 
         let exp = vec![
            Stmt::If { 
                 condition: Expr::Binary {
-                    left: Box::new(Expr::Int {val: 1}),
-                    right: Box::new(Expr::Int {val: 2}),
+                    left: Box::new(Expr::Int {val: 1, id: 2}),
+                    right: Box::new(Expr::Int {val: 2, id: 3}),
                     op: Token::Lt,
+                    id: 1,
                 },
-                consequence: vec![Stmt::Print {expr: Expr::String {val: "yah".to_string()}}],
-                alternative: Some(vec![Stmt::Print {expr: Expr::String {val: "nah".to_string()}}]),
+                consequence: vec![Stmt::Print {expr: Expr::String {val: "yah".to_string(), id: 5}, id: 4}],
+                alternative: Some(vec![Stmt::Print {expr: Expr::String {val: "nah".to_string(), id: 7}, id: 6}]),
+                id: 0,
            },
         ];
         check_stmt(s, exp);
@@ -590,17 +1303,17 @@ mod tests {
 
     #[test]
     fn test_and() {
-        let s = r#"
-        true and false
-        "#;
+        let s = fixtures::parser::AND;
 
         let exp = vec![
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Bool { val: true}),
-                    right: Box::new(Expr::Bool { val: false}),
+                    left: Box::new(Expr::Bool { val: true, id: 2}),
+                    right: Box::new(Expr::Bool { val: false, id: 3}),
                     op: Token::And,
-                }
+                    id: 1,
+                },
+                id: 0,
             }
         ];
 
@@ -609,56 +1322,477 @@ mod tests {
 
     #[test]
     fn test_or() {
-        let s = r#"
-        true or false
-        "#;
+        let s = fixtures::parser::OR;
 
         let exp = vec![
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Bool { val: true}),
-                    right: Box::new(Expr::Bool { val: false}),
+                    left: Box::new(Expr::Bool { val: true, id: 2}),
+                    right: Box::new(Expr::Bool { val: false, id: 3}),
                     op: Token::Or,
-                }
+                    id: 1,
+                },
+                id: 0,
             }
         ];
 
         check_stmt(s, exp);
     }
 
+    #[test]
+    fn test_for() {
+        let s = fixtures::parser::FOR;
+
+        let exp = vec![
+            Stmt::For {
+                name: Token::Ident("a".to_string()),
+                value: None,
+                iterable: Expr::Name { val: "arr".to_string(), id: 1 },
+                body: vec![Stmt::Print { expr: Expr::Name { val: "a".to_string(), id: 3 }, id: 2 }],
+                id: 0,
+            },
+            Stmt::For {
+                name: Token::Ident("i".to_string()),
+                value: None,
+                iterable: Expr::Range {
+                    start: Box::new(Expr::Int { val: 0, id: 6 }),
+                    end: Box::new(Expr::Int { val: 10, id: 7 }),
+                    inclusive: false,
+                    id: 5,
+                },
+                body: vec![Stmt::Expression {
+                    expr: Expr::Assign {
+                        id: 9,
+                        name: Token::Ident("total".to_string()),
+                        expr: Box::new(Expr::Binary {
+                            left: Box::new(Expr::Name { val: "total".to_string(), id: 11 }),
+                            op: Token::Plus,
+                            right: Box::new(Expr::Name { val: "i".to_string(), id: 12 }),
+                            id: 10,
+                        }),
+                    },
+                    id: 8,
+                }],
+                id: 4,
+            },
+            Stmt::For {
+                name: Token::Ident("i".to_string()),
+                value: None,
+                iterable: Expr::Range {
+                    start: Box::new(Expr::Int { val: 0, id: 15 }),
+                    end: Box::new(Expr::Binary {
+                        left: Box::new(Expr::Name { val: "n".to_string(), id: 17 }),
+                        op: Token::Plus,
+                        right: Box::new(Expr::Int { val: 1, id: 18 }),
+                        id: 16,
+                    }),
+                    inclusive: true,
+                    id: 14,
+                },
+                body: vec![],
+                id: 13,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_while() {
+        let s = fixtures::parser::WHILE;
+
+        let exp = vec![
+            Stmt::While {
+                condition: Expr::Binary {
+                    left: Box::new(Expr::Name { val: "i".to_string(), id: 2 }),
+                    op: Token::Lt,
+                    right: Box::new(Expr::Int { val: 10, id: 3 }),
+                    id: 1,
+                },
+                body: vec![
+                    Stmt::If {
+                        condition: Expr::Binary {
+                            left: Box::new(Expr::Name { val: "i".to_string(), id: 6 }),
+                            op: Token::EqEq,
+                            right: Box::new(Expr::Int { val: 5, id: 7 }),
+                            id: 5,
+                        },
+                        consequence: vec![Stmt::Break { id: 8 }],
+                        alternative: None,
+                        id: 4,
+                    },
+                    Stmt::Continue { id: 9 },
+                ],
+                id: 0,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_struct() {
+        let s = fixtures::parser::STRUCT;
+
+        let exp = vec![
+            Stmt::Struct {
+                name: Token::Ident("Saiyan".to_string()),
+                protocols: vec![],
+                fields: vec![
+                    Field { name: Token::Ident("name".to_string()), type_name: Token::Ident("string".to_string()) },
+                    Field { name: Token::Ident("powerLevel".to_string()), type_name: Token::Ident("int".to_string()) },
+                ],
+                methods: vec![
+                    Stmt::FunDeclaration {
+                        name: Token::Ident("powerUp".to_string()),
+                        params: vec![],
+                        return_type: None,
+                        body: vec![Stmt::Expression {
+                            expr: Expr::CompoundAssign {
+                                target: Box::new(Expr::Name { val: "powerLevel".to_string(), id: 4 }),
+                                op: Token::Plus,
+                                expr: Box::new(Expr::Int { val: 1000, id: 5 }),
+                                id: 3,
+                            },
+                            id: 2,
+                        }],
+                        doc: None,
+                        id: 1,
+                    },
+                    Stmt::FunDeclaration {
+                        name: Token::Ident("goSuperSaiyan".to_string()),
+                        params: vec![],
+                        return_type: None,
+                        body: vec![Stmt::Expression {
+                            expr: Expr::CompoundAssign {
+                                target: Box::new(Expr::Get {
+                                    object: Box::new(Expr::SelfRef { id: 10 }),
+                                    name: Token::Ident("powerLevel".to_string()),
+                                    id: 9,
+                                }),
+                                op: Token::Star,
+                                expr: Box::new(Expr::Int { val: 50, id: 11 }),
+                                id: 8,
+                            },
+                            id: 7,
+                        }],
+                        doc: None,
+                        id: 6,
+                    },
+                ],
+                doc: None,
+                id: 0,
+            },
+            Stmt::Let {
+                name: Token::Ident("goku".to_string()),
+                type_name: None,
+                expr: Expr::Call {
+                    callee: Box::new(Expr::Name { val: "Saiyan".to_string(), id: 14 }),
+                    args: vec![Expr::String { val: "goku".to_string(), id: 15 }, Expr::Int { val: 9000, id: 16 }],
+                    id: 13,
+                },
+                id: 12,
+            },
+            Stmt::Expression {
+                expr: Expr::Call {
+                    callee: Box::new(Expr::Get {
+                        object: Box::new(Expr::Name { val: "goku".to_string(), id: 20 }),
+                        name: Token::Ident("powerUp".to_string()),
+                        id: 19,
+                    }),
+                    args: vec![],
+                    id: 18,
+                },
+                id: 17,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_enum() {
+        let s = fixtures::parser::ENUM;
+
+        let exp = vec![
+            Stmt::Enum {
+                name: Token::Ident("Form".to_string()),
+                cases: vec![
+                    Token::Ident("base".to_string()),
+                    Token::Ident("superSaiyan".to_string()),
+                    Token::Ident("superSaiyanBlue".to_string()),
+                ],
+                doc: None,
+                id: 0,
+            },
+            Stmt::FunDeclaration {
+                name: Token::Ident("transform".to_string()),
+                params: vec![Param {
+                    name: Token::Ident("form".to_string()),
+                    type_name: Some(Token::Ident("Form".to_string())),
+                }],
+                return_type: None,
+                body: vec![Stmt::Expression {
+                    expr: Expr::Assign {
+                        id: 3,
+                        name: Token::Ident("currentForm".to_string()),
+                        expr: Box::new(Expr::ImplicitMember { name: Token::Ident("superSaiyan".to_string()), id: 4 }),
+                    },
+                    id: 2,
+                }],
+                doc: None,
+                id: 1,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_protocol() {
+        let s = fixtures::parser::PROTOCOL;
+
+        let exp = vec![
+            Stmt::Protocol {
+                name: Token::Ident("Warrior".to_string()),
+                methods: vec![
+                    Signature { name: Token::Ident("fight".to_string()), params: vec![], return_type: None, doc: None },
+                    Signature {
+                        name: Token::Ident("train".to_string()),
+                        params: vec![Param {
+                            name: Token::Ident("hours".to_string()),
+                            type_name: Some(Token::Ident("int".to_string())),
+                        }],
+                        return_type: None,
+                        doc: None,
+                    },
+                ],
+                doc: None,
+                id: 0,
+            },
+            Stmt::Struct {
+                name: Token::Ident("Saiyan".to_string()),
+                protocols: vec![
+                    Expr::Name { val: "Warrior".to_string(), id: 2 },
+                    Expr::Name { val: "Alien".to_string(), id: 3 },
+                ],
+                fields: vec![],
+                methods: vec![],
+                doc: None,
+                id: 1,
+            },
+            Stmt::Expression {
+                expr: Expr::Binary {
+                    left: Box::new(Expr::Name { val: "goku".to_string(), id: 6 }),
+                    op: Token::Is,
+                    right: Box::new(Expr::Name { val: "Warrior".to_string(), id: 7 }),
+                    id: 5,
+                },
+                id: 4,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_array() {
+        let s = fixtures::parser::ARRAY;
+
+        let exp = vec![
+            Stmt::Let {
+                name: Token::Ident("arr".to_string()),
+                type_name: None,
+                expr: Expr::Array {
+                    elements: vec![
+                        Expr::String { val: "free".to_string(), id: 2 },
+                        Expr::Int { val: 9, id: 3 },
+                        Expr::Array { elements: vec![], id: 4 },
+                    ],
+                    id: 1,
+                },
+                id: 0,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_lambda() {
+        let s = fixtures::parser::LAMBDA;
+
+        let exp = vec![
+            Stmt::Expression {
+                expr: Expr::Call {
+                    callee: Box::new(Expr::Lambda {
+                        params: vec![Param { name: Token::Ident("x".to_string()), type_name: None }],
+                        return_type: None,
+                        body: vec![Stmt::Return {
+                            expr: Some(Binary {
+                                left: Box::new(Expr::Name { val: "x".to_string(), id: 5 }),
+                                op: Token::Star,
+                                right: Box::new(Int { val: 2, id: 6 }),
+                                id: 4,
+                            }),
+                            id: 3,
+                        }],
+                        id: 2,
+                    }),
+                    args: vec![Int { val: 21, id: 7 }],
+                    id: 1,
+                },
+                id: 0,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_maps() {
+        let s = fixtures::parser::MAPS;
+
+        let exp = vec![
+            Stmt::Let {
+                name: Token::Ident("m".to_string()),
+                type_name: None,
+                expr: Expr::Map {
+                    entries: vec![
+                        (Expr::String { val: "a".to_string(), id: 2 }, Int { val: 1, id: 3 }),
+                        (Int { val: 2, id: 4 }, Expr::Name { val: "b".to_string(), id: 5 }),
+                    ],
+                    id: 1,
+                },
+                id: 0,
+            },
+            Stmt::Expression {
+                expr: Expr::SetIndex {
+                    object: Box::new(Expr::Name { val: "m".to_string(), id: 8 }),
+                    index: Box::new(Expr::String { val: "a".to_string(), id: 9 }),
+                    expr: Box::new(Expr::Index {
+                        object: Box::new(Expr::Name { val: "m".to_string(), id: 11 }),
+                        index: Box::new(Int { val: 2, id: 12 }),
+                        id: 10,
+                    }),
+                    id: 7,
+                },
+                id: 6,
+            },
+            Stmt::For {
+                name: Token::Ident("k".to_string()),
+                value: Some(Token::Ident("v".to_string())),
+                iterable: Expr::Name { val: "m".to_string(), id: 14 },
+                body: vec![],
+                id: 13,
+            },
+        ];
+
+        check_stmt(s, exp);
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let s = fixtures::parser::INTERPOLATION;
+
+        let exp = vec![
+            Stmt::Expression {
+                expr: Interpolation {
+                    parts: vec![
+                        Expr::String { val: "power: ".to_string(), id: 2 },
+                        Binary {
+                            left: Box::new(Expr::Name { val: "level".to_string(), id: 4 }),
+                            op: Token::Star,
+                            right: Box::new(Int { val: 2, id: 5 }),
+                            id: 3,
+                        },
+                        Expr::String { val: "!".to_string(), id: 6 },
+                    ],
+                    id: 1,
+                },
+                id: 0,
+            },
+            Stmt::Expression { expr: Interpolation { parts: vec![Expr::Name { val: "x".to_string(), id: 9 }], id: 8 }, id: 7 },
+        ];
+
+        check_stmt(s, exp);
+    }
+
     #[test]
     fn test_equality() {
-        let s = r#"
-        true == true
-        5 == 1
-        durk != keef
-        "#;
+        let s = fixtures::parser::EQUALITY;
 
         let exp = vec![
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Bool { val: true }),
+                    left: Box::new(Expr::Bool { val: true, id: 2 }),
                     op: Token::EqEq,
-                    right: Box::new(Expr::Bool { val: true }),
-                }
+                    right: Box::new(Expr::Bool { val: true, id: 3 }),
+                    id: 1,
+                },
+                id: 0,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Int { val: 5 }),
+                    left: Box::new(Expr::Int { val: 5, id: 6 }),
                     op: Token::EqEq,
-                    right: Box::new(Expr::Int { val: 1 }),
-                }
+                    right: Box::new(Expr::Int { val: 1, id: 7 }),
+                    id: 5,
+                },
+                id: 4,
             },
             Stmt::Expression {
                 expr: Expr::Binary {
-                    left: Box::new(Expr::Name { val: "durk".to_string() }),
+                    left: Box::new(Expr::Name { val: "durk".to_string(), id: 10 }),
                     op: Token::BangEq,
-                    right: Box::new(Expr::Name { val: "keef".to_string() }),
-                }
+                    right: Box::new(Expr::Name { val: "keef".to_string(), id: 11 }),
+                    id: 9,
+                },
+                id: 8,
             },
         ];
 
         check_stmt(s, exp);
     }
 
+
+    #[test]
+    fn test_unexpected_end() {
+        // running out of tokens is an error rather than reading past the end
+        assert_eq!(errors("if true\nprint 1\n"), vec!["[line 3] expected End but found Eof"]);
+        assert_eq!(errors("struct Saiyan\nlet name: string"), vec!["[line 2] expected field or method in struct but found Eof"]);
+        assert_eq!(errors("print [1, 2"), vec!["[line 1] expected RBracket but found Eof"]);
+        assert!(parse("").is_ok());
+        // and an `end` or `else` with no block to close is an error rather than somewhere to stop
+        assert_eq!(errors("end\nprint 1\nelse\n"), vec![
+            "[line 1] expected expression but found End",
+            "[line 3] expected expression but found Else",
+        ]);
+    }
+
+    #[test]
+    fn test_return_before_end() {
+        let s = fixtures::parser::RETURN_BEFORE_END;
+        let lambda = Lambda {
+            params: vec![Param { name: Token::Ident("x".to_string()), type_name: None }],
+            return_type: None,
+            body: vec![Return { expr: Some(Name { val: "x".to_string(), id: 3 }), id: 2 }],
+            id: 1,
+        };
+        assert_eq!(parse(s).unwrap(), vec![
+            Let { name: Token::Ident("g".to_string()), type_name: None, expr: lambda, id: 0 },
+        ]);
+    }
+
+    #[test]
+    fn test_interpolation_errors() {
+        // the error is about the `} y"` that ended the expression early, on the line the string is on
+        let errors = parse("print \"x ${1 +} y\"\n").unwrap_err();
+        assert_eq!(errors[0].message(), "[line 1] expected expression but found InterpolationEnd(\" y\")");
+        assert_eq!(errors[0].span(), Some(14..18));
+
+        let errors = parse("print 1\nprint \"a ${1 2} b\"\n").unwrap_err();
+        assert_eq!(errors[0].message(), "[line 2] expected '}' after interpolated expression but found Int(2)");
+        assert_eq!(errors[0].span(), Some(21..22));
+    }
 }
@@ -0,0 +1,842 @@
+use std::collections::{HashMap, HashSet};
+use crate::ast::{Expr, NodeId, Param, Stmt};
+use crate::compiler::FunctionKind;
+use crate::error::Error;
+use crate::parser::Program;
+use crate::token::Token;
+use crate::visitor::{ExprVisitor, StmtVisitor};
+
+// where the variable a name refers to lives at runtime
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum Binding {
+    // a stack slot relative to the current frame
+    Local(u16),
+    // a local of an enclosing function, captured by the closure being compiled
+    Upvalue(u16),
+    // a field or method of `self`, used by bare name inside a method
+    Member,
+    Global,
+}
+
+// the binding of every `Expr::Name` and `Expr::Assign` in a program, keyed by the node's id
+pub(crate) type Bindings = HashMap<NodeId, Binding>;
+
+// where a closure finds a captured variable when it is created: a local of the enclosing function or one of its upvalues
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct Upvalue {
+    pub(crate) index: u16,
+    pub(crate) is_local: bool,
+}
+
+// the upvalues of every function and lambda that captures any, keyed by the function's id
+pub(crate) type Captures = HashMap<NodeId, Vec<Upvalue>>;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) enum DeclarationKind {
+    // a top level `let`, struct, enum or protocol
+    Global,
+    Local,
+    Parameter,
+    Function,
+}
+
+// a name a program declares and how the rest of the program uses it, for the linter
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Declaration {
+    pub(crate) kind: DeclarationKind,
+    // read somewhere other than its own declaration. assigning to it doesn't count
+    pub(crate) used: bool,
+    // hides a local of an enclosing block or function, or a global
+    pub(crate) shadows: bool,
+}
+
+// a name a program declares, as the id of the node that declares it and which of the node's names it is. a
+// function's parameters are counted after its own name, which a lambda doesn't have, and the value of a
+// `for` after its key
+pub(crate) type DeclarationId = (NodeId, usize);
+
+// every declaration in a program
+pub(crate) type Declarations = HashMap<DeclarationId, Declaration>;
+
+#[derive(Default)]
+struct Scope {
+    locals: HashMap<String, u16>,
+    // the declarations of the locals, which hidden locals like `self` have none of
+    declarations: HashMap<String, DeclarationId>,
+    // names declared further down the block, which cannot be used yet
+    pending: HashSet<String>,
+}
+
+struct Function {
+    kind: FunctionKind,
+    scopes: Vec<Scope>,
+    // the members of the struct a method belongs to
+    members: HashSet<String>,
+    // the locals of enclosing functions this function captures
+    upvalues: Vec<Upvalue>,
+}
+
+impl Function {
+    fn new(kind: FunctionKind, members: HashSet<String>) -> Self {
+        Function { kind, scopes: vec![], members, upvalues: vec![] }
+    }
+
+    fn local(&self, name: &str) -> Option<u16> {
+        self.scopes.iter().rev().find_map(|s| s.locals.get(name).copied())
+    }
+}
+
+struct Resolver {
+    functions: Vec<Function>,
+    // every top level declaration, which function bodies can use wherever it appears
+    globals: HashSet<String>,
+    // the top level declarations reached so far, which are all top level code can use
+    defined: HashSet<String>,
+    // the top level declarations by name
+    global_declarations: HashMap<String, DeclarationId>,
+    bindings: Bindings,
+    captures: Captures,
+    declarations: Declarations,
+    errors: Vec<Error>,
+}
+
+pub(crate) fn resolve(program: &Program, globals: &[String]) -> Result<(Bindings, Captures), Vec<Error>> {
+    run(program, globals).map(|resolver| (resolver.bindings, resolver.captures))
+}
+
+pub(crate) fn declarations(program: &Program, globals: &[String]) -> Result<Declarations, Vec<Error>> {
+    run(program, globals).map(|resolver| resolver.declarations)
+}
+
+fn run(program: &Program, globals: &[String]) -> Result<Resolver, Vec<Error>> {
+    let mut resolver = Resolver::new();
+    resolver.resolve(program, globals);
+    if resolver.errors.is_empty() {
+        Ok(resolver)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Resolver {
+            functions: vec![Function::new(FunctionKind::Script, HashSet::new())],
+            globals: HashSet::new(),
+            defined: HashSet::new(),
+            global_declarations: HashMap::new(),
+            bindings: HashMap::new(),
+            captures: HashMap::new(),
+            declarations: HashMap::new(),
+            errors: vec![],
+        }
+    }
+
+    // `globals` were defined before this program, which may declare them again
+    fn resolve(&mut self, program: &Program, globals: &[String]) {
+        for stmt in program {
+            if let Some(token) = Self::declared_token(stmt) {
+                let name = Self::ident(token);
+                if !self.globals.insert(name.to_string()) {
+                    self.errors.push(Error::new(&format!("'{}' is already declared in this scope", name)));
+                }
+                let kind = if matches!(stmt, Stmt::FunDeclaration { .. }) { DeclarationKind::Function } else { DeclarationKind::Global };
+                self.global_declarations.insert(name.to_string(), (stmt.id(), 0));
+                self.declarations.insert((stmt.id(), 0), Declaration { kind, used: false, shadows: false });
+                // everything but `let` can be used before it appears
+                if !matches!(stmt, Stmt::Let { .. }) {
+                    self.defined.insert(name.to_string());
+                }
+            }
+        }
+        self.globals.extend(globals.iter().cloned());
+        self.defined.extend(globals.iter().cloned());
+
+        for stmt in program {
+            self.resolve_stmt(stmt);
+        }
+    }
+
+    fn declared_name(stmt: &Stmt) -> Option<&str> {
+        Self::declared_token(stmt).map(Self::ident)
+    }
+
+    fn declared_token(stmt: &Stmt) -> Option<&Token> {
+        match stmt {
+            Stmt::Let { name, .. }
+            | Stmt::FunDeclaration { name, .. }
+            | Stmt::Struct { name, .. }
+            | Stmt::Enum { name, .. }
+            | Stmt::Protocol { name, .. } => Some(name),
+            _ => None,
+        }
+    }
+
+    fn function(&mut self) -> &mut Function {
+        self.functions.last_mut().expect("function")
+    }
+
+    // starts a scope in which `stmts` will declare their names
+    fn begin_scope(&mut self, stmts: &[Stmt]) {
+        let pending = stmts.iter().filter_map(Self::declared_name).map(|n| n.to_string()).collect();
+        self.function().scopes.push(Scope { locals: HashMap::new(), declarations: HashMap::new(), pending });
+    }
+
+    fn end_scope(&mut self) {
+        self.function().scopes.pop();
+    }
+
+    fn resolve_block(&mut self, stmts: &[Stmt]) {
+        self.begin_scope(stmts);
+        for stmt in stmts {
+            self.resolve_stmt(stmt);
+        }
+        self.end_scope();
+    }
+
+    // binds `name` in the current scope, giving locals the next stack slot the compiler will use
+    fn declare(&mut self, name: &str) {
+        if self.function().scopes.is_empty() {
+            // top level names were collected up front
+            self.defined.insert(name.to_string());
+            return;
+        }
+
+        let function = self.function();
+        let slot = function.scopes.iter().map(|s| s.locals.len()).sum::<usize>() as u16;
+        let scope = function.scopes.last_mut().expect("scope");
+        scope.pending.remove(name);
+        if scope.locals.insert(name.to_string(), slot).is_some() {
+            self.errors.push(Error::new(&format!("'{}' is already declared in this scope", name)));
+        }
+    }
+
+    // records the declaration of a local for the linter, after `declare` has bound its name
+    fn record(&mut self, token: &Token, declaration: DeclarationId, kind: DeclarationKind) {
+        if self.function().scopes.is_empty() {
+            // top level names were recorded up front
+            return;
+        }
+        let name = Self::ident(token);
+        let shadows = self.declaration_of(name).is_some();
+        let scope = self.function().scopes.last_mut().expect("scope");
+        scope.declarations.insert(name.to_string(), declaration);
+        self.declarations.insert(declaration, Declaration { kind, used: false, shadows });
+    }
+
+    // the declaration of what `name` refers to, looked up in the same order as `lookup`
+    fn declaration_of(&self, name: &str) -> Option<DeclarationId> {
+        for (i, function) in self.functions.iter().enumerate().rev() {
+            if let Some(declaration) = function.scopes.iter().rev().find_map(|s| s.declarations.get(name)) {
+                return Some(*declaration);
+            }
+            if i == self.functions.len() - 1 && function.members.contains(name) {
+                return None;
+            }
+        }
+        self.global_declarations.get(name).copied()
+    }
+
+    fn function_body(&mut self, id: NodeId, params: &[Param], body: &[Stmt], kind: FunctionKind, members: HashSet<String>) {
+        self.functions.push(Function::new(kind, members));
+        self.begin_scope(body);
+
+        // slot 0 holds the callee, or the receiver for methods
+        self.declare(if kind == FunctionKind::Method { "self" } else { "" });
+        for (i, param) in params.iter().enumerate() {
+            self.declare(Self::ident(&param.name));
+            self.record(&param.name, (id, i + 1), DeclarationKind::Parameter);
+        }
+        for stmt in body {
+            self.resolve_stmt(stmt);
+        }
+
+        let function = self.functions.pop().expect("function");
+        if !function.upvalues.is_empty() {
+            self.captures.insert(id, function.upvalues);
+        }
+    }
+
+    // locals shadow the members of the current method's struct, which shadow globals
+    fn lookup(&mut self, name: &str) -> Option<Binding> {
+        let function = self.functions.last().expect("function");
+        for scope in function.scopes.iter().rev() {
+            if let Some(slot) = scope.locals.get(name) {
+                return Some(Binding::Local(*slot));
+            }
+            if scope.pending.contains(name) {
+                self.errors.push(Error::new(&format!("'{}' is used before its declaration", name)));
+                return Some(Binding::Global);
+            }
+        }
+        if function.members.contains(name) {
+            return Some(Binding::Member);
+        }
+
+        let script = function.kind == FunctionKind::Script;
+        if let Some(index) = self.resolve_upvalue(self.functions.len() - 1, name) {
+            return Some(Binding::Upvalue(index));
+        }
+
+        if self.globals.contains(name) {
+            if script && !self.defined.contains(name) {
+                self.errors.push(Error::new(&format!("'{}' is used before its declaration", name)));
+            }
+            return Some(Binding::Global);
+        }
+        None
+    }
+
+    // resolves `name` as an upvalue of `self.functions[function]`, adding it to the functions in between
+    fn resolve_upvalue(&mut self, function: usize, name: &str) -> Option<u16> {
+        let enclosing = function.checked_sub(1)?;
+        if let Some(slot) = self.functions[enclosing].local(name) {
+            return Some(self.add_upvalue(function, Upvalue { index: slot, is_local: true }));
+        }
+        let index = self.resolve_upvalue(enclosing, name)?;
+        Some(self.add_upvalue(function, Upvalue { index, is_local: false }))
+    }
+
+    fn add_upvalue(&mut self, function: usize, upvalue: Upvalue) -> u16 {
+        let upvalues = &mut self.functions[function].upvalues;
+        match upvalues.iter().position(|u| *u == upvalue) {
+            Some(index) => index as u16,
+            None => {
+                upvalues.push(upvalue);
+                (upvalues.len() - 1) as u16
+            }
+        }
+    }
+
+    fn bind(&mut self, id: NodeId, binding: Binding) {
+        self.bindings.insert(id, binding);
+    }
+
+    fn resolve_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { .. } => self.visit_expr_stmt(stmt),
+            Stmt::Let { .. } => self.visit_let(stmt),
+            Stmt::Print { .. } => self.visit_print(stmt),
+            Stmt::For { .. } => self.visit_for(stmt),
+            Stmt::If { .. } => self.visit_if(stmt),
+            Stmt::While { .. } => self.visit_while(stmt),
+            Stmt::Break { .. } => self.visit_break(stmt),
+            Stmt::Continue { .. } => self.visit_continue(stmt),
+            Stmt::FunDeclaration { .. } => self.visit_fun(stmt),
+            Stmt::Return { .. } => self.visit_return(stmt),
+            Stmt::Struct { .. } => self.visit_struct(stmt),
+            Stmt::Enum { .. } => self.visit_enum(stmt),
+            Stmt::Protocol { .. } => self.visit_protocol(stmt),
+        }
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { .. } => self.visit_binary(expr),
+            Expr::Unary { .. } => self.visit_unary(expr),
+            Expr::Int { .. } => self.visit_int(expr),
+            Expr::Float { .. } => self.visit_float(expr),
+            Expr::BigInt { .. } => self.visit_big_int(expr),
+            Expr::String { .. } => self.visit_string(expr),
+            Expr::Bool { .. } => self.visit_bool(expr),
+            Expr::Nil { .. } => self.visit_nil(expr),
+            Expr::Interpolation { .. } => self.visit_interpolation(expr),
+            Expr::Grouping { .. } => self.visit_grouping(expr),
+            Expr::Name { .. } => self.visit_name(expr),
+            Expr::Assign { .. } => self.visit_assign(expr),
+            Expr::CompoundAssign { .. } => self.visit_compound_assign(expr),
+            Expr::Array { .. } => self.visit_array(expr),
+            Expr::Map { .. } => self.visit_map(expr),
+            Expr::Index { .. } => self.visit_index(expr),
+            Expr::SetIndex { .. } => self.visit_set_index(expr),
+            Expr::Range { .. } => self.visit_range(expr),
+            Expr::Call { .. } => self.visit_call(expr),
+            Expr::Get { .. } => self.visit_get(expr),
+            Expr::Set { .. } => self.visit_set(expr),
+            Expr::SelfRef { .. } => self.visit_self(expr),
+            Expr::ImplicitMember { .. } => (),
+            Expr::Lambda { .. } => self.visit_lambda(expr),
+        }
+    }
+
+    fn ident(token: &Token) -> &str {
+        match token {
+            Token::Ident(name) => name,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl StmtVisitor for Resolver {
+    type Output = ();
+
+    fn visit_expr_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expr, .. } => self.resolve_expr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_let(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, expr, id, .. } => {
+                self.resolve_expr(expr);
+                self.declare(Self::ident(name));
+                self.record(name, (*id, 0), DeclarationKind::Local);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_print(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Print { expr, .. } => self.resolve_expr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_for(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::For { name, value, iterable, body, id } => {
+                self.resolve_expr(iterable);
+                // the compiler's hidden locals for the sequence and the index
+                self.begin_scope(&[]);
+                self.declare("for seq");
+                self.declare("for index");
+
+                self.begin_scope(body);
+                self.declare(Self::ident(name));
+                self.record(name, (*id, 0), DeclarationKind::Local);
+                if let Some(value) = value {
+                    self.declare(Self::ident(value));
+                    self.record(value, (*id, 1), DeclarationKind::Local);
+                }
+                for stmt in body {
+                    self.resolve_stmt(stmt);
+                }
+                self.end_scope();
+                self.end_scope();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_if(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::If { condition, consequence, alternative, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_block(consequence);
+                if let Some(alternative) = alternative {
+                    self.resolve_block(alternative);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_while(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::While { condition, body, .. } => {
+                self.resolve_expr(condition);
+                self.resolve_block(body);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_break(&mut self, _stmt: &Stmt) {}
+
+    fn visit_continue(&mut self, _stmt: &Stmt) {}
+
+    fn visit_fun(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FunDeclaration { name, params, body, id, .. } => {
+                // declared first so the function can capture itself and recurse
+                self.declare(Self::ident(name));
+                self.record(name, (*id, 0), DeclarationKind::Function);
+                self.function_body(*id, params, body, FunctionKind::Function, HashSet::new());
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_return(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return { expr, .. } => {
+                if self.function().kind == FunctionKind::Script {
+                    self.errors.push(Error::new("'return' outside of a function"));
+                }
+                if let Some(expr) = expr {
+                    self.resolve_expr(expr);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_struct(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Struct { name, protocols, fields, methods, .. } => {
+                // the compiler reports names that aren't protocols, declared or not
+                for protocol in protocols {
+                    if let Expr::Name { val, id } = protocol {
+                        let binding = self.lookup(val).unwrap_or(Binding::Global);
+                        self.bind(*id, binding);
+                    }
+                }
+                let mut members: HashSet<String> = fields.iter().map(|f| Self::ident(&f.name).to_string()).collect();
+                for method in methods {
+                    members.extend(Self::declared_name(method).map(|n| n.to_string()));
+                }
+                for method in methods {
+                    if let Stmt::FunDeclaration { params, body, id, .. } = method {
+                        self.function_body(*id, params, body, FunctionKind::Method, members.clone());
+                    }
+                }
+                self.declare(Self::ident(name));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_enum(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Enum { name, .. } => self.declare(Self::ident(name)),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_protocol(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Protocol { name, .. } => self.declare(Self::ident(name)),
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ExprVisitor for Resolver {
+    type Output = ();
+
+    fn visit_binary(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_unary(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Unary { right, .. } => self.resolve_expr(right),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_int(&mut self, _expr: &Expr) {}
+
+    fn visit_float(&mut self, _expr: &Expr) {}
+
+    fn visit_big_int(&mut self, _expr: &Expr) {}
+
+    fn visit_string(&mut self, _expr: &Expr) {}
+
+    fn visit_bool(&mut self, _expr: &Expr) {}
+
+    fn visit_nil(&mut self, _expr: &Expr) {}
+
+    fn visit_interpolation(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Interpolation { parts, .. } => {
+                for part in parts {
+                    self.resolve_expr(part);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Grouping { expr, .. } => self.resolve_expr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_name(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Name { val, id } => {
+                let binding = self.lookup(val).unwrap_or_else(|| {
+                    self.errors.push(Error::new(&format!("undefined variable '{}'", val)));
+                    Binding::Global
+                });
+                self.bind(*id, binding);
+                if let Some(declaration) = self.declaration_of(val) {
+                    self.declarations.get_mut(&declaration).expect("declaration").used = true;
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_assign(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Assign { name, expr: value, id } => {
+                self.resolve_expr(value);
+                let name = Self::ident(name);
+                let binding = self.lookup(name).unwrap_or_else(|| {
+                    self.errors.push(Error::new(&format!("cannot assign to undeclared variable '{}'", name)));
+                    Binding::Global
+                });
+                self.bind(*id, binding);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_compound_assign(&mut self, expr: &Expr) {
+        match expr {
+            Expr::CompoundAssign { target, expr, .. } => {
+                self.resolve_expr(target);
+                self.resolve_expr(expr);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_array(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.resolve_expr(element);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_map(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.resolve_expr(key);
+                    self.resolve_expr(value);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_index(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Index { object, index, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set_index(&mut self, expr: &Expr) {
+        match expr {
+            Expr::SetIndex { object, index, expr, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(index);
+                self.resolve_expr(expr);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_range(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Range { start, end, .. } => {
+                self.resolve_expr(start);
+                self.resolve_expr(end);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_call(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Call { callee, args, .. } => {
+                self.resolve_expr(callee);
+                for arg in args {
+                    self.resolve_expr(arg);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_get(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Get { object, .. } => self.resolve_expr(object),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Set { object, expr, .. } => {
+                self.resolve_expr(object);
+                self.resolve_expr(expr);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_self(&mut self, _expr: &Expr) {}
+
+    fn visit_lambda(&mut self, expr: &Expr) {
+        match expr {
+            Expr::Lambda { params, body, id, .. } => {
+                self.function_body(*id, params, body, FunctionKind::Function, HashSet::new());
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+    use super::{declarations, resolve, Binding, DeclarationKind, Upvalue};
+    use crate::ast::{Expr, Stmt};
+
+    fn errors(s: &str) -> Vec<String> {
+        match resolve(&parse(s).unwrap(), &[]) {
+            Ok(_) => vec![],
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_bindings() {
+        let s = r#"
+        let g = 1
+        fun f(a, b)
+            let c = a
+            return b
+        end
+        "#;
+        let program = parse(s).unwrap();
+        let (bindings, _) = resolve(&program, &[]).unwrap();
+
+        let Stmt::FunDeclaration { body, .. } = &program[1] else { panic!("expected a function") };
+        let Stmt::Let { expr: Expr::Name { id: a, .. }, .. } = &body[0] else { panic!("expected a let") };
+        let Stmt::Return { expr: Some(Expr::Name { id: b, .. }), .. } = &body[1] else { panic!("expected a return") };
+        assert_eq!(bindings[a], Binding::Local(1));
+        assert_eq!(bindings[b], Binding::Local(2));
+    }
+
+    #[test]
+    fn test_upvalues() {
+        let s = r#"
+        fun outer(a)
+            fun inner()
+                return a
+            end
+        end
+        "#;
+        let program = parse(s).unwrap();
+        let (bindings, captures) = resolve(&program, &[]).unwrap();
+
+        let Stmt::FunDeclaration { body, id: outer, .. } = &program[0] else { panic!("expected a function") };
+        let Stmt::FunDeclaration { body, id: inner, .. } = &body[0] else { panic!("expected a function") };
+        let Stmt::Return { expr: Some(Expr::Name { id: a, .. }), .. } = &body[0] else { panic!("expected a return") };
+        assert_eq!(bindings[a], Binding::Upvalue(0));
+        assert_eq!(captures[inner], vec![Upvalue { index: 1, is_local: true }]);
+        assert!(!captures.contains_key(outer));
+    }
+
+    #[test]
+    fn test_undeclared() {
+        let s = r#"
+        print x
+        y = 1
+        fun f()
+            return later
+        end
+        let later = 1
+        "#;
+        assert_eq!(errors(s), vec!["undefined variable 'x'", "cannot assign to undeclared variable 'y'"]);
+    }
+
+    #[test]
+    fn test_use_before_declaration() {
+        let s = r#"
+        print g
+        let g = 1
+        if true
+            print l
+            let l = 2
+        end
+        "#;
+        assert_eq!(errors(s), vec![
+            "'g' is used before its declaration",
+            "'l' is used before its declaration",
+        ]);
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let s = r#"
+        let a = 1
+        fun a()
+        end
+        fun f(x, x)
+            let y = 1
+            if true
+                let y = 2
+            end
+            let y = 3
+        end
+        "#;
+        assert_eq!(errors(s), vec![
+            "'a' is already declared in this scope",
+            "'x' is already declared in this scope",
+            "'y' is already declared in this scope",
+        ]);
+    }
+
+    #[test]
+    fn test_redeclaring_predefined_globals() {
+        let s = "print max\nlet max = 1\nfun pi()\nend\n";
+        assert!(resolve(&parse(s).unwrap(), &["max".to_string(), "pi".to_string()]).is_ok());
+    }
+
+    #[test]
+    fn test_return_outside_function() {
+        assert_eq!(errors("return 1\n"), vec!["'return' outside of a function"]);
+    }
+
+    #[test]
+    fn test_declarations() {
+        let s = r#"
+        let g = 1
+        fun f(a, b)
+            let g = a
+            return fun(c) b end
+        end
+        "#;
+        let program = parse(s).unwrap();
+        let declarations = declarations(&program, &[]).unwrap();
+
+        let Stmt::FunDeclaration { body, id: f, .. } = &program[1] else { panic!("expected a function") };
+        let Stmt::Return { expr: Some(Expr::Lambda { id: lambda, .. }), .. } = &body[1] else { panic!("expected a lambda") };
+        let declaration = |id| {
+            let declaration = &declarations[&id];
+            (declaration.kind, declaration.used, declaration.shadows)
+        };
+        assert_eq!(declaration((program[0].id(), 0)), (DeclarationKind::Global, false, false));
+        assert_eq!(declaration((*f, 0)), (DeclarationKind::Function, false, false));
+        assert_eq!(declaration((*f, 1)), (DeclarationKind::Parameter, true, false));
+        // captured by the lambda
+        assert_eq!(declaration((*f, 2)), (DeclarationKind::Parameter, true, false));
+        assert_eq!(declaration((body[0].id(), 0)), (DeclarationKind::Local, false, true));
+        assert_eq!(declaration((*lambda, 1)), (DeclarationKind::Parameter, false, false));
+    }
+}
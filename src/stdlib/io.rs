@@ -0,0 +1,80 @@
+use std::cell::RefCell;
+use std::env;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::process;
+use std::rc::Rc;
+use crate::engine::Engine;
+use crate::error::Error;
+
+// `args` holds the script arguments, which the host can set after registering
+pub(crate) fn register(engine: &mut Engine, args: Rc<RefCell<Vec<String>>>) {
+    engine.register_fn("read_file", |path: String| {
+        fs::read_to_string(&path).map_err(|e| Error::new(&format!("could not read {}: {}", path, e)))
+    });
+    engine.register_fn("read_lines", |path: String| {
+        let contents = fs::read_to_string(&path).map_err(|e| Error::new(&format!("could not read {}: {}", path, e)))?;
+        Ok(contents.lines().map(str::to_string).collect::<Vec<_>>())
+    });
+    engine.register_fn("write_file", |path: String, contents: String| {
+        fs::write(&path, contents).map_err(|e| Error::new(&format!("could not write {}: {}", path, e)))
+    });
+    // the next line of stdin without its line ending, or nil at the end of input
+    engine.register_fn("read_line", || {
+        let mut line = String::new();
+        match io::stdin().lock().read_line(&mut line) {
+            Ok(0) => Ok(None),
+            Ok(_) => Ok(Some(line.trim_end_matches(['\n', '\r']).to_string())),
+            Err(e) => Err(Error::new(&format!("could not read stdin: {}", e))),
+        }
+    });
+    engine.register_fn("args", move || args.borrow().clone());
+    engine.register_fn("env", |name: String| env::var(name).ok());
+    engine.register_fn("exit", |code: i32| -> () {
+        let _ = io::stdout().flush();
+        process::exit(code)
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use std::env;
+    use std::fs;
+    use crate::engine::{Capabilities, Engine};
+
+    #[test]
+    fn test_files() {
+        let path = env::temp_dir().join(format!("frieza-io-{}.txt", std::process::id()));
+        let path = path.to_str().unwrap();
+        let mut engine = Engine::with_capabilities(Capabilities { io: true });
+        engine.register_value("path", path);
+        engine.register_value("contents", "goku\nvegeta\n");
+
+        engine.run("write_file(path, contents)\n").unwrap();
+        assert_eq!(engine.eval::<String>("read_file(path)\n").unwrap(), "goku\nvegeta\n");
+        assert_eq!(engine.eval::<Vec<String>>("read_lines(path)\n").unwrap(), vec!["goku", "vegeta"]);
+        fs::remove_file(path).unwrap();
+
+        let errors = engine.eval::<String>("read_file(path)\n").unwrap_err();
+        assert!(errors[0].message().starts_with(&format!("could not read {}", path)));
+    }
+
+    #[test]
+    fn test_args_and_env() {
+        let mut engine = Engine::with_capabilities(Capabilities { io: true });
+        engine.set_args(vec!["--verbose".to_string(), "input.txt".to_string()]);
+        assert_eq!(engine.eval::<Vec<String>>("args()\n").unwrap(), vec!["--verbose", "input.txt"]);
+        assert_eq!(engine.eval::<Option<String>>("env(\"FRIEZA_SURELY_UNSET\")\n").unwrap(), None);
+        assert_eq!(engine.eval::<Option<String>>("env(\"PATH\")\n").unwrap(), env::var("PATH").ok());
+    }
+
+    #[test]
+    fn test_disabled() {
+        // an engine can't touch the outside world unless it's asked to
+        let mut engine = Engine::new();
+        let errors = engine.eval::<String>("read_file(\"/etc/passwd\")\n").unwrap_err();
+        assert_eq!(errors[0].message(), "undefined variable 'read_file'");
+        // the rest of the standard library is still there
+        assert_eq!(engine.eval::<i32>("abs(-1)\n").unwrap(), 1);
+    }
+}
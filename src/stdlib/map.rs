@@ -0,0 +1,69 @@
+use std::rc::Rc;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::value::{Map, Obj, Value};
+use crate::vm::VM;
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_native("keys", 1, Rc::new(|vm: &mut VM, args: &[Value]| {
+        let keys = map(vm, "keys", &args[0])?.entries().iter().map(|(k, _)| k.clone()).collect();
+        Ok(vm.alloc(Obj::Array(keys)))
+    }));
+    engine.register_native("values", 1, Rc::new(|vm: &mut VM, args: &[Value]| {
+        let values = map(vm, "values", &args[0])?.entries().iter().map(|(_, v)| v.clone()).collect();
+        Ok(vm.alloc(Obj::Array(values)))
+    }));
+    engine.register_native("contains_key", 2, Rc::new(|vm: &mut VM, args: &[Value]| {
+        let key = vm.key(&args[1])?;
+        Ok(Value::bool(map(vm, "contains_key", &args[0])?.get(&key).is_some()))
+    }));
+    // returns the removed value, or nil when the key was not there
+    engine.register_native("remove", 2, Rc::new(|vm: &mut VM, args: &[Value]| {
+        let key = vm.key(&args[1])?;
+        map(vm, "remove", &args[0])?;
+        match vm.obj_mut(&args[0]) {
+            Some(Obj::Map(map)) => Ok(map.remove(&key).unwrap_or_else(Value::nil)),
+            _ => unreachable!(),
+        }
+    }));
+}
+
+fn map<'a>(vm: &'a VM, name: &str, value: &Value) -> Result<&'a Map, Error> {
+    match vm.obj(value) {
+        Some(Obj::Map(map)) => Ok(map),
+        _ => Err(Error::new(&format!("{} expects a map but got {}", name, vm.type_name(value)))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+    use crate::value::Value;
+
+    fn eval(source: &str) -> String {
+        let mut engine = Engine::new();
+        let value: Value = engine.eval(&format!("{}\n", source)).unwrap_or_else(|e| panic!("{}", e[0]));
+        engine.stringify(&value)
+    }
+
+    fn error(source: &str) -> String {
+        let mut engine = Engine::new();
+        engine.eval::<Value>(&format!("{}\n", source)).err().expect("error")[0].message().to_string()
+    }
+
+    #[test]
+    fn test_functions() {
+        let m = "let m = {\"a\": 1, 2: true, false: \"c\"}\n";
+        assert_eq!(eval(&format!("{}keys(m)", m)), "[a, 2, false]");
+        assert_eq!(eval(&format!("{}values(m)", m)), "[1, true, c]");
+        assert_eq!(eval(&format!("{}[contains_key(m, 2), contains_key(m, \"b\")]", m)), "[true, false]");
+        assert_eq!(eval(&format!("{}[remove(m, \"a\"), remove(m, \"a\"), m]", m)), "[1, nil, {2: true, false: c}]");
+        assert_eq!(eval(&format!("{}len(m)", m)), "3");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("keys([1])"), "keys expects a map but got array");
+        assert_eq!(error("contains_key({}, 1.5)"), "Unhashable map key of type float");
+    }
+}
@@ -0,0 +1,222 @@
+use std::cell::Cell;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::engine::{Engine, FromValue, IntoValue};
+use crate::error::Error;
+use crate::value::{Value, ValueType};
+use crate::vm::VM;
+
+// the seed random numbers start from until a program calls `seed`
+const DEFAULT_SEED: u64 = 0x9e37_79b9_7f4a_7c15;
+
+// an int or a float, so functions can keep ints as ints the way the vm's arithmetic does
+enum Number {
+    Int(i64),
+    Big(BigInt),
+    Float(f64),
+}
+
+impl Number {
+    fn float(&self) -> f64 {
+        match self {
+            Number::Int(i) => *i as f64,
+            Number::Big(n) => n.to_f64(),
+            Number::Float(f) => *f,
+        }
+    }
+
+    fn big(&self) -> Option<BigInt> {
+        match self {
+            Number::Int(i) => Some(BigInt::from(*i)),
+            Number::Big(n) => Some(n.clone()),
+            Number::Float(_) => None,
+        }
+    }
+}
+
+impl FromValue for Number {
+    fn from_value(value: &Value, vm: &VM) -> Result<Self, Error> {
+        match value.value_type {
+            ValueType::Int => Ok(Number::Int(unsafe { value.val.i })),
+            ValueType::Float => Ok(Number::Float(unsafe { value.val.f })),
+            _ => vm.bigint(value).map(Number::Big).ok_or_else(|| Error::new(&format!("expected a number but got {}", vm.type_name(value)))),
+        }
+    }
+}
+
+impl IntoValue for Number {
+    fn into_value(self, vm: &mut VM) -> Value {
+        match self {
+            Number::Int(i) => Value::int(i),
+            Number::Big(n) => vm.integer(n),
+            Number::Float(f) => Value::float(f),
+        }
+    }
+}
+
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_value("pi", std::f64::consts::PI);
+
+    engine.register_fn("abs", |x: Number| match x {
+        Number::Int(i) => Number::Big(BigInt::from(i).abs()),
+        Number::Big(n) => Number::Big(n.abs()),
+        Number::Float(f) => Number::Float(f.abs()),
+    });
+    engine.register_fn("min", |a: Number, b: Number| match (a.big(), b.big()) {
+        (Some(a), Some(b)) => Number::Big(a.min(b)),
+        _ => Number::Float(a.float().min(b.float())),
+    });
+    engine.register_fn("max", |a: Number, b: Number| match (a.big(), b.big()) {
+        (Some(a), Some(b)) => Number::Big(a.max(b)),
+        _ => Number::Float(a.float().max(b.float())),
+    });
+    engine.register_fn("floor", |x: Number| round(x, f64::floor));
+    engine.register_fn("ceil", |x: Number| round(x, f64::ceil));
+    engine.register_fn("round", |x: Number| round(x, f64::round));
+    engine.register_fn("sqrt", |x: f64| x.sqrt());
+    engine.register_fn("pow", |base: Number, exponent: Number| match (base.big(), &exponent) {
+        (Some(b), Number::Int(e)) if *e >= 0 => {
+            b.pow(&BigInt::from(*e)).map(Number::Big).ok_or_else(|| Error::new("pow result is too large"))
+        }
+        _ => Ok(Number::Float(base.float().powf(exponent.float()))),
+    });
+    engine.register_fn("sin", |x: f64| x.sin());
+    engine.register_fn("cos", |x: f64| x.cos());
+    engine.register_fn("tan", |x: f64| x.tan());
+    engine.register_fn("log", |x: f64| x.ln());
+
+    engine.register_fn("int", |x: Number| match x {
+        Number::Float(f) => BigInt::from_f64(f).map(Number::Big).ok_or_else(|| Error::new(&format!("cannot convert {} to int", f))),
+        n => Ok(n),
+    });
+    engine.register_fn("float", |x: f64| x);
+
+    // arithmetic on ints promotes to big ints when it overflows, these wrap around at 64 bits instead
+    engine.register_fn("wrapping_add", |a: i64, b: i64| a.wrapping_add(b));
+    engine.register_fn("wrapping_sub", |a: i64, b: i64| a.wrapping_sub(b));
+    engine.register_fn("wrapping_mul", |a: i64, b: i64| a.wrapping_mul(b));
+    engine.register_fn("wrapping_div", |a: i64, b: i64| {
+        if b == 0 {
+            return Err(Error::new("Division by zero"));
+        }
+        Ok(a.wrapping_div(b))
+    });
+    engine.register_fn("wrapping_neg", |a: i64| a.wrapping_neg());
+
+    let state = Rc::new(Cell::new(DEFAULT_SEED));
+    let s = state.clone();
+    engine.register_fn("seed", move |seed: i64| s.set(seed as u64));
+    let s = state.clone();
+    engine.register_fn("random", move || (next(&s) >> 11) as f64 / (1u64 << 53) as f64);
+    // an int from low up to but not including high
+    engine.register_fn("random_int", move |low: i64, high: i64| {
+        if low >= high {
+            return Err(Error::new("random_int expects low to be less than high"));
+        }
+        let span = (high as i128 - low as i128) as u64;
+        Ok((low as i128 + (next(&state) % span) as i128) as i64)
+    });
+}
+
+// keeps ints as they are and rounds floats without turning them into ints
+fn round(x: Number, f: fn(f64) -> f64) -> Number {
+    match x {
+        Number::Float(x) => Number::Float(f(x)),
+        n => n,
+    }
+}
+
+// splitmix64, which is small and gives the same numbers on every platform for a seed
+fn next(state: &Cell<u64>) -> u64 {
+    let s = state.get().wrapping_add(0x9e37_79b9_7f4a_7c15);
+    state.set(s);
+    let mut z = s;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^ (z >> 31)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+    use crate::value::Value;
+
+    fn eval(source: &str) -> String {
+        let mut engine = Engine::new();
+        let value: Value = engine.eval(&format!("{}\n", source)).unwrap_or_else(|e| panic!("{}", e[0]));
+        engine.stringify(&value)
+    }
+
+    fn error(source: &str) -> String {
+        let mut engine = Engine::new();
+        engine.eval::<Value>(&format!("{}\n", source)).err().expect("error")[0].message().to_string()
+    }
+
+    #[test]
+    fn test_promotion() {
+        assert_eq!(eval("abs(-3)"), "3");
+        assert_eq!(eval("abs(-2.5)"), "2.5");
+        assert_eq!(eval("min(2, 3)"), "2");
+        assert_eq!(eval("max(2, 3.5)"), "3.5");
+        assert_eq!(eval("floor(2.7)"), "2");
+        assert_eq!(eval("floor(7)"), "7");
+        assert_eq!(eval("ceil(2.1)"), "3");
+        assert_eq!(eval("round(-2.5)"), "-3");
+        assert_eq!(eval("pow(2, 10)"), "1024");
+        assert_eq!(eval("pow(2, -1)"), "0.5");
+        assert_eq!(eval("pow(4, 0.5)"), "2");
+        assert_eq!(eval("sqrt(16)"), "4");
+    }
+
+    #[test]
+    fn test_trig_and_log() {
+        assert_eq!(eval("sin(0)"), "0");
+        assert_eq!(eval("cos(pi)"), "-1");
+        assert_eq!(eval("round(tan(pi / 4) * 1000)"), "1000");
+        assert_eq!(eval("log(1)"), "0");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(eval("int(3.9)"), "3");
+        assert_eq!(eval("int(-3.9)"), "-3");
+        assert_eq!(eval("float(3) / 2"), "1.5");
+        assert_eq!(eval("int(2147483648.0)"), "2147483648");
+        assert_eq!(eval("int(-1e19)"), "-10000000000000000000");
+        assert_eq!(error("int(1e308 * 10)"), "cannot convert inf to int");
+        assert_eq!(error("int(\"3\")"), "expected a number but got string");
+    }
+
+    #[test]
+    fn test_big_ints() {
+        assert_eq!(eval("pow(2, 62)"), "4611686018427387904");
+        assert_eq!(eval("pow(2, 100)"), "1267650600228229401496703205376");
+        assert_eq!(eval("pow(-1, 9223372036854775807)"), "-1");
+        assert_eq!(error("pow(2, 9223372036854775807)"), "pow result is too large");
+        assert_eq!(eval("abs(-9223372036854775807 - 1)"), "9223372036854775808");
+        assert_eq!(eval("min(pow(2, 64), 3)"), "3");
+        assert_eq!(eval("max(pow(2, 64), 3.5)"), "18446744073709552000");
+        assert_eq!(eval("round(pow(2, 64))"), "18446744073709551616");
+        assert_eq!(eval("sqrt(pow(2, 64))"), "4294967296");
+        assert_eq!(error("wrapping_add(pow(2, 64), 1)"), "18446744073709551616 does not fit in a 64-bit int");
+    }
+
+    #[test]
+    fn test_wrapping() {
+        assert_eq!(eval("wrapping_add(9223372036854775807, 1)"), "-9223372036854775808");
+        assert_eq!(eval("wrapping_sub(-9223372036854775807, 2)"), "9223372036854775807");
+        assert_eq!(eval("wrapping_mul(4611686018427387904, 2)"), "-9223372036854775808");
+        assert_eq!(eval("wrapping_div(-9223372036854775807 - 1, -1)"), "-9223372036854775808");
+        assert_eq!(eval("wrapping_neg(-9223372036854775807 - 1)"), "-9223372036854775808");
+        assert_eq!(eval("wrapping_add(2, 3)"), "5");
+        assert_eq!(error("wrapping_div(1, 0)"), "Division by zero");
+    }
+
+    #[test]
+    fn test_random_is_deterministic() {
+        let source = "seed(42)\nlet a = random()\nlet b = random_int(1, 7)\nseed(42)\n[a == random(), b == random_int(1, 7), a >= 0 and a < 1, b >= 1 and b < 7]";
+        assert_eq!(eval(source), "[true, true, true, true]");
+        assert_eq!(eval("random()"), eval("random()"));
+        assert_eq!(error("random_int(3, 3)"), "random_int expects low to be less than high");
+    }
+}
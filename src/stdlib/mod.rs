@@ -0,0 +1,18 @@
+use std::cell::RefCell;
+use std::rc::Rc;
+use crate::engine::{Capabilities, Engine};
+
+pub(crate) mod io;
+pub(crate) mod map;
+pub(crate) mod math;
+pub(crate) mod string;
+
+// registers the modules every engine starts with, and the ones its capabilities allow
+pub(crate) fn register(engine: &mut Engine, capabilities: &Capabilities, args: Rc<RefCell<Vec<String>>>) {
+    math::register(engine);
+    string::register(engine);
+    map::register(engine);
+    if capabilities.io {
+        io::register(engine, args);
+    }
+}
@@ -0,0 +1,113 @@
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::engine::Engine;
+use crate::error::Error;
+use crate::value::{Obj, Value};
+use crate::vm::VM;
+
+// string functions count and index by character rather than by byte
+pub(crate) fn register(engine: &mut Engine) {
+    engine.register_native("len", 1, Rc::new(|vm: &mut VM, args: &[Value]| match vm.obj(&args[0]) {
+        Some(Obj::String(string)) => Ok(Value::int(string.chars().count() as i64)),
+        Some(Obj::Array(elements)) => Ok(Value::int(elements.len() as i64)),
+        Some(Obj::Map(map)) => Ok(Value::int(map.entries().len() as i64)),
+        _ => Err(Error::new(&format!("len expects a string, an array or a map but got {}", vm.type_name(&args[0])))),
+    }));
+    engine.register_native("to_string", 1, Rc::new(|vm: &mut VM, args: &[Value]| {
+        let string = vm.stringify(&args[0]);
+        Ok(vm.alloc(Obj::String(string)))
+    }));
+
+    engine.register_fn("upper", |s: String| s.to_uppercase());
+    engine.register_fn("lower", |s: String| s.to_lowercase());
+    engine.register_fn("trim", |s: String| s.trim().to_string());
+    engine.register_fn("split", |s: String, separator: String| {
+        if separator.is_empty() {
+            return Err(Error::new("split expects a non-empty separator"));
+        }
+        Ok(s.split(separator.as_str()).map(str::to_string).collect::<Vec<_>>())
+    });
+    engine.register_fn("join", |parts: Vec<String>, separator: String| parts.join(&separator));
+    engine.register_fn("contains", |s: String, part: String| s.contains(part.as_str()));
+    engine.register_fn("starts_with", |s: String, prefix: String| s.starts_with(prefix.as_str()));
+    engine.register_fn("ends_with", |s: String, suffix: String| s.ends_with(suffix.as_str()));
+    engine.register_fn("replace", |s: String, from: String, to: String| {
+        if from.is_empty() {
+            return Err(Error::new("replace expects a non-empty pattern"));
+        }
+        Ok(s.replace(from.as_str(), &to))
+    });
+    engine.register_fn("substring", |s: String, start: i64, end: i64| {
+        let count = s.chars().count() as i64;
+        if start < 0 || end < start || end > count {
+            return Err(Error::new(&format!("substring {}..{} is out of range for a string of length {}", start, end, count)));
+        }
+        Ok(s.chars().skip(start as usize).take((end - start) as usize).collect::<String>())
+    });
+    engine.register_fn("chars", |s: String| s.chars().map(String::from).collect::<Vec<_>>());
+    engine.register_fn("parse_int", |s: String| BigInt::parse(s.trim()));
+    engine.register_fn("parse_float", |s: String| s.trim().parse::<f64>().ok().filter(|f| f.is_finite()));
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::engine::Engine;
+    use crate::value::Value;
+
+    fn eval(source: &str) -> String {
+        let mut engine = Engine::new();
+        let value: Value = engine.eval(&format!("{}\n", source)).unwrap_or_else(|e| panic!("{}", e[0]));
+        engine.stringify(&value)
+    }
+
+    fn error(source: &str) -> String {
+        let mut engine = Engine::new();
+        engine.eval::<Value>(&format!("{}\n", source)).err().expect("error")[0].message().to_string()
+    }
+
+    #[test]
+    fn test_utf8() {
+        assert_eq!(eval(r#"len("héllo")"#), "5");
+        assert_eq!(eval(r#"substring("héllo wörld", 1, 5)"#), "éllo");
+        assert_eq!(eval(r#"upper("straße")"#), "STRASSE");
+        assert_eq!(eval(r#"chars("añb")"#), "[a, ñ, b]");
+        assert_eq!(eval("let s = \"\"\nfor c in \"日本\"\n    s = c + s\nend\ns"), "本日");
+    }
+
+    #[test]
+    fn test_functions() {
+        assert_eq!(eval(r#"lower("FRIEZA")"#), "frieza");
+        assert_eq!(eval(r#"trim("  ginyu  ")"#), "ginyu");
+        assert_eq!(eval(r#"split("a,b,,c", ",")"#), "[a, b, , c]");
+        assert_eq!(eval(r#"join(["goku", "vegeta"], " & ")"#), "goku & vegeta");
+        assert_eq!(eval(r#"[contains("namek", "me"), starts_with("namek", "na"), ends_with("namek", "na")]"#), "[true, true, false]");
+        assert_eq!(eval(r#"replace("kakarot kakarot", "kaka", "ca")"#), "carot carot");
+        assert_eq!(eval("len([1, 2, 3])"), "3");
+    }
+
+    #[test]
+    fn test_conversions() {
+        assert_eq!(eval("to_string(1.5) + to_string([1, true])"), "1.5[1, true]");
+        assert_eq!(eval(r#"parse_int(" 42 ") + 1"#), "43");
+        assert_eq!(eval(r#"parse_int("4x2")"#), "nil");
+        assert_eq!(eval(r#"parse_int("-123456789012345678901234567890") * 10"#), "-1234567890123456789012345678900");
+        assert_eq!(eval(r#""${pow(2, 64)}""#), "18446744073709551616");
+        assert_eq!(eval(r#"parse_float("2.5")"#), "2.5");
+        assert_eq!(eval(r#"parse_float("nope")"#), "nil");
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let s = "let goku = {\"name\": \"goku\", \"level\": 9000}\n\"${goku[\"name\"]} is at ${goku[\"level\"] + 1}, \\${not} ${[1.5, nil]}\"";
+        assert_eq!(eval(s), "goku is at 9001, ${not} [1.5, nil]");
+        assert_eq!(eval(r#""outer ${"inner ${1 + 1}"}""#), "outer inner 2");
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error(r#"substring("abc", 2, 4)"#), "substring 2..4 is out of range for a string of length 3");
+        assert_eq!(error(r#"split("abc", "")"#), "split expects a non-empty separator");
+        assert_eq!(error("len(1)"), "len expects a string, an array or a map but got int");
+        assert_eq!(error(r#"upper(1)"#), "expected string but got int");
+    }
+}
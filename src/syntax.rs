@@ -0,0 +1,491 @@
+use crate::ast::{Expr, Stmt};
+use crate::lexer::{self, Spans};
+use crate::parser;
+use crate::token::Token;
+
+// a lossless view of source text for tools that rewrite it, like a formatter. every byte of the source
+// belongs to exactly one token or piece of trivia, so printing a tree back out gives the source unchanged,
+// even when the source has errors.
+//
+// the tree has a node for each statement and expression the parser finds, holding the tokens it was parsed
+// from, and each node carries the id of its `ast::Stmt` or `ast::Expr`. a statement that doesn't parse is an
+// `Error` node, and the tokens outside any statement, like the newlines between them, belong to the root
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TriviaKind {
+    // spaces, tabs and carriage returns, since newlines are tokens
+    Whitespace,
+    // `//` comments, including `////` ones, but not `///` doc comments, which are tokens
+    LineComment,
+    BlockComment,
+    // characters the lexer ignores
+    Skipped,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Trivia {
+    pub kind: TriviaKind,
+    pub text: String,
+}
+
+// a token with the text it was scanned from and the trivia between it and the token before it
+#[derive(Clone, Debug, PartialEq)]
+pub struct SyntaxToken {
+    pub kind: Token,
+    pub text: String,
+    pub leading: Vec<Trivia>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum NodeKind {
+    Root,
+    // a statement that doesn't parse, up to the end of the line where the parser picked up again
+    Error,
+    // the statements, where a declaration starts at its doc comment
+    Fun, Return, Expression, Let, Print, If, For, While, Struct, Protocol, Enum, Break, Continue,
+    // the expressions
+    Binary, Unary, Int, Float, String, BigInt, Interpolation, Grouping, Name, Bool, Nil, Assign, CompoundAssign,
+    Array, Map, Index, SetIndex, Range, Call, Get, Set, SelfRef, ImplicitMember, Lambda,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Node {
+    pub kind: NodeKind,
+    // the id of the ast node it was built from, which ids nodes in the order they start with a node before
+    // the nodes inside it. the root and `Error` nodes have none
+    pub id: Option<usize>,
+    pub children: Vec<Element>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Element {
+    Node(Node),
+    Token(SyntaxToken),
+}
+
+// the tokens of `source` with their trivia, ending with an `Eof` that holds the trivia at the end
+pub fn tokens(source: &str) -> Vec<SyntaxToken> {
+    let (tokens, spans) = lexer::scan_lenient(source);
+    syntax_tokens(source, tokens, &spans)
+}
+
+fn syntax_tokens(source: &str, tokens: Vec<Token>, spans: &Spans) -> Vec<SyntaxToken> {
+    let mut end = 0;
+    tokens.into_iter().zip(spans).map(|(kind, span)| {
+        let leading = trivia(&source[end..span.start]);
+        end = span.end;
+        SyntaxToken { kind, text: source[span.clone()].to_string(), leading }
+    }).collect()
+}
+
+// the tree of `source`, with the statements that parse as nodes of their statements and expressions
+pub fn parse(source: &str) -> Node {
+    let (kinds, spans) = lexer::scan_lenient(source);
+    let tokens = syntax_tokens(source, kinds.clone(), &spans);
+    let parsed = parser::parse_lenient(kinds, spans);
+
+    let mut kinds = Kinds(vec![NodeKind::Error; parsed.nodes.len()]);
+    kinds.stmts(&parsed.program);
+    let mut nodes: Vec<(std::ops::Range<usize>, NodeKind, Option<usize>)> = parsed.nodes.into_iter().enumerate()
+        .map(|(id, range)| (range, kinds.0[id], Some(id)))
+        .chain(parsed.skipped.into_iter().map(|range| (range, NodeKind::Error, None)))
+        .collect();
+    // outermost first, which the ids already are when two nodes cover the same tokens
+    nodes.sort_by_key(|(range, _, _)| (range.start, std::cmp::Reverse(range.end)));
+
+    let mut builder = Builder { open: vec![(Node { kind: NodeKind::Root, id: None, children: vec![] }, usize::MAX)] };
+    let mut nodes = nodes.into_iter().peekable();
+    for (i, token) in tokens.into_iter().enumerate() {
+        while builder.open.last().is_some_and(|(_, end)| *end <= i) {
+            builder.close();
+        }
+        while let Some((range, kind, id)) = nodes.next_if(|(range, _, _)| range.start == i) {
+            builder.open.push((Node { kind, id, children: vec![] }, range.end));
+        }
+        builder.open.last_mut().unwrap().0.children.push(Element::Token(token));
+    }
+    while builder.open.len() > 1 {
+        builder.close();
+    }
+    builder.open.pop().unwrap().0
+}
+
+impl Node {
+    // the source text the node was built from
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        for token in self.tokens() {
+            for trivia in &token.leading {
+                text.push_str(&trivia.text);
+            }
+            text.push_str(&token.text);
+        }
+        text
+    }
+
+    // every token in the node in source order
+    pub fn tokens(&self) -> Vec<&SyntaxToken> {
+        let mut tokens = vec![];
+        for child in &self.children {
+            match child {
+                Element::Node(node) => tokens.extend(node.tokens()),
+                Element::Token(token) => tokens.push(token),
+            }
+        }
+        tokens
+    }
+}
+
+struct Builder {
+    // the nodes that haven't ended yet, innermost last, with the token each ends before
+    open: Vec<(Node, usize)>,
+}
+
+impl Builder {
+    fn close(&mut self) {
+        let (node, _) = self.open.pop().unwrap();
+        self.open.last_mut().unwrap().0.children.push(Element::Node(node));
+    }
+}
+
+// the kind of each node of a program, indexed by its id
+struct Kinds(Vec<NodeKind>);
+
+impl Kinds {
+    fn stmts(&mut self, stmts: &[Stmt]) {
+        for stmt in stmts {
+            self.stmt(stmt);
+        }
+    }
+
+    fn exprs<'a>(&mut self, exprs: impl IntoIterator<Item = &'a Expr>) {
+        for expr in exprs {
+            self.expr(expr);
+        }
+    }
+
+    fn stmt(&mut self, stmt: &Stmt) {
+        self.0[stmt.id()] = match stmt {
+            Stmt::FunDeclaration { body, .. } => {
+                self.stmts(body);
+                NodeKind::Fun
+            }
+            Stmt::Return { expr, .. } => {
+                self.exprs(expr);
+                NodeKind::Return
+            }
+            Stmt::Expression { expr, .. } => {
+                self.expr(expr);
+                NodeKind::Expression
+            }
+            Stmt::Let { expr, .. } => {
+                self.expr(expr);
+                NodeKind::Let
+            }
+            Stmt::Print { expr, .. } => {
+                self.expr(expr);
+                NodeKind::Print
+            }
+            Stmt::If { condition, consequence, alternative, .. } => {
+                self.expr(condition);
+                self.stmts(consequence);
+                self.stmts(alternative.as_deref().unwrap_or_default());
+                NodeKind::If
+            }
+            Stmt::For { iterable, body, .. } => {
+                self.expr(iterable);
+                self.stmts(body);
+                NodeKind::For
+            }
+            Stmt::While { condition, body, .. } => {
+                self.expr(condition);
+                self.stmts(body);
+                NodeKind::While
+            }
+            Stmt::Struct { protocols, methods, .. } => {
+                self.exprs(protocols);
+                self.stmts(methods);
+                NodeKind::Struct
+            }
+            Stmt::Protocol { .. } => NodeKind::Protocol,
+            Stmt::Enum { .. } => NodeKind::Enum,
+            Stmt::Break { .. } => NodeKind::Break,
+            Stmt::Continue { .. } => NodeKind::Continue,
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        self.0[expr.id()] = match expr {
+            Expr::Binary { left, right, .. } => {
+                self.exprs([left.as_ref(), right]);
+                NodeKind::Binary
+            }
+            Expr::Unary { right, .. } => {
+                self.expr(right);
+                NodeKind::Unary
+            }
+            Expr::Int { .. } => NodeKind::Int,
+            Expr::Float { .. } => NodeKind::Float,
+            Expr::String { .. } => NodeKind::String,
+            Expr::BigInt { .. } => NodeKind::BigInt,
+            Expr::Interpolation { parts, .. } => {
+                self.exprs(parts);
+                NodeKind::Interpolation
+            }
+            Expr::Grouping { expr, .. } => {
+                self.expr(expr);
+                NodeKind::Grouping
+            }
+            Expr::Name { .. } => NodeKind::Name,
+            Expr::Bool { .. } => NodeKind::Bool,
+            Expr::Nil { .. } => NodeKind::Nil,
+            Expr::Assign { expr, .. } => {
+                self.expr(expr);
+                NodeKind::Assign
+            }
+            Expr::CompoundAssign { target, expr, .. } => {
+                self.exprs([target.as_ref(), expr]);
+                NodeKind::CompoundAssign
+            }
+            Expr::Array { elements, .. } => {
+                self.exprs(elements);
+                NodeKind::Array
+            }
+            Expr::Map { entries, .. } => {
+                self.exprs(entries.iter().flat_map(|(key, value)| [key, value]));
+                NodeKind::Map
+            }
+            Expr::Index { object, index, .. } => {
+                self.exprs([object.as_ref(), index]);
+                NodeKind::Index
+            }
+            Expr::SetIndex { object, index, expr, .. } => {
+                self.exprs([object.as_ref(), index, expr]);
+                NodeKind::SetIndex
+            }
+            Expr::Range { start, end, .. } => {
+                self.exprs([start.as_ref(), end]);
+                NodeKind::Range
+            }
+            Expr::Call { callee, args, .. } => {
+                self.expr(callee);
+                self.exprs(args);
+                NodeKind::Call
+            }
+            Expr::Get { object, .. } => {
+                self.expr(object);
+                NodeKind::Get
+            }
+            Expr::Set { object, expr, .. } => {
+                self.exprs([object.as_ref(), expr]);
+                NodeKind::Set
+            }
+            Expr::SelfRef { .. } => NodeKind::SelfRef,
+            Expr::ImplicitMember { .. } => NodeKind::ImplicitMember,
+            Expr::Lambda { body, .. } => {
+                self.stmts(body);
+                NodeKind::Lambda
+            }
+        }
+    }
+}
+
+fn trivia(text: &str) -> Vec<Trivia> {
+    let mut pieces: Vec<Trivia> = vec![];
+    let mut rest = text;
+    while !rest.is_empty() {
+        let (kind, len) = if rest.starts_with("//") {
+            (TriviaKind::LineComment, rest.find('\n').unwrap_or(rest.len()))
+        } else if rest.starts_with("/*") {
+            (TriviaKind::BlockComment, block_comment_len(rest))
+        } else if rest.starts_with([' ', '\t', '\r']) {
+            (TriviaKind::Whitespace, rest.find(|c| !matches!(c, ' ' | '\t' | '\r')).unwrap_or(rest.len()))
+        } else {
+            (TriviaKind::Skipped, rest.chars().next().map_or(1, char::len_utf8))
+        };
+        match pieces.last_mut() {
+            Some(last) if last.kind == TriviaKind::Skipped && kind == TriviaKind::Skipped => last.text.push_str(&rest[..len]),
+            _ => pieces.push(Trivia { kind, text: rest[..len].to_string() }),
+        }
+        rest = &rest[len..];
+    }
+    pieces
+}
+
+// block comments nest the same way the lexer reads them
+fn block_comment_len(text: &str) -> usize {
+    let bytes = text.as_bytes();
+    let mut depth = 0;
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i..].starts_with(b"/*") {
+            depth += 1;
+            i += 2;
+        } else if bytes[i..].starts_with(b"*/") {
+            depth -= 1;
+            i += 2;
+            if depth == 0 {
+                break;
+            }
+        } else {
+            i += 1;
+        }
+    }
+    i
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::ast::Stmt;
+    use crate::lexer::scan;
+    use crate::token::Token;
+    use super::*;
+
+    const SOURCE: &str = r#"/* a program
+   /* with nested comments */ */
+/// a saiyan
+struct Saiyan: Warrior
+    let name:   string  // trailing
+    fun greet()
+        print "hi ${self.name}, ${ {"a": [1, (2)]}["a"] } and ${"${1}"}!"
+    end
+end
+
+protocol Warrior
+    fun fight()
+end
+
+let goku =Saiyan("goku")	@
+if goku is Warrior
+    let f = fun(x) x ** 2 end
+else
+    print r"raw \n" + """
+        triple
+        """
+end
+   // the end
+"#;
+
+    #[test]
+    fn test_round_trip() {
+        for source in [SOURCE, "", "\n\n", "let x = 1", "   ", "1 /* */", "(1\n", "end )", "\r\n1\r\n"] {
+            assert_eq!(parse(source).text(), source);
+        }
+    }
+
+    #[test]
+    fn test_round_trip_malformed() {
+        let malformed = [
+            "print \"unterminated", "print \"a ${b", "print \"a ${b} c", "\"${", "\"${\"${1}", "}\"", "\"\\q\" \"\\u{zz}\"",
+            "\"\"\"never closed\n", "r\"raw", "/* unterminated /* nested */", "// no newline", "0x 0b2 1e 1__0 1.5e400 0xffff_ffff_ffff_ffff_ff",
+            "let é = 1 # ~ ` \\", "\"\"\"a ${", "1.. 1...2 ..= =>",
+        ];
+        for source in malformed {
+            assert_eq!(parse(source).text(), source);
+        }
+        // every prefix of a program, which cuts strings, comments and numbers off part way
+        for (end, _) in SOURCE.char_indices() {
+            assert_eq!(parse(&SOURCE[..end]).text(), &SOURCE[..end]);
+        }
+    }
+
+    #[test]
+    fn test_tokens_match_the_lexer() {
+        let kinds: Vec<Token> = tokens(SOURCE).into_iter().map(|t| t.kind).collect();
+        assert_eq!(kinds, scan(SOURCE).unwrap().0);
+    }
+
+    #[test]
+    fn test_trivia() {
+        let tokens = tokens("let x = 1 // one\n  /* a */ x @ ");
+        let trivia = |i: usize| -> Vec<(TriviaKind, &str)> {
+            tokens[i].leading.iter().map(|t| (t.kind, t.text.as_str())).collect()
+        };
+        assert_eq!(tokens[0].text, "let");
+        assert_eq!(trivia(1), vec![(TriviaKind::Whitespace, " ")]);
+        assert_eq!(trivia(4), vec![(TriviaKind::Whitespace, " "), (TriviaKind::LineComment, "// one")]);
+        assert_eq!(tokens[4].text, "\n");
+        assert_eq!(trivia(5), vec![
+            (TriviaKind::Whitespace, "  "),
+            (TriviaKind::BlockComment, "/* a */"),
+            (TriviaKind::Whitespace, " "),
+        ]);
+        assert_eq!(tokens[6].kind, Token::Eof);
+        assert_eq!(trivia(6), vec![
+            (TriviaKind::Whitespace, " "),
+            (TriviaKind::Skipped, "@"),
+            (TriviaKind::Whitespace, " "),
+        ]);
+    }
+
+    // the first node among a node's children of the kind
+    fn child(node: &Node, kind: NodeKind) -> &Node {
+        node.children.iter().find_map(|c| match c {
+            Element::Node(node) if node.kind == kind => Some(node),
+            _ => None,
+        }).unwrap()
+    }
+
+    #[test]
+    fn test_structure() {
+        let root = parse(SOURCE);
+        let nodes: Vec<(NodeKind, &str)> = root.children.iter().filter_map(|c| match c {
+            Element::Node(node) => Some((node.kind, node.tokens()[0].text.as_str())),
+            Element::Token(_) => None,
+        }).collect();
+        assert_eq!(nodes, vec![
+            (NodeKind::Struct, "/// a saiyan"),
+            (NodeKind::Protocol, "protocol"),
+            (NodeKind::Let, "let"),
+            (NodeKind::If, "if"),
+        ]);
+
+        // the method holds its print, which holds the interpolated string and the expressions in it
+        let greet = child(child(&root, NodeKind::Struct), NodeKind::Fun);
+        let template = child(child(greet, NodeKind::Print), NodeKind::Interpolation);
+        assert!(template.text().ends_with("}!\""));
+        assert_eq!(child(template, NodeKind::Get).text(), "self.name");
+        assert_eq!(greet.tokens().last().unwrap().kind, Token::End);
+        assert_eq!(root.tokens().last().unwrap().kind, Token::Eof);
+    }
+
+    #[test]
+    fn test_nodes_have_the_ids_of_the_ast() {
+        let root = parse(SOURCE);
+        let program = crate::parser::parse(SOURCE).unwrap();
+        let let_node = child(&root, NodeKind::Let);
+        let Stmt::Let { expr, id, .. } = &program[2] else { panic!("expected a let") };
+        assert_eq!(let_node.id, Some(*id));
+        assert_eq!(child(let_node, NodeKind::Call).id, Some(expr.id()));
+        assert_eq!(child(let_node, NodeKind::Call).text(), "Saiyan(\"goku\")");
+
+        // ids go in the order the nodes start, with a node before the nodes inside it
+        fn collect(node: &Node, ids: &mut Vec<usize>) {
+            ids.extend(node.id);
+            for child in &node.children {
+                if let Element::Node(child) = child {
+                    collect(child, ids);
+                }
+            }
+        }
+        let mut found = vec![];
+        collect(&root, &mut found);
+        assert_eq!(found, (0..found.len()).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_errors() {
+        let root = parse("let x = 1\nlet = 2\nprint x +\nend\nprint x\n");
+        let nodes: Vec<(NodeKind, Option<usize>, std::string::String)> = root.children.iter().filter_map(|c| match c {
+            Element::Node(node) => Some((node.kind, node.id, node.text())),
+            Element::Token(_) => None,
+        }).collect();
+        assert_eq!(nodes, vec![
+            (NodeKind::Let, Some(0), "let x = 1".to_string()),
+            (NodeKind::Error, None, "let = 2\n".to_string()),
+            (NodeKind::Error, None, "print x +\n".to_string()),
+            (NodeKind::Error, None, "end\n".to_string()),
+            (NodeKind::Print, Some(2), "print x".to_string()),
+        ]);
+    }
+}
@@ -2,12 +2,25 @@
 // use crate::parser::Parser;
 
 #[derive(PartialEq, Debug, Clone)]
-pub(crate) enum Token {
+pub enum Token {
     Eof,
-    LParen, RParen, Colon, Comma, Semicolon,
-    Let, If, Else, Fun, For, Return, End, True, False, Nil, Print, In,
-    Ident(String), Int(i32), Float(f64), String(String),
+    LParen, RParen, LBracket, RBracket, LBrace, RBrace, Colon, Comma, Semicolon, Dot, DotDot, DotDotEq,
+    Let, If, Else, Fun, For, While, Break, Continue, Return, End, True, False, Nil, Print, In,
+    Struct, SelfKw, Enum, Protocol, Is,
+    Ident(String), Int(i64), Float(f64), String(String),
+    // the digits of a decimal literal too large for an Int
+    BigInt(String),
+    // the text of a string before a `${`, followed by the tokens of the expression and the rest of the string
+    Interpolation(String),
+    // the text between the `}` ending one interpolated expression and the `${` starting the next
+    InterpolationMiddle(String),
+    // the text after the last `}`, up to the closing quote
+    InterpolationEnd(String),
+    // the text of a `///` comment after the slashes and a space
+    Doc(String),
     Eq, EqEq, Plus, Minus, Slash, Star, Bang, BangEq, Lt, LtEq, Gt, GtEq,
+    Percent, StarStar, Div, Amp, Pipe, Caret, Tilde, LtLt, GtGt,
+    PlusEq, MinusEq, StarEq, SlashEq, Arrow,
     And, Or,
     NewLine,
 }
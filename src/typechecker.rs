@@ -0,0 +1,1126 @@
+use std::collections::HashMap;
+use std::fmt;
+use crate::ast::{Expr, Param, Stmt};
+use crate::error::Error;
+use crate::parser::Program;
+use crate::token::Token;
+use crate::visitor::{ExprVisitor, StmtVisitor};
+
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) enum Type {
+    Int, Float, String, Bool, Nil, Array, Map, Range,
+    // an instance of a struct, a case of an enum, or any value conforming to a protocol
+    Struct(String), Enum(String), Protocol(String),
+    // the declarations themselves, as in `Saiyan(...)`, `Form.base` and `goku is Warrior`
+    StructDecl(String), EnumDecl(String), ProtocolDecl(String),
+    Function(Vec<Type>, Box<Type>),
+    // whatever unannotated code produces, which is never a type error
+    Any,
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Type::Int => write!(f, "int"),
+            Type::Float => write!(f, "float"),
+            Type::String => write!(f, "string"),
+            Type::Bool => write!(f, "bool"),
+            Type::Nil => write!(f, "nil"),
+            Type::Array => write!(f, "array"),
+            Type::Map => write!(f, "map"),
+            Type::Range => write!(f, "range"),
+            Type::Struct(name) | Type::Enum(name) | Type::Protocol(name) => write!(f, "{}", name),
+            Type::StructDecl(name) => write!(f, "struct {}", name),
+            Type::EnumDecl(name) => write!(f, "enum {}", name),
+            Type::ProtocolDecl(name) => write!(f, "protocol {}", name),
+            Type::Function(params, ret) => {
+                let params: Vec<String> = params.iter().map(|p| p.to_string()).collect();
+                write!(f, "fun({}) -> {}", params.join(", "), ret)
+            }
+            Type::Any => write!(f, "any"),
+        }
+    }
+}
+
+#[derive(Default)]
+struct StructInfo {
+    fields: Vec<(String, Type)>,
+    methods: HashMap<String, Type>,
+    protocols: Vec<String>,
+}
+
+struct TypeChecker {
+    // the globals are scopes[0]
+    scopes: Vec<HashMap<String, Type>>,
+    structs: HashMap<String, StructInfo>,
+    enums: HashMap<String, Vec<String>>,
+    protocols: HashMap<String, HashMap<String, Type>>,
+    // the name and declared return type of each function being checked, innermost last
+    returns: Vec<(String, Type)>,
+    // the struct whose methods are being checked, whose members method bodies can use by bare name
+    current_struct: Option<String>,
+    errors: Vec<Error>,
+}
+
+pub(crate) fn check(program: &Program) -> Result<(), Vec<Error>> {
+    let mut checker = TypeChecker::new();
+    checker.check(program);
+    if checker.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(checker.errors)
+    }
+}
+
+impl TypeChecker {
+    fn new() -> Self {
+        TypeChecker {
+            scopes: vec![HashMap::new()],
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            protocols: HashMap::new(),
+            returns: vec![],
+            current_struct: None,
+            errors: vec![],
+        }
+    }
+
+    fn check(&mut self, program: &Program) {
+        // type names first so signatures can refer to types declared after them
+        for stmt in program {
+            self.declare_type(stmt);
+        }
+        for stmt in program {
+            self.declare_signatures(stmt);
+        }
+
+        for stmt in program {
+            self.check_stmt(stmt);
+        }
+    }
+
+    fn declare_type(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Struct { name, protocols, .. } => {
+                let name = Self::ident(name).to_string();
+                let protocols = protocols.iter().map(|p| p.string()).collect();
+                self.structs.insert(name.clone(), StructInfo { protocols, ..Default::default() });
+                self.scopes[0].insert(name.clone(), Type::StructDecl(name));
+            }
+            Stmt::Enum { name, cases, .. } => {
+                let name = Self::ident(name).to_string();
+                self.enums.insert(name.clone(), cases.iter().map(|c| Self::ident(c).to_string()).collect());
+                self.scopes[0].insert(name.clone(), Type::EnumDecl(name));
+            }
+            Stmt::Protocol { name, .. } => {
+                let name = Self::ident(name).to_string();
+                self.protocols.insert(name.clone(), HashMap::new());
+                self.scopes[0].insert(name.clone(), Type::ProtocolDecl(name));
+            }
+            _ => (),
+        }
+    }
+
+    // unknown type names are taken as `any` here and reported when the declaration itself is checked
+    fn declare_signatures(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FunDeclaration { name, params, return_type, .. } => {
+                let signature = self.signature(params, return_type);
+                self.scopes[0].insert(Self::ident(name).to_string(), signature);
+            }
+            Stmt::Struct { name, fields, methods, .. } => {
+                let fields = fields.iter()
+                    .map(|f| (Self::ident(&f.name).to_string(), self.annotation(&Some(f.type_name.clone()))))
+                    .collect();
+                let mut signatures = HashMap::new();
+                for method in methods {
+                    if let Stmt::FunDeclaration { name, params, return_type, .. } = method {
+                        signatures.insert(Self::ident(name).to_string(), self.signature(params, return_type));
+                    }
+                }
+                let info = self.structs.get_mut(Self::ident(name)).expect("declared struct");
+                info.fields = fields;
+                info.methods = signatures;
+            }
+            Stmt::Protocol { name, methods, .. } => {
+                let methods = methods.iter()
+                    .map(|m| (Self::ident(&m.name).to_string(), self.signature(&m.params, &m.return_type)))
+                    .collect();
+                self.protocols.insert(Self::ident(name).to_string(), methods);
+            }
+            _ => (),
+        }
+    }
+
+    fn signature(&self, params: &[Param], return_type: &Option<Token>) -> Type {
+        let params = params.iter().map(|p| self.annotation(&p.type_name)).collect();
+        Type::Function(params, Box::new(self.annotation(return_type)))
+    }
+
+    fn resolve(&self, name: &str) -> Option<Type> {
+        match name {
+            "int" => Some(Type::Int),
+            "float" => Some(Type::Float),
+            "string" => Some(Type::String),
+            "bool" => Some(Type::Bool),
+            "array" => Some(Type::Array),
+            "map" => Some(Type::Map),
+            "range" => Some(Type::Range),
+            "any" => Some(Type::Any),
+            _ if self.structs.contains_key(name) => Some(Type::Struct(name.to_string())),
+            _ if self.enums.contains_key(name) => Some(Type::Enum(name.to_string())),
+            _ if self.protocols.contains_key(name) => Some(Type::Protocol(name.to_string())),
+            _ => None,
+        }
+    }
+
+    // the type an annotation names, `any` when there is none
+    fn annotation(&self, type_name: &Option<Token>) -> Type {
+        type_name.as_ref().and_then(|t| self.resolve(Self::ident(t))).unwrap_or(Type::Any)
+    }
+
+    fn check_annotation(&mut self, type_name: &Option<Token>) {
+        if let Some(type_name) = type_name {
+            let name = Self::ident(type_name);
+            if self.resolve(name).is_none() {
+                self.errors.push(Error::new(&format!("unknown type '{}'", name)));
+            }
+        }
+    }
+
+    fn check_signature(&mut self, params: &[Param], return_type: &Option<Token>) {
+        for param in params {
+            self.check_annotation(&param.type_name);
+        }
+        self.check_annotation(return_type);
+    }
+
+    fn assignable(&self, from: &Type, to: &Type) -> bool {
+        match (from, to) {
+            (Type::Any, _) | (_, Type::Any) => true,
+            (Type::Int, Type::Float) => true,
+            (Type::Struct(s), Type::Protocol(p)) => self.structs.get(s).is_some_and(|i| i.protocols.contains(p)),
+            _ => from == to,
+        }
+    }
+
+    // checks `expr` where a value of type `expected` is wanted, which is what `.case` shorthand resolves against
+    fn check_expected(&mut self, expr: &Expr, expected: &Type) -> Type {
+        match (expr, expected) {
+            (Expr::ImplicitMember { name, .. }, Type::Enum(enum_name)) => {
+                self.case(enum_name, Self::ident(name));
+                expected.clone()
+            }
+            // the compiler reports shorthand it cannot infer
+            (Expr::ImplicitMember { .. }, _) => Type::Any,
+            _ => self.check_expr(expr),
+        }
+    }
+
+    // the type of `expr` when it cannot be used where `expected` is wanted
+    fn mismatch(&mut self, expr: &Expr, expected: &Type) -> Option<Type> {
+        let actual = self.check_expected(expr, expected);
+        if self.assignable(&actual, expected) { None } else { Some(actual) }
+    }
+
+    // arrays take int indices and maps take any key
+    fn check_index(&mut self, object: &Expr, index: &Expr) {
+        match self.check_expr(object) {
+            Type::Array => {
+                if let Some(actual) = self.mismatch(index, &Type::Int) {
+                    self.errors.push(Error::new(&format!("array index must be int but got {}", actual)));
+                }
+            }
+            Type::Map | Type::Any => {
+                self.check_expr(index);
+            }
+            t => {
+                self.errors.push(Error::new(&format!("cannot index {}", t)));
+                self.check_expr(index);
+            }
+        }
+    }
+
+    fn case(&mut self, enum_name: &str, case: &str) {
+        if !self.enums.get(enum_name).is_some_and(|cases| cases.iter().any(|c| c == case)) {
+            self.errors.push(Error::new(&format!("{} has no case '{}'", enum_name, case)));
+        }
+    }
+
+    fn member(&self, type_name: &str, member: &str) -> Option<Type> {
+        if let Some(methods) = self.protocols.get(type_name) {
+            return methods.get(member).cloned();
+        }
+        let info = self.structs.get(type_name)?;
+        info.fields.iter().find(|(name, _)| name == member).map(|(_, t)| t.clone())
+            .or_else(|| info.methods.get(member).cloned())
+    }
+
+    // locals shadow the current struct's members, which shadow globals
+    fn lookup(&self, name: &str) -> Option<Type> {
+        for scope in self.scopes[1..].iter().rev() {
+            if let Some(t) = scope.get(name) {
+                return Some(t.clone());
+            }
+        }
+        if let Some(t) = self.current_struct.as_ref().and_then(|s| self.member(s, name)) {
+            return Some(t);
+        }
+        self.scopes[0].get(name).cloned()
+    }
+
+    fn define(&mut self, name: &str, t: Type) {
+        self.scopes.last_mut().expect("scope").insert(name.to_string(), t);
+    }
+
+    fn check_block(&mut self, stmts: &[Stmt]) {
+        self.scopes.push(HashMap::new());
+        for stmt in stmts {
+            self.check_stmt(stmt);
+        }
+        self.scopes.pop();
+    }
+
+    fn function(&mut self, name: &str, params: &[Param], return_type: &Option<Token>, body: &[Stmt]) {
+        self.check_signature(params, return_type);
+        self.returns.push((name.to_string(), self.annotation(return_type)));
+
+        let mut scope = HashMap::new();
+        for param in params {
+            scope.insert(Self::ident(&param.name).to_string(), self.annotation(&param.type_name));
+        }
+        self.scopes.push(scope);
+        for stmt in body {
+            self.check_stmt(stmt);
+        }
+        self.scopes.pop();
+        self.returns.pop();
+    }
+
+    fn arguments(&mut self, name: &str, params: &[Type], args: &[Expr]) {
+        if params.len() != args.len() {
+            self.errors.push(Error::new(&format!("{} expects {} arguments but got {}", name, params.len(), args.len())));
+            for arg in args {
+                self.check_expr(arg);
+            }
+            return;
+        }
+        for (i, (arg, param)) in args.iter().zip(params).enumerate() {
+            if let Some(actual) = self.mismatch(arg, param) {
+                self.errors.push(Error::new(&format!(
+                    "argument {} of {} expects {} but got {}", i + 1, name, param, actual
+                )));
+            }
+        }
+    }
+
+    fn arithmetic(op: &Token, left: &Type, right: &Type) -> Option<Type> {
+        let numeric = |t: &Type| matches!(t, Type::Int | Type::Float | Type::Any);
+        match (left, right) {
+            (Type::Int, Type::Int) => Some(Type::Int),
+            (Type::String, Type::String | Type::Any) | (Type::Any, Type::String) if *op == Token::Plus => Some(Type::String),
+            (Type::Any, _) | (_, Type::Any) if numeric(left) && numeric(right) => Some(Type::Any),
+            _ if numeric(left) && numeric(right) => Some(Type::Float),
+            _ => None,
+        }
+    }
+
+    // bitwise operators only take ints
+    fn bitwise(left: &Type, right: &Type) -> Option<Type> {
+        match (left, right) {
+            (Type::Int, Type::Int) => Some(Type::Int),
+            (Type::Int | Type::Any, Type::Int | Type::Any) => Some(Type::Any),
+            _ => None,
+        }
+    }
+
+    fn operator(op: &Token) -> &str {
+        match op {
+            Token::Plus => "+",
+            Token::Minus => "-",
+            Token::Star => "*",
+            Token::Slash => "/",
+            Token::Percent => "%",
+            Token::StarStar => "**",
+            Token::Div => "div",
+            Token::Amp => "&",
+            Token::Pipe => "|",
+            Token::Caret => "^",
+            Token::Tilde => "~",
+            Token::LtLt => "<<",
+            Token::GtGt => ">>",
+            Token::Lt => "<",
+            Token::LtEq => "<=",
+            Token::Gt => ">",
+            Token::GtEq => ">=",
+            Token::Bang => "!",
+            _ => unreachable!(),
+        }
+    }
+
+    fn callee_name(callee: &Expr) -> String {
+        match callee {
+            Expr::Name { val, .. } => val.clone(),
+            Expr::Get { name, .. } => Self::ident(name).to_string(),
+            _ => "function".to_string(),
+        }
+    }
+
+    fn check_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { .. } => self.visit_expr_stmt(stmt),
+            Stmt::Let { .. } => self.visit_let(stmt),
+            Stmt::Print { .. } => self.visit_print(stmt),
+            Stmt::For { .. } => self.visit_for(stmt),
+            Stmt::If { .. } => self.visit_if(stmt),
+            Stmt::While { .. } => self.visit_while(stmt),
+            Stmt::Break { .. } => self.visit_break(stmt),
+            Stmt::Continue { .. } => self.visit_continue(stmt),
+            Stmt::FunDeclaration { .. } => self.visit_fun(stmt),
+            Stmt::Return { .. } => self.visit_return(stmt),
+            Stmt::Struct { .. } => self.visit_struct(stmt),
+            Stmt::Enum { .. } => self.visit_enum(stmt),
+            Stmt::Protocol { .. } => self.visit_protocol(stmt),
+        }
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Binary { .. } => self.visit_binary(expr),
+            Expr::Unary { .. } => self.visit_unary(expr),
+            Expr::Int { .. } => self.visit_int(expr),
+            Expr::Float { .. } => self.visit_float(expr),
+            Expr::BigInt { .. } => self.visit_big_int(expr),
+            Expr::String { .. } => self.visit_string(expr),
+            Expr::Bool { .. } => self.visit_bool(expr),
+            Expr::Nil { .. } => self.visit_nil(expr),
+            Expr::Interpolation { .. } => self.visit_interpolation(expr),
+            Expr::Grouping { .. } => self.visit_grouping(expr),
+            Expr::Name { .. } => self.visit_name(expr),
+            Expr::Assign { .. } => self.visit_assign(expr),
+            Expr::CompoundAssign { .. } => self.visit_compound_assign(expr),
+            Expr::Array { .. } => self.visit_array(expr),
+            Expr::Map { .. } => self.visit_map(expr),
+            Expr::Index { .. } => self.visit_index(expr),
+            Expr::SetIndex { .. } => self.visit_set_index(expr),
+            Expr::Range { .. } => self.visit_range(expr),
+            Expr::Call { .. } => self.visit_call(expr),
+            Expr::Get { .. } => self.visit_get(expr),
+            Expr::Set { .. } => self.visit_set(expr),
+            Expr::SelfRef { .. } => self.visit_self(expr),
+            Expr::ImplicitMember { .. } => self.check_expected(expr, &Type::Any),
+            Expr::Lambda { .. } => self.visit_lambda(expr),
+        }
+    }
+
+    fn ident(token: &Token) -> &str {
+        match token {
+            Token::Ident(name) => name,
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl StmtVisitor for TypeChecker {
+    type Output = ();
+
+    fn visit_expr_stmt(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Expression { expr, .. } => {
+                self.check_expr(expr);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_let(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Let { name, type_name: Some(type_name), expr, .. } => {
+                let type_name = Some(type_name.clone());
+                self.check_annotation(&type_name);
+                let declared = self.annotation(&type_name);
+                if let Some(actual) = self.mismatch(expr, &declared) {
+                    self.errors.push(Error::new(&format!(
+                        "cannot assign {} to '{}' of type {}", actual, Self::ident(name), declared
+                    )));
+                }
+                self.define(Self::ident(name), declared);
+            }
+            Stmt::Let { name, type_name: None, expr, .. } => {
+                // an unannotated `let` takes the type of its initializer, except that nil could later be anything
+                let inferred = match self.check_expr(expr) {
+                    Type::Nil => Type::Any,
+                    t => t,
+                };
+                self.define(Self::ident(name), inferred);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_print(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Print { expr, .. } => {
+                self.check_expr(expr);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_for(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::For { name, value: None, iterable, body, .. } => {
+                let element = match self.check_expr(iterable) {
+                    Type::Range => Type::Int,
+                    Type::String => Type::String,
+                    Type::Array | Type::Map | Type::Any => Type::Any,
+                    t => {
+                        self.errors.push(Error::new(&format!("cannot iterate over {}", t)));
+                        Type::Any
+                    }
+                };
+                self.scopes.push(HashMap::new());
+                self.define(Self::ident(name), element);
+                self.check_block(body);
+                self.scopes.pop();
+            }
+            // keys and values of a map, or indices and elements of an array
+            Stmt::For { name, value: Some(value), iterable, body, .. } => {
+                let key = match self.check_expr(iterable) {
+                    Type::Array => Type::Int,
+                    Type::Map | Type::Any => Type::Any,
+                    t => {
+                        self.errors.push(Error::new(&format!("cannot iterate over {} with two variables", t)));
+                        Type::Any
+                    }
+                };
+                self.scopes.push(HashMap::new());
+                self.define(Self::ident(name), key);
+                self.define(Self::ident(value), Type::Any);
+                self.check_block(body);
+                self.scopes.pop();
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_if(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::If { condition, consequence, alternative, .. } => {
+                self.check_expr(condition);
+                self.check_block(consequence);
+                if let Some(alternative) = alternative {
+                    self.check_block(alternative);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_while(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::While { condition, body, .. } => {
+                self.check_expr(condition);
+                self.check_block(body);
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_break(&mut self, _stmt: &Stmt) {}
+
+    fn visit_continue(&mut self, _stmt: &Stmt) {}
+
+    fn visit_fun(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::FunDeclaration { name, params, return_type, body, .. } => {
+                let name = Self::ident(name);
+                // top level functions were declared up front, nested ones can call themselves
+                if self.scopes.len() > 1 {
+                    let signature = self.signature(params, return_type);
+                    self.define(name, signature);
+                }
+                let current_struct = self.current_struct.take();
+                self.function(name, params, return_type, body);
+                self.current_struct = current_struct;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_return(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Return { expr, .. } => {
+                let Some((name, expected)) = self.returns.last().cloned() else {
+                    if let Some(expr) = expr {
+                        self.check_expr(expr);
+                    }
+                    return;
+                };
+                let actual = match expr {
+                    Some(expr) => match self.mismatch(expr, &expected) {
+                        Some(actual) => actual,
+                        None => return,
+                    },
+                    None if self.assignable(&Type::Nil, &expected) => return,
+                    None => Type::Nil,
+                };
+                self.errors.push(Error::new(&format!("{} returns {} but got {}", name, expected, actual)));
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_struct(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Struct { name, fields, methods, .. } => {
+                for field in fields {
+                    self.check_annotation(&Some(field.type_name.clone()));
+                }
+                let current_struct = self.current_struct.replace(Self::ident(name).to_string());
+                for method in methods {
+                    if let Stmt::FunDeclaration { name, params, return_type, body, .. } = method {
+                        self.function(Self::ident(name), params, return_type, body);
+                    }
+                }
+                self.current_struct = current_struct;
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_enum(&mut self, _stmt: &Stmt) {}
+
+    fn visit_protocol(&mut self, stmt: &Stmt) {
+        match stmt {
+            Stmt::Protocol { methods, .. } => {
+                for method in methods {
+                    self.check_signature(&method.params, &method.return_type);
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+impl ExprVisitor for TypeChecker {
+    type Output = Type;
+
+    fn visit_binary(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Binary { op: Token::And | Token::Or, left, right, .. } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                // either operand can be the result
+                if left == right { left } else { Type::Any }
+            }
+            Expr::Binary { op: Token::EqEq | Token::BangEq, left, right, .. } => {
+                let left = self.check_expr(left);
+                self.check_expected(right, &left);
+                Type::Bool
+            }
+            Expr::Binary { op: Token::Is, left, right, .. } => {
+                self.check_expr(left);
+                match self.check_expr(right) {
+                    Type::StructDecl(_) | Type::ProtocolDecl(_) | Type::Any => (),
+                    t => self.errors.push(Error::new(&format!("'is' expects a struct or protocol but got {}", t))),
+                }
+                Type::Bool
+            }
+            Expr::Binary { op, left, right, .. } => {
+                let left = self.check_expr(left);
+                let right = self.check_expr(right);
+                let comparison = matches!(op, Token::Lt | Token::LtEq | Token::Gt | Token::GtEq);
+                let bitwise = matches!(op, Token::Amp | Token::Pipe | Token::Caret | Token::LtLt | Token::GtGt);
+                let result = if comparison {
+                    // numbers compare with numbers and strings with strings
+                    Self::arithmetic(&Token::Plus, &left, &right).map(|_| Type::Bool)
+                } else if bitwise {
+                    Self::bitwise(&left, &right)
+                } else {
+                    Self::arithmetic(op, &left, &right)
+                };
+                result.unwrap_or_else(|| {
+                    self.errors.push(Error::new(&format!(
+                        "operator '{}' cannot be applied to {} and {}", Self::operator(op), left, right
+                    )));
+                    Type::Any
+                })
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_unary(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Unary { op: Token::Bang, right, .. } => {
+                self.check_expr(right);
+                Type::Bool
+            }
+            Expr::Unary { op, right, .. } => match self.check_expr(right) {
+                t @ (Type::Int | Type::Any) => t,
+                // `~` only takes ints
+                Type::Float if *op != Token::Tilde => Type::Float,
+                t => {
+                    self.errors.push(Error::new(&format!("operator '{}' cannot be applied to {}", Self::operator(op), t)));
+                    Type::Any
+                }
+            },
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_int(&mut self, _expr: &Expr) -> Type {
+        Type::Int
+    }
+
+    fn visit_float(&mut self, _expr: &Expr) -> Type {
+        Type::Float
+    }
+
+    fn visit_big_int(&mut self, _expr: &Expr) -> Type {
+        Type::Int
+    }
+
+    fn visit_string(&mut self, _expr: &Expr) -> Type {
+        Type::String
+    }
+
+    fn visit_bool(&mut self, _expr: &Expr) -> Type {
+        Type::Bool
+    }
+
+    fn visit_nil(&mut self, _expr: &Expr) -> Type {
+        Type::Nil
+    }
+
+    // any value can be interpolated, so the parts are only checked for their own errors
+    fn visit_interpolation(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Interpolation { parts, .. } => {
+                for part in parts {
+                    self.check_expr(part);
+                }
+                Type::String
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_grouping(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Grouping { expr, .. } => self.check_expr(expr),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_name(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Name { val, .. } => self.lookup(val).unwrap_or(Type::Any),
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_assign(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Assign { name, expr, .. } => {
+                let name = Self::ident(name);
+                let declared = self.lookup(name).unwrap_or(Type::Any);
+                if let Some(actual) = self.mismatch(expr, &declared) {
+                    self.errors.push(Error::new(&format!(
+                        "cannot assign {} to '{}' of type {}", actual, name, declared
+                    )));
+                }
+                declared
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    // checked as the assignment it stands for, `a.b = a.b + 1` for `a.b += 1`
+    fn visit_compound_assign(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::CompoundAssign { target, op, expr, id } => {
+                let value = Box::new(Expr::Binary { op: op.clone(), left: target.clone(), right: expr.clone(), id: *id });
+                let assignment = match target.as_ref() {
+                    Expr::Name { val, .. } => Expr::Assign { name: Token::Ident(val.clone()), expr: value, id: *id },
+                    Expr::Get { object, name, .. } => Expr::Set { object: object.clone(), name: name.clone(), expr: value, id: *id },
+                    Expr::Index { object, index, .. } => {
+                        Expr::SetIndex { object: object.clone(), index: index.clone(), expr: value, id: *id }
+                    }
+                    _ => unreachable!(),
+                };
+                self.check_expr(&assignment)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_array(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Array { elements, .. } => {
+                for element in elements {
+                    self.check_expr(element);
+                }
+                Type::Array
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_map(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Map { entries, .. } => {
+                for (key, value) in entries {
+                    self.check_expr(key);
+                    self.check_expr(value);
+                }
+                Type::Map
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_index(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Index { object, index, .. } => {
+                self.check_index(object, index);
+                Type::Any
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set_index(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::SetIndex { object, index, expr, .. } => {
+                self.check_index(object, index);
+                self.check_expr(expr)
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_range(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Range { start, end, .. } => {
+                for bound in [start, end] {
+                    if let Some(actual) = self.mismatch(bound, &Type::Int) {
+                        self.errors.push(Error::new(&format!("range bounds must be int but got {}", actual)));
+                    }
+                }
+                Type::Range
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_call(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Call { callee, args, .. } => {
+                let name = Self::callee_name(callee);
+                match self.check_expr(callee) {
+                    Type::Function(params, ret) => {
+                        self.arguments(&name, &params, args);
+                        *ret
+                    }
+                    Type::StructDecl(struct_name) => {
+                        let fields: Vec<Type> = self.structs[&struct_name].fields.iter().map(|(_, t)| t.clone()).collect();
+                        self.arguments(&name, &fields, args);
+                        Type::Struct(struct_name)
+                    }
+                    t => {
+                        if t != Type::Any {
+                            self.errors.push(Error::new(&format!("cannot call {}", t)));
+                        }
+                        for arg in args {
+                            self.check_expr(arg);
+                        }
+                        Type::Any
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_get(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Get { object, name, .. } => {
+                let name = Self::ident(name);
+                match self.check_expr(object) {
+                    Type::EnumDecl(enum_name) => {
+                        self.case(&enum_name, name);
+                        Type::Enum(enum_name)
+                    }
+                    Type::Struct(type_name) | Type::Protocol(type_name) => {
+                        self.member(&type_name, name).unwrap_or_else(|| {
+                            self.errors.push(Error::new(&format!("{} has no member '{}'", type_name, name)));
+                            Type::Any
+                        })
+                    }
+                    Type::Any => Type::Any,
+                    t => {
+                        self.errors.push(Error::new(&format!("{} has no member '{}'", t, name)));
+                        Type::Any
+                    }
+                }
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_set(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Set { object, name, expr, .. } => {
+                let name = Self::ident(name);
+                let declared = match self.check_expr(object) {
+                    Type::Struct(struct_name) => {
+                        let field = self.structs[&struct_name].fields.iter().find(|(f, _)| f == name).map(|(_, t)| t.clone());
+                        field.unwrap_or_else(|| {
+                            self.errors.push(Error::new(&format!("{} has no field '{}'", struct_name, name)));
+                            Type::Any
+                        })
+                    }
+                    Type::Any => Type::Any,
+                    t => {
+                        self.errors.push(Error::new(&format!("{} has no field '{}'", t, name)));
+                        Type::Any
+                    }
+                };
+                if let Some(actual) = self.mismatch(expr, &declared) {
+                    self.errors.push(Error::new(&format!(
+                        "cannot assign {} to '{}' of type {}", actual, name, declared
+                    )));
+                }
+                declared
+            }
+            _ => unreachable!(),
+        }
+    }
+
+    fn visit_self(&mut self, _expr: &Expr) -> Type {
+        match &self.current_struct {
+            Some(name) => Type::Struct(name.clone()),
+            None => Type::Any,
+        }
+    }
+
+    fn visit_lambda(&mut self, expr: &Expr) -> Type {
+        match expr {
+            Expr::Lambda { params, return_type, body, .. } => {
+                let current_struct = self.current_struct.take();
+                self.function("lambda", params, return_type, body);
+                self.current_struct = current_struct;
+                self.signature(params, return_type)
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parser::parse;
+    use super::check;
+
+    fn errors(s: &str) -> Vec<String> {
+        match check(&parse(s).unwrap()) {
+            Ok(()) => vec![],
+            Err(errors) => errors.into_iter().map(|e| e.message).collect(),
+        }
+    }
+
+    #[test]
+    fn test_annotated_program() {
+        let s = r#"
+        protocol Warrior
+            fun fight() -> int
+        end
+        enum Form
+            base, superSaiyan
+        end
+        struct Saiyan: Warrior
+            let name: string
+            let powerLevel: int
+            let currentForm: Form
+
+            fun fight() -> int
+                powerLevel += 1000
+                transform(.superSaiyan)
+                return powerLevel
+            end
+
+            fun transform(form: Form)
+                currentForm = form
+            end
+        end
+        fun battle(w: Warrior) -> int
+            return w.fight()
+        end
+        let goku = Saiyan("goku", 9000, .base)
+        let level: float = battle(goku)
+        goku.currentForm == .base
+        "#;
+        assert_eq!(errors(s), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_unknown_type() {
+        let s = r#"
+        enum Form
+            base
+        end
+        struct Saiyan
+            let currentForm: From
+        end
+        fun f(x: Int) -> strin
+        end
+        let y: Form = .base
+        "#;
+        assert_eq!(errors(s), vec!["unknown type 'From'", "unknown type 'Int'", "unknown type 'strin'"]);
+    }
+
+    #[test]
+    fn test_operands() {
+        assert_eq!(errors(r#""a" * true"#), vec!["operator '*' cannot be applied to string and bool"]);
+        assert_eq!(errors("1 < \"b\"\n"), vec!["operator '<' cannot be applied to int and string"]);
+        assert_eq!(errors("-\"a\"\n"), vec!["operator '-' cannot be applied to string"]);
+        assert_eq!(errors("\"a\" + \"b\" < \"c\"\n"), Vec::<String>::new());
+        assert_eq!(errors("1.5 & 1\n"), vec!["operator '&' cannot be applied to float and int"]);
+        assert_eq!(errors("1 << 2.0\n"), vec!["operator '<<' cannot be applied to int and float"]);
+        assert_eq!(errors("~1.5\n"), vec!["operator '~' cannot be applied to float"]);
+        assert_eq!(errors("\"a\" % 2\n"), vec!["operator '%' cannot be applied to string and int"]);
+        assert_eq!(errors("let x: int = 7 div 2 + 2 ** 3 % 5 | ~1 ^ 3 & 4 >> 1\nlet y: float = 7.5 div 2\n"), Vec::<String>::new());
+    }
+
+    #[test]
+    fn test_calls() {
+        let s = r#"
+        fun add(x: int, y: int) -> int
+            return x + y
+        end
+        add(1)
+        add(1, "2")
+        add(1, 2.5)
+        "#;
+        assert_eq!(errors(s), vec![
+            "add expects 2 arguments but got 1",
+            "argument 2 of add expects int but got string",
+            "argument 2 of add expects int but got float",
+        ]);
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let s = r#"
+        let double = fun(x: int) -> int x * 2 end
+        double("a")
+        let shout = fun(s: string) -> int s + "!" end
+        "#;
+        assert_eq!(errors(s), vec![
+            "argument 1 of double expects int but got string",
+            "lambda returns int but got string",
+        ]);
+    }
+
+    #[test]
+    fn test_return_type() {
+        let s = r#"
+        fun name() -> string
+            return 9000
+        end
+        fun level() -> int
+            return
+        end
+        fun untyped()
+            return "anything"
+        end
+        "#;
+        assert_eq!(errors(s), vec!["name returns string but got int", "level returns int but got nil"]);
+    }
+
+    #[test]
+    fn test_inference() {
+        let s = r#"
+        let x = 1
+        x = "one"
+        let y = x * 2.5
+        y = true
+        let z = nil
+        z = "anything"
+        let w: int = "w"
+        "#;
+        assert_eq!(errors(s), vec![
+            "cannot assign string to 'x' of type int",
+            "cannot assign bool to 'y' of type float",
+            "cannot assign string to 'w' of type int",
+        ]);
+    }
+
+    #[test]
+    fn test_members() {
+        let s = r#"
+        enum Form
+            base
+        end
+        struct Saiyan
+            let powerLevel: int
+        end
+        let goku = Saiyan("9000")
+        goku.power
+        goku.powerLevel = "over 9000"
+        let f: Form = .super
+        Form.ultra
+        "#;
+        assert_eq!(errors(s), vec![
+            "argument 1 of Saiyan expects int but got string",
+            "Saiyan has no member 'power'",
+            "cannot assign string to 'powerLevel' of type int",
+            "Form has no case 'super'",
+            "Form has no case 'ultra'",
+        ]);
+    }
+
+    #[test]
+    fn test_indexing() {
+        let s = r#"
+        let m: map = {"a": 1}
+        let a: array = [1]
+        let n: int = 1
+        m[1.5] = a["0"]
+        n[0]
+        for k, v in "abc"
+        end
+        for k, v in m
+        end
+        "#;
+        assert_eq!(errors(s), vec![
+            "array index must be int but got string",
+            "cannot index int",
+            "cannot iterate over string with two variables",
+        ]);
+    }
+
+    #[test]
+    fn test_is() {
+        let s = r#"
+        protocol Warrior
+        end
+        struct Saiyan: Warrior
+        end
+        enum Form
+            base
+        end
+        let goku = Saiyan()
+        print goku is Saiyan
+        print goku is Warrior
+        print goku is Form
+        print goku is 1
+        "#;
+        assert_eq!(errors(s), vec![
+            "'is' expects a struct or protocol but got enum Form",
+            "'is' expects a struct or protocol but got int",
+        ]);
+    }
+}
@@ -1,18 +1,131 @@
-use std::collections::hash_set::Union;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::bigint::BigInt;
+use crate::error::Error;
+use crate::vm::VM;
 
+// a value the vm works with, which embedders convert to and from rust values with `FromValue` and `IntoValue`
 #[derive(Clone)]
-pub(crate) struct Value {
+pub struct Value {
     pub(crate) value_type: ValueType,
     pub(crate) val: Val,
 }
 
 #[derive(Clone, Copy)]
 pub(crate) union Val {
-    pub(crate) i: i32,
+    pub(crate) i: i64,
     pub(crate) f: f64,
+    pub(crate) b: bool,
+    // index of an `Obj` in the vm heap
+    pub(crate) o: usize,
 }
 
-#[derive(Clone)]
+#[derive(Clone, PartialEq, Debug)]
 pub(crate) enum ValueType {
-    Int, Float,
+    Int, Float, Bool, Nil, Obj,
+}
+
+impl Value {
+    pub(crate) fn int(i: i64) -> Self {
+        Value { value_type: ValueType::Int, val: Val { i } }
+    }
+
+    pub(crate) fn float(f: f64) -> Self {
+        Value { value_type: ValueType::Float, val: Val { f } }
+    }
+
+    pub(crate) fn bool(b: bool) -> Self {
+        Value { value_type: ValueType::Bool, val: Val { b } }
+    }
+
+    pub(crate) fn nil() -> Self {
+        Value { value_type: ValueType::Nil, val: Val { i: 0 } }
+    }
+
+    pub(crate) fn obj(o: usize) -> Self {
+        Value { value_type: ValueType::Obj, val: Val { o } }
+    }
+}
+
+pub(crate) enum Obj {
+    String(String),
+    // an int too large for 64 bits; ints that fit are never stored as one
+    BigInt(BigInt),
+    Array(Vec<Value>),
+    Map(Map),
+    Range { start: i64, end: i64 },
+    // `address` is where the function starts in `code`, the chunk it was compiled in
+    Function { name: String, arity: u8, code: Rc<[u8]>, address: usize },
+    // a function with the heap indices of the upvalues it captured
+    Closure { function: Value, upvalues: Vec<usize> },
+    Upvalue(Upvalue),
+    // a rust function registered by an embedder
+    Native { name: String, arity: u8, function: Rc<NativeFn> },
+    // protocols holds the heap indices of the protocols the struct conforms to
+    Struct { name: String, fields: Vec<String>, methods: HashMap<String, Value>, protocols: Vec<usize> },
+    Protocol { name: String },
+    // fields are stored in the order the struct declares them
+    Instance { struct_obj: usize, fields: Vec<Value> },
+    BoundMethod { receiver: Value, method: Value },
+    // each case is allocated once with the enum, so cases compare by identity
+    Enum { name: String, cases: Vec<String>, values: Vec<Value> },
+    EnumCase { enum_obj: usize, case: usize },
+    // a slot the collector freed
+    Free,
+}
+
+// what a map hashes a key as; enum cases are allocated once, so their heap index identifies them
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub(crate) enum Key {
+    Int(i64),
+    BigInt(BigInt),
+    Bool(bool),
+    String(String),
+    Case(usize),
+}
+
+// a hash map that keeps its entries in the order they were first inserted
+#[derive(Default)]
+pub(crate) struct Map {
+    entries: Vec<(Value, Value)>,
+    indices: HashMap<Key, usize>,
+}
+
+impl Map {
+    pub(crate) fn get(&self, key: &Key) -> Option<&Value> {
+        self.indices.get(key).map(|&i| &self.entries[i].1)
+    }
+
+    pub(crate) fn insert(&mut self, key: Key, key_value: Value, value: Value) {
+        match self.indices.get(&key) {
+            Some(&i) => self.entries[i].1 = value,
+            None => {
+                self.indices.insert(key, self.entries.len());
+                self.entries.push((key_value, value));
+            }
+        }
+    }
+
+    pub(crate) fn remove(&mut self, key: &Key) -> Option<Value> {
+        let i = self.indices.remove(key)?;
+        let (_, value) = self.entries.remove(i);
+        for index in self.indices.values_mut() {
+            if *index > i {
+                *index -= 1;
+            }
+        }
+        Some(value)
+    }
+
+    pub(crate) fn entries(&self) -> &[(Value, Value)] {
+        &self.entries
+    }
+}
+
+pub(crate) type NativeFn = dyn Fn(&mut VM, &[Value]) -> Result<Value, Error>;
+
+// a captured local, which points into the stack until the local goes out of scope and then holds the value itself
+pub(crate) enum Upvalue {
+    Open(usize),
+    Closed(Value),
 }
@@ -1,11 +1,47 @@
 use crate::ast::{Expr, Stmt};
 
 pub(crate) trait ExprVisitor {
-    fn visit_binary(&mut self, expr: &Expr);
-    fn visit_int(&mut self, expr: &Expr);
-    fn visit_float(&mut self, expr: &Expr);
+    type Output;
+
+    fn visit_binary(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_unary(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_int(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_float(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_big_int(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_string(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_bool(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_nil(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_interpolation(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_grouping(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_name(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_assign(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_compound_assign(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_array(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_map(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_index(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_set_index(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_range(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_call(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_get(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_set(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_self(&mut self, expr: &Expr) -> Self::Output;
+    fn visit_lambda(&mut self, expr: &Expr) -> Self::Output;
 }
 
 pub(crate) trait StmtVisitor {
-    fn visit_expr_stmt(&mut self, stmt: &Stmt);
-}
\ No newline at end of file
+    type Output;
+
+    fn visit_expr_stmt(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_let(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_print(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_for(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_if(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_while(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_break(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_continue(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_fun(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_return(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_struct(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_enum(&mut self, stmt: &Stmt) -> Self::Output;
+    fn visit_protocol(&mut self, stmt: &Stmt) -> Self::Output;
+}
@@ -1,39 +1,131 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::rc::Rc;
+use crate::bigint::{BigInt, MAX_BITS};
+use crate::error::Error;
 use crate::opcodes::OpCode;
-use crate::value::{Value, ValueType, Val};
+use crate::value::{Key, Map, NativeFn, Obj, Upvalue, Value, ValueType, Val};
 
-pub(crate) struct VM {
+pub struct VM {
     ip: usize,
-    stack_pointer: usize,
+    // stack slot of the current frame's callee, which locals are relative to
+    base: usize,
+    // heap index of the current frame's closure, if it has upvalues
+    closure: Option<usize>,
     stack: Vec<Value>,
-    memory: Vec<u8>,
+    frames: Vec<Frame>,
+    // the chunk of code being run: a program's, or that of the function it called. functions keep the chunk
+    // they were compiled in, so a program's code is dropped once nothing from it is left to call
+    code: Rc<[u8]>,
+    heap: Vec<Obj>,
+    // heap slots the collector freed, which `alloc` fills before growing the heap
+    free: Vec<usize>,
+    // how many objects the heap may hold before the next collection
+    next_gc: usize,
+    globals: HashMap<String, Value>,
+    // heap indices of the upvalues still pointing into the stack
+    open_upvalues: Vec<usize>,
+}
+
+struct Frame {
+    return_ip: usize,
+    return_code: Rc<[u8]>,
+    base: usize,
+    closure: Option<usize>,
 }
 
 static DEBUG: bool = true;
+const FRAMES_MAX: usize = 1024;
+const STACK_MAX: usize = FRAMES_MAX * 256;
+const GC_MIN: usize = 1024;
 
 impl VM {
     pub(crate) fn new() -> Self {
         VM {
             ip: 0,
-            stack_pointer: 0,
+            base: 0,
+            closure: None,
             stack: vec![],
-            memory: vec![],
+            frames: vec![],
+            code: Rc::from([]),
+            heap: vec![],
+            free: vec![],
+            next_gc: GC_MIN,
+            globals: HashMap::new(),
+            open_upvalues: vec![],
         }
     }
 
-    pub(crate) fn interpret(&mut self, bytes: &[u8]) -> &Value {
-        // copy program into memory
-        for b in bytes {
-            self.memory.push(b.clone());
+    pub(crate) fn interpret(&mut self, bytes: &[u8]) -> Result<Value, Error> {
+        // a previous program may have stopped on an error partway through a call
+        self.close_upvalues(0);
+        self.stack.clear();
+        self.frames.clear();
+        self.base = 0;
+        self.closure = None;
+        // what the previous program left behind is only reachable from globals now
+        self.collect();
+
+        self.code = Rc::from(bytes);
+        self.ip = 0;
+        self.run(0)
+    }
+
+    pub(crate) fn define_native(&mut self, name: &str, arity: u8, function: Rc<NativeFn>) {
+        let val = self.alloc(Obj::Native { name: name.to_string(), arity, function });
+        self.define_global(name, val);
+    }
+
+    pub(crate) fn define_global(&mut self, name: &str, value: Value) {
+        self.globals.insert(name.to_string(), value);
+    }
+
+    pub(crate) fn global(&self, name: &str) -> Option<Value> {
+        self.globals.get(name).cloned()
+    }
+
+    pub(crate) fn global_names(&self) -> Vec<String> {
+        self.globals.keys().cloned().collect()
+    }
+
+    // calls a function value from outside the bytecode, running it to completion
+    pub(crate) fn call_value(&mut self, callee: Value, args: Vec<Value>) -> Result<Value, Error> {
+        let (ip, code, base, closure, depth) = (self.ip, self.code.clone(), self.base, self.closure, self.frames.len());
+        let slot = self.stack.len();
+        let argc = args.len();
+        self.push(callee);
+        for arg in args {
+            self.push(arg);
+        }
+
+        let result = self.call_at(argc).and_then(|_| {
+            // natives and struct constructors have already left their result
+            if self.frames.len() > depth { self.run(depth + 1) } else { Ok(self.pop()) }
+        });
+        if result.is_err() {
+            self.close_upvalues(slot);
+            self.stack.truncate(slot);
+            self.frames.truncate(depth);
+            (self.ip, self.code, self.base, self.closure) = (ip, code, base, closure);
         }
+        result
+    }
 
-        while self.ip < bytes.len() {
+    // runs until the frame at `depth` returns, or to the end of the program when `depth` is 0
+    fn run(&mut self, depth: usize) -> Result<Value, Error> {
+        while self.ip < self.code.len() {
+            // natives running a nested `run` may hold values only rust can see, so only the outermost one collects
+            if depth == 0 && self.heap.len() - self.free.len() > self.next_gc {
+                self.collect();
+            }
             if DEBUG {
                 // todo!("dissemble val")
             }
 
-            let b = self.memory[self.ip]
-                .try_into()
-                .expect(&format!("Invalid OpCode {} at instruction {}", bytes[self.ip], self.ip));
+            let b = match self.code[self.ip].try_into() {
+                Ok(b) => b,
+                Err(_) => return Err(Error::new(&format!("Invalid OpCode {} at instruction {}", self.code[self.ip], self.ip))),
+            };
 
             self.ip += 1;
             match b {
@@ -41,30 +133,232 @@ impl VM {
                     if DEBUG {
                         // todo!("dissemble val")
                     }
-                    return self.peek();
+                    match self.frames.pop() {
+                        None => return Ok(self.peek().clone()),
+                        Some(frame) => {
+                            let result = self.pop();
+                            self.close_upvalues(self.base);
+                            self.stack.truncate(self.base);
+                            self.ip = frame.return_ip;
+                            self.code = frame.return_code;
+                            self.base = frame.base;
+                            self.closure = frame.closure;
+                            if self.frames.len() < depth {
+                                return Ok(result);
+                            }
+                            self.push(result);
+                        }
+                    }
+                }
+                OpCode::Int => self.add_int(),
+                OpCode::Negate => self.negate()?,
+                OpCode::Add => self.add()?,
+                OpCode::Subtract => self.subtract()?,
+                OpCode::Multiply => self.multiply()?,
+                OpCode::Divide => self.divide()?,
+                OpCode::Float => self.add_float(),
+                OpCode::BigInt => {
+                    let digits = self.read_string();
+                    let n = BigInt::parse(&digits).expect("the compiler only emits decimal digits");
+                    let val = self.integer(n);
+                    self.push(val);
+                }
+                OpCode::Nil => self.push(Value::nil()),
+                OpCode::True => self.push(Value::bool(true)),
+                OpCode::False => self.push(Value::bool(false)),
+                OpCode::String => {
+                    let string = self.read_string();
+                    let val = self.alloc(Obj::String(string));
+                    self.push(val);
+                }
+                OpCode::Pop => { self.pop(); }
+                OpCode::Dup => self.push(self.peek().clone()),
+                OpCode::DupTwo => {
+                    let below = self.stack[self.stack.len() - 2].clone();
+                    self.push(below);
+                    self.push(self.stack[self.stack.len() - 2].clone());
+                }
+                OpCode::Stringify => {
+                    let val = self.pop();
+                    let string = self.stringify(&val);
+                    let val = self.alloc(Obj::String(string));
+                    self.push(val);
+                }
+                OpCode::Print => {
+                    let val = self.pop();
+                    println!("{}", self.stringify(&val));
+                }
+                OpCode::DefineGlobal => {
+                    let name = self.read_string();
+                    let val = self.pop();
+                    self.globals.insert(name, val);
+                }
+                OpCode::GetGlobal => {
+                    let name = self.read_string();
+                    match self.globals.get(&name) {
+                        Some(val) => self.push(val.clone()),
+                        None => return Err(Error::new(&format!("Undefined variable '{}'", name))),
+                    }
+                }
+                OpCode::SetGlobal => {
+                    let name = self.read_string();
+                    let val = self.peek().clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = val,
+                        None => return Err(Error::new(&format!("Undefined variable '{}'", name))),
+                    }
+                }
+                OpCode::GetLocal => {
+                    let slot = self.base + self.read_u16() as usize;
+                    self.push(self.stack[slot].clone());
+                }
+                OpCode::SetLocal => {
+                    let slot = self.base + self.read_u16() as usize;
+                    self.stack[slot] = self.peek().clone();
+                }
+                OpCode::Not => {
+                    let val = self.pop();
+                    self.push(Value::bool(is_falsey(&val)));
                 }
-                OpCode::Int => self.add_int(bytes),
-                OpCode::Negate => self.negate(),
-                OpCode::Add => self.add(),
-                OpCode::Subtract => self.subtract(),
-                OpCode::Multiply => self.multiply(),
-                OpCode::Divide => self.divide(),
-                OpCode::Float => self.add_float(bytes),
+                OpCode::Loop => {
+                    let offset = self.read_u16() as usize;
+                    self.ip -= offset;
+                }
+                OpCode::Array => {
+                    let count = self.read_u16() as usize;
+                    let elements = self.stack.split_off(self.stack.len() - count);
+                    let val = self.alloc(Obj::Array(elements));
+                    self.push(val);
+                }
+                OpCode::Map => {
+                    let count = self.read_u16() as usize;
+                    let values = self.stack.split_off(self.stack.len() - count * 2);
+                    let mut map = Map::default();
+                    for pair in values.chunks(2) {
+                        map.insert(self.key(&pair[0])?, pair[0].clone(), pair[1].clone());
+                    }
+                    let val = self.alloc(Obj::Map(map));
+                    self.push(val);
+                }
+                OpCode::GetIndex => self.get_index()?,
+                OpCode::SetIndex => self.set_index()?,
+                OpCode::Range => self.range(false)?,
+                OpCode::RangeInclusive => self.range(true)?,
+                OpCode::ForNext => self.for_next(false)?,
+                OpCode::ForNextPair => self.for_next(true)?,
+                OpCode::Jump => {
+                    let offset = self.read_u16() as usize;
+                    self.ip += offset;
+                }
+                OpCode::JumpIfFalse => {
+                    let offset = self.read_u16() as usize;
+                    if is_falsey(self.peek()) {
+                        self.ip += offset;
+                    }
+                }
+                OpCode::Equal => {
+                    let b = self.pop();
+                    let a = self.pop();
+                    let equal = self.values_equal(&a, &b);
+                    self.push(Value::bool(equal));
+                }
+                OpCode::Greater => self.compare(|o| o == Ordering::Greater)?,
+                OpCode::Less => self.compare(|o| o == Ordering::Less)?,
+                OpCode::GreaterEqual => self.compare(|o| o != Ordering::Less)?,
+                OpCode::LessEqual => self.compare(|o| o != Ordering::Greater)?,
+                OpCode::Function => {
+                    let arity = self.read_byte();
+                    let address = self.read_u32() as usize;
+                    let name = self.read_string();
+                    let val = self.alloc(Obj::Function { name, arity, code: self.code.clone(), address });
+                    self.push(val);
+                }
+                OpCode::Closure => self.closure(),
+                OpCode::GetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = self.upvalue(index);
+                    let val = match &self.heap[upvalue] {
+                        Obj::Upvalue(Upvalue::Open(slot)) => self.stack[*slot].clone(),
+                        Obj::Upvalue(Upvalue::Closed(val)) => val.clone(),
+                        _ => unreachable!("upvalue is not an upvalue"),
+                    };
+                    self.push(val);
+                }
+                OpCode::SetUpvalue => {
+                    let index = self.read_u16() as usize;
+                    let upvalue = self.upvalue(index);
+                    let val = self.peek().clone();
+                    match &mut self.heap[upvalue] {
+                        Obj::Upvalue(Upvalue::Open(slot)) => self.stack[*slot] = val,
+                        Obj::Upvalue(Upvalue::Closed(closed)) => *closed = val,
+                        _ => unreachable!("upvalue is not an upvalue"),
+                    }
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.pop();
+                }
+                OpCode::Call => self.call()?,
+                OpCode::Struct => self.define_struct()?,
+                OpCode::GetField => self.get_field()?,
+                OpCode::SetField => self.set_field()?,
+                OpCode::Enum => self.define_enum(),
+                OpCode::Protocol => {
+                    let name = self.read_string();
+                    let val = self.alloc(Obj::Protocol { name });
+                    self.push(val);
+                }
+                OpCode::Is => self.is()?,
+                OpCode::Modulo => self.modulo()?,
+                OpCode::Power => self.power()?,
+                OpCode::FloorDivide => self.floor_divide()?,
+                OpCode::BitAnd => self.bitwise(|a, b| a & b, BigInt::and)?,
+                OpCode::BitOr => self.bitwise(|a, b| a | b, BigInt::or)?,
+                OpCode::BitXor => self.bitwise(|a, b| a ^ b, BigInt::xor)?,
+                OpCode::BitNot => self.bit_not()?,
+                OpCode::ShiftLeft => self.shift(true)?,
+                OpCode::ShiftRight => self.shift(false)?,
             }
         }
 
-        todo!("error");
+        // running off the end returns the trailing expression's value, or nil
+        Ok(self.stack.last().cloned().unwrap_or_else(Value::nil))
+    }
+
+    fn read_byte(&mut self) -> u8 {
+        let byte = self.code[self.ip];
+        self.ip += 1;
+        byte
+    }
+
+    fn read_u16(&mut self) -> u16 {
+        u16::from_be_bytes([self.read_byte(), self.read_byte()])
+    }
+
+    fn read_u32(&mut self) -> u32 {
+        u32::from_be_bytes([self.read_byte(), self.read_byte(), self.read_byte(), self.read_byte()])
+    }
+
+    fn read_string(&mut self) -> String {
+        let len = self.read_u32() as usize;
+        let bytes = &self.code[self.ip..self.ip + len];
+        self.ip += len;
+        String::from_utf8(bytes.to_vec()).expect("invalid utf-8 in string constant")
     }
 
-    fn add_int(&mut self, bytes: &[u8]) {
-        let byte1 = bytes[self.ip];
-        let byte2 = bytes[self.ip + 1];
-        let byte3 = bytes[self.ip + 2];
-        let byte4 = bytes[self.ip + 3];
+    fn add_int(&mut self) {
+        let byte1 = self.code[self.ip];
+        let byte2 = self.code[self.ip + 1];
+        let byte3 = self.code[self.ip + 2];
+        let byte4 = self.code[self.ip + 3];
+        let byte5 = self.code[self.ip + 4];
+        let byte6 = self.code[self.ip + 5];
+        let byte7 = self.code[self.ip + 6];
+        let byte8 = self.code[self.ip + 7];
 
-        let num = i32::from_be_bytes([byte1, byte2, byte3, byte4]);
+        let num = i64::from_be_bytes([byte1, byte2, byte3, byte4, byte5, byte6, byte7, byte8]);
 
-        self.ip += 4;
+        self.ip += 8;
 
         let val = Value {
             value_type: ValueType::Int,
@@ -74,15 +368,15 @@ impl VM {
         self.push(val);
     }
 
-    fn add_float(&mut self, bytes: &[u8]) {
-        let byte1 = bytes[self.ip];
-        let byte2 = bytes[self.ip + 1];
-        let byte3 = bytes[self.ip + 2];
-        let byte4 = bytes[self.ip + 3];
-        let byte5 = bytes[self.ip + 4];
-        let byte6 = bytes[self.ip + 5];
-        let byte7 = bytes[self.ip + 6];
-        let byte8 = bytes[self.ip + 7];
+    fn add_float(&mut self) {
+        let byte1 = self.code[self.ip];
+        let byte2 = self.code[self.ip + 1];
+        let byte3 = self.code[self.ip + 2];
+        let byte4 = self.code[self.ip + 3];
+        let byte5 = self.code[self.ip + 4];
+        let byte6 = self.code[self.ip + 5];
+        let byte7 = self.code[self.ip + 6];
+        let byte8 = self.code[self.ip + 7];
 
         let num = f64::from_be_bytes([byte1, byte2, byte3, byte4, byte5, byte6, byte7, byte8]);
 
@@ -96,15 +390,416 @@ impl VM {
         self.push(val);
     }
 
-    fn negate(&mut self) {
+    fn range(&mut self, inclusive: bool) -> Result<(), Error> {
+        let end = self.pop();
+        let start = self.pop();
+
+        match (&start.value_type, &end.value_type) {
+            (ValueType::Int, ValueType::Int) => {
+                let start = unsafe { start.val.i };
+                let end = unsafe { end.val.i };
+                // ranges are stored half-open
+                let end = if inclusive {
+                    end.checked_add(1).ok_or_else(|| Error::new("Range end is too large"))?
+                } else {
+                    end
+                };
+                let val = self.alloc(Obj::Range { start, end });
+                self.push(val);
+            }
+            _ => return Err(Error::new("Range bounds must be ints")),
+        }
+        Ok(())
+    }
+
+    // advances the loop whose sequence is in `slot` and whose position is in the slot after it,
+    // pushing the next element or jumping past the loop when the sequence is exhausted.
+    // a loop with two variables gets the key and value of a map entry or the index and element of an array
+    fn for_next(&mut self, pair: bool) -> Result<(), Error> {
+        let slot = self.base + self.read_u16() as usize;
+        let exit = self.read_u16() as usize;
+
+        let seq = self.stack[slot].clone();
+        let index = unsafe { self.stack[slot + 1].val.i } as usize;
+
+        let unsupported = || match pair {
+            true => Error::new("Can only iterate over maps and arrays with two variables"),
+            false => Error::new("Can only iterate over arrays, maps, strings and ranges"),
+        };
+        if seq.value_type != ValueType::Obj {
+            return Err(unsupported());
+        }
+
+        // the values to push and the position after them
+        let next = match &self.heap[unsafe { seq.val.o }] {
+            Obj::Array(elements) if pair => elements.get(index).map(|e| (Value::int(index as i64), Some(e.clone()), index + 1)),
+            Obj::Array(elements) => elements.get(index).map(|e| (e.clone(), None, index + 1)),
+            // a single variable gets the keys
+            Obj::Map(map) => map.entries().get(index).map(|(k, v)| (k.clone(), pair.then(|| v.clone()), index + 1)),
+            _ if pair => return Err(unsupported()),
+            Obj::Range { start, end } => {
+                let i = start + index as i64;
+                if i < *end { Some((Value::int(i), None, index + 1)) } else { None }
+            }
+            // strings are walked by character, keeping the byte offset of the next one
+            Obj::String(string) => match string[index..].chars().next() {
+                Some(c) => {
+                    let val = self.alloc(Obj::String(c.to_string()));
+                    Some((val, None, index + c.len_utf8()))
+                }
+                None => None,
+            },
+            _ => return Err(unsupported()),
+        };
+
+        match next {
+            Some((first, second, index)) => {
+                self.stack[slot + 1] = Value::int(index as i64);
+                self.push(first);
+                if let Some(second) = second {
+                    self.push(second);
+                }
+            }
+            None => self.ip += exit,
+        }
+        Ok(())
+    }
+
+    // what a value hashes as when used as a map key
+    pub(crate) fn key(&self, value: &Value) -> Result<Key, Error> {
+        match value.value_type {
+            ValueType::Int => return Ok(Key::Int(unsafe { value.val.i })),
+            ValueType::Bool => return Ok(Key::Bool(unsafe { value.val.b })),
+            ValueType::Obj => match &self.heap[unsafe { value.val.o }] {
+                Obj::String(string) => return Ok(Key::String(string.clone())),
+                Obj::BigInt(n) => return Ok(Key::BigInt(n.clone())),
+                Obj::EnumCase { .. } => return Ok(Key::Case(unsafe { value.val.o })),
+                _ => (),
+            },
+            _ => (),
+        }
+        Err(Error::new(&format!("Unhashable map key of type {}", self.type_name(value))))
+    }
+
+    fn get_index(&mut self) -> Result<(), Error> {
+        let index = self.pop();
+        let object = self.pop();
+        let val = match self.obj(&object) {
+            Some(Obj::Array(elements)) => elements[self.array_index(&index, elements.len())?].clone(),
+            Some(Obj::Map(map)) => match map.get(&self.key(&index)?) {
+                Some(val) => val.clone(),
+                None => return Err(Error::new(&format!("Map has no key {}", self.stringify(&index)))),
+            },
+            _ => return Err(Error::new(&format!("Cannot index {}", self.type_name(&object)))),
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    fn set_index(&mut self) -> Result<(), Error> {
+        let val = self.pop();
+        let index = self.pop();
+        let object = self.pop();
+        match self.obj(&object) {
+            Some(Obj::Array(elements)) => {
+                let i = self.array_index(&index, elements.len())?;
+                if let Obj::Array(elements) = &mut self.heap[unsafe { object.val.o }] {
+                    elements[i] = val.clone();
+                }
+            }
+            Some(Obj::Map(_)) => {
+                let key = self.key(&index)?;
+                if let Obj::Map(map) = &mut self.heap[unsafe { object.val.o }] {
+                    map.insert(key, index, val.clone());
+                }
+            }
+            _ => return Err(Error::new(&format!("Cannot index {}", self.type_name(&object)))),
+        }
+        self.push(val);
+        Ok(())
+    }
+
+    fn array_index(&self, index: &Value, len: usize) -> Result<usize, Error> {
+        if self.is_big(index) {
+            return Err(Error::new(&format!("Array index {} is out of bounds for length {}", self.stringify(index), len)));
+        }
+        if index.value_type != ValueType::Int {
+            return Err(Error::new(&format!("Array index must be an int but got {}", self.type_name(index))));
+        }
+        let i = unsafe { index.val.i };
+        if i < 0 || i as usize >= len {
+            return Err(Error::new(&format!("Array index {} is out of bounds for length {}", i, len)));
+        }
+        Ok(i as usize)
+    }
+
+    fn call(&mut self) -> Result<(), Error> {
+        let argc = self.read_byte() as usize;
+        self.call_at(argc)
+    }
+
+    // calls the value below the top `argc` values on the stack
+    fn call_at(&mut self, argc: usize) -> Result<(), Error> {
+        let slot = self.stack.len() - argc - 1;
+        let callee = self.stack[slot].clone();
+
+        if callee.value_type != ValueType::Obj {
+            return Err(Error::new("Can only call functions and structs"));
+        }
+
+        match &self.heap[unsafe { callee.val.o }] {
+            Obj::Function { .. } | Obj::Closure { .. } => self.call_function(&callee, argc, slot)?,
+            Obj::Struct { name, fields, .. } => {
+                if argc != fields.len() {
+                    return Err(Error::new(&format!("{} expects {} fields but got {}", name, fields.len(), argc)));
+                }
+                let fields = self.stack.split_off(slot + 1);
+                self.stack.truncate(slot);
+                let val = self.alloc(Obj::Instance { struct_obj: unsafe { callee.val.o }, fields });
+                self.push(val);
+            }
+            Obj::BoundMethod { receiver, method } => {
+                let method = method.clone();
+                // the receiver takes the callee's slot, becoming `self` in the method
+                self.stack[slot] = receiver.clone();
+                self.call_function(&method, argc, slot)?;
+            }
+            Obj::Native { name, arity, function } => {
+                if argc != *arity as usize {
+                    return Err(Error::new(&format!("{} expects {} arguments but got {}", name, arity, argc)));
+                }
+                let function = function.clone();
+                let args = self.stack.split_off(slot + 1);
+                self.stack.truncate(slot);
+                let val = function(self, &args)?;
+                self.push(val);
+            }
+            _ => return Err(Error::new("Can only call functions and structs")),
+        }
+        Ok(())
+    }
+
+    fn call_function(&mut self, function: &Value, argc: usize, slot: usize) -> Result<(), Error> {
+        let callee = unsafe { function.val.o };
+        let (function, closure) = match &self.heap[callee] {
+            Obj::Closure { function, .. } => (function.clone(), Some(callee)),
+            _ => (function.clone(), None),
+        };
+        let (code, address) = match &self.heap[unsafe { function.val.o }] {
+            Obj::Function { name, arity, code, address } => {
+                if argc != *arity as usize {
+                    return Err(Error::new(&format!("{} expects {} arguments but got {}", name, arity, argc)));
+                }
+                (code.clone(), *address)
+            }
+            _ => return Err(Error::new("Can only call functions and structs")),
+        };
+
+        if self.frames.len() >= FRAMES_MAX || self.stack.len() >= STACK_MAX {
+            return Err(Error::new("Stack overflow"));
+        }
+        let return_code = std::mem::replace(&mut self.code, code);
+        self.frames.push(Frame { return_ip: self.ip, return_code, base: self.base, closure: self.closure });
+        self.base = slot;
+        self.closure = closure;
+        self.ip = address;
+        Ok(())
+    }
+
+    // wraps the function on top of the stack with the upvalues it captures from the current frame
+    fn closure(&mut self) {
+        let function = self.pop();
+        let count = self.read_byte();
+        let mut upvalues = vec![];
+        for _ in 0..count {
+            let is_local = self.read_byte() == 1;
+            let index = self.read_u16() as usize;
+            let upvalue = if is_local { self.capture_upvalue(self.base + index) } else { self.upvalue(index) };
+            upvalues.push(upvalue);
+        }
+        let val = self.alloc(Obj::Closure { function, upvalues });
+        self.push(val);
+    }
+
+    // closures capturing the same local share one upvalue
+    fn capture_upvalue(&mut self, slot: usize) -> usize {
+        for &upvalue in &self.open_upvalues {
+            if matches!(self.heap[upvalue], Obj::Upvalue(Upvalue::Open(s)) if s == slot) {
+                return upvalue;
+            }
+        }
+        let upvalue = unsafe { self.alloc(Obj::Upvalue(Upvalue::Open(slot))).val.o };
+        self.open_upvalues.push(upvalue);
+        upvalue
+    }
+
+    // moves the values of locals at or above `from` into the upvalues that captured them
+    fn close_upvalues(&mut self, from: usize) {
+        let heap = &mut self.heap;
+        let stack = &self.stack;
+        self.open_upvalues.retain(|&upvalue| match &heap[upvalue] {
+            Obj::Upvalue(Upvalue::Open(slot)) if *slot >= from => {
+                heap[upvalue] = Obj::Upvalue(Upvalue::Closed(stack[*slot].clone()));
+                false
+            }
+            _ => true,
+        });
+    }
+
+    // the heap index of the current closure's upvalue at `index`
+    fn upvalue(&self, index: usize) -> usize {
+        match self.closure.map(|c| &self.heap[c]) {
+            Some(Obj::Closure { upvalues, .. }) => upvalues[index],
+            _ => panic!("Only closures have upvalues"),
+        }
+    }
+
+    fn define_struct(&mut self) -> Result<(), Error> {
+        let name = self.read_string();
+        let field_count = self.read_byte();
+        let fields: Vec<String> = (0..field_count).map(|_| self.read_string()).collect();
+        let method_count = self.read_byte() as usize;
+        let names: Vec<String> = (0..method_count).map(|_| self.read_string()).collect();
+
+        let protocol_count = self.read_byte() as usize;
+
+        let functions = self.stack.split_off(self.stack.len() - method_count);
+        let methods = names.into_iter().zip(functions).collect();
+        let protocols = self.stack.split_off(self.stack.len() - protocol_count)
+            .iter()
+            .map(|p| match p.value_type {
+                ValueType::Obj if matches!(self.heap[unsafe { p.val.o }], Obj::Protocol { .. }) => Ok(unsafe { p.val.o }),
+                _ => Err(Error::new(&format!("{} can only conform to protocols", name))),
+            })
+            .collect::<Result<_, _>>()?;
+        let val = self.alloc(Obj::Struct { name, fields, methods, protocols });
+        self.push(val);
+        Ok(())
+    }
+
+    // `value is Type` where the type is a struct or a protocol
+    fn is(&mut self) -> Result<(), Error> {
+        let type_val = self.pop();
+        let val = self.pop();
+
+        let type_obj = match type_val.value_type {
+            ValueType::Obj => unsafe { type_val.val.o },
+            _ => return Err(Error::new("Right operand of 'is' must be a struct or protocol")),
+        };
+        let struct_obj = match val.value_type {
+            ValueType::Obj => match &self.heap[unsafe { val.val.o }] {
+                Obj::Instance { struct_obj, .. } => Some(*struct_obj),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let result = match &self.heap[type_obj] {
+            Obj::Struct { .. } => struct_obj == Some(type_obj),
+            Obj::Protocol { .. } => struct_obj.is_some_and(|s| match &self.heap[s] {
+                Obj::Struct { protocols, .. } => protocols.contains(&type_obj),
+                _ => false,
+            }),
+            _ => return Err(Error::new("Right operand of 'is' must be a struct or protocol")),
+        };
+        self.push(Value::bool(result));
+        Ok(())
+    }
+
+    fn define_enum(&mut self) {
+        let name = self.read_string();
+        let count = self.read_byte();
+        let cases: Vec<String> = (0..count).map(|_| self.read_string()).collect();
+
+        let val = self.alloc(Obj::Enum { name, cases: cases.clone(), values: vec![] });
+        let enum_obj = unsafe { val.val.o };
+        let values: Vec<Value> = (0..cases.len()).map(|case| self.alloc(Obj::EnumCase { enum_obj, case })).collect();
+        if let Obj::Enum { values: v, .. } = &mut self.heap[enum_obj] {
+            *v = values;
+        }
+        self.push(val);
+    }
+
+    // looks up a field, or a method bound to the instance, or a case of an enum
+    fn get_field(&mut self) -> Result<(), Error> {
+        let name = self.read_string();
+        let object = self.pop();
+
+        if object.value_type == ValueType::Obj {
+            if let Obj::Enum { name: enum_name, cases, values } = &self.heap[unsafe { object.val.o }] {
+                let val = match cases.iter().position(|c| *c == name) {
+                    Some(i) => values[i].clone(),
+                    None => return Err(Error::new(&format!("{} has no case '{}'", enum_name, name))),
+                };
+                self.push(val);
+                return Ok(());
+            }
+        }
+
+        let struct_obj = self.instance(&object)?;
+
+        let val = match &self.heap[struct_obj] {
+            Obj::Struct { fields, methods, .. } => {
+                match fields.iter().position(|n| *n == name) {
+                    Some(i) => match &self.heap[unsafe { object.val.o }] {
+                        Obj::Instance { fields, .. } => fields[i].clone(),
+                        _ => unreachable!("checked by instance"),
+                    },
+                    None => match methods.get(&name) {
+                        Some(method) => {
+                            let method = method.clone();
+                            self.alloc(Obj::BoundMethod { receiver: object, method })
+                        }
+                        None => return Err(Error::new(&format!("Undefined property '{}'", name))),
+                    },
+                }
+            }
+            _ => unreachable!("instance of a non-struct"),
+        };
+
+        self.push(val);
+        Ok(())
+    }
+
+    fn set_field(&mut self) -> Result<(), Error> {
+        let name = self.read_string();
+        let val = self.pop();
+        let object = self.pop();
+        let struct_obj = self.instance(&object)?;
+
+        let index = match &self.heap[struct_obj] {
+            Obj::Struct { fields, .. } => fields.iter().position(|n| *n == name),
+            _ => unreachable!("instance of a non-struct"),
+        };
+        let index = index.ok_or_else(|| Error::new(&format!("Undefined field '{}'", name)))?;
+        if let Obj::Instance { fields, .. } = &mut self.heap[unsafe { object.val.o }] {
+            fields[index] = val.clone();
+        }
+
+        self.push(val);
+        Ok(())
+    }
+
+    // returns the heap index of the instance's struct
+    fn instance(&self, value: &Value) -> Result<usize, Error> {
+        if value.value_type == ValueType::Obj {
+            if let Obj::Instance { struct_obj, .. } = &self.heap[unsafe { value.val.o }] {
+                return Ok(*struct_obj);
+            }
+        }
+        Err(Error::new("Only instances have properties"))
+    }
+
+    fn negate(&mut self) -> Result<(), Error> {
         let val = self.pop();
         match val.value_type {
             ValueType::Int => {
                 let num = unsafe { val.val.i };
-                self.push(Value {
-                    value_type: ValueType::Int,
-                    val: Val { i: -num },
-                })
+                let val = match num.checked_neg() {
+                    Some(i) => Value::int(i),
+                    None => self.integer(BigInt::from(num).neg()),
+                };
+                self.push(val)
             }
             ValueType::Float => {
                 let num = unsafe { val.val.f };
@@ -113,11 +808,18 @@ impl VM {
                     val: Val { f: -num },
                 })
             }
-            _ => todo!("error"),
+            _ => match self.obj(&val) {
+                Some(Obj::BigInt(n)) => {
+                    let val = self.integer(n.neg());
+                    self.push(val)
+                }
+                _ => return Err(Error::new("Operand must be a number")),
+            },
         }
+        Ok(())
     }
 
-    fn add(&mut self) {
+    fn add(&mut self) -> Result<(), Error> {
         let b = self.pop();
         let a = self.pop();
 
@@ -125,10 +827,12 @@ impl VM {
             (ValueType::Int, ValueType::Int) => {
                 let num1 = unsafe { a.val.i };
                 let num2 = unsafe { b.val.i };
-                self.push(Value {
-                    value_type: ValueType::Int,
-                    val: Val { i: num1 + num2 },
-                })
+                let val = match num1.checked_add(num2) {
+                    Some(i) => Value::int(i),
+                    // promote to a big int instead of overflowing
+                    None => self.integer(BigInt::from(num1).add(&BigInt::from(num2))),
+                };
+                self.push(val)
             },
             (ValueType::Int, ValueType::Float) => {
                 let num1 = unsafe { a.val.i };
@@ -154,10 +858,24 @@ impl VM {
                     val: Val { f: num1 + num2 }
                 })
             }
+            _ if self.is_big(&a) || self.is_big(&b) => {
+                let val = self.arithmetic(&a, &b, |x, y| Some(x.add(y)), |x, y| x + y, "Operands must be two numbers or two strings")?;
+                self.push(val);
+            }
+            (ValueType::Obj, ValueType::Obj) => {
+                let string = match (&self.heap[unsafe { a.val.o }], &self.heap[unsafe { b.val.o }]) {
+                    (Obj::String(a), Obj::String(b)) => format!("{}{}", a, b),
+                    _ => return Err(Error::new("Operands must be two numbers or two strings")),
+                };
+                let val = self.alloc(Obj::String(string));
+                self.push(val);
+            }
+            _ => return Err(Error::new("Operands must be two numbers or two strings")),
         }
+        Ok(())
     }
 
-    fn subtract(&mut self) {
+    fn subtract(&mut self) -> Result<(), Error> {
         let b = self.pop();
         let a = self.pop();
 
@@ -165,10 +883,11 @@ impl VM {
             (ValueType::Int, ValueType::Int) => {
                 let num1 = unsafe { a.val.i };
                 let num2 = unsafe { b.val.i };
-                self.push(Value {
-                    value_type: ValueType::Int,
-                    val: Val { i: num1 - num2 },
-                })
+                let val = match num1.checked_sub(num2) {
+                    Some(i) => Value::int(i),
+                    None => self.integer(BigInt::from(num1).sub(&BigInt::from(num2))),
+                };
+                self.push(val)
             },
             (ValueType::Int, ValueType::Float) => {
                 let num1 = unsafe { a.val.i };
@@ -194,10 +913,16 @@ impl VM {
                     val: Val { f: num1 - num2 }
                 })
             }
+            _ if self.is_big(&a) || self.is_big(&b) => {
+                let val = self.arithmetic(&a, &b, |x, y| Some(x.sub(y)), |x, y| x - y, "Operands must be numbers")?;
+                self.push(val);
+            }
+            _ => return Err(Error::new("Operands must be numbers")),
         }
+        Ok(())
     }
 
-    fn multiply(&mut self) {
+    fn multiply(&mut self) -> Result<(), Error> {
         let b = self.pop();
         let a = self.pop();
 
@@ -205,10 +930,11 @@ impl VM {
             (ValueType::Int, ValueType::Int) => {
                 let num1 = unsafe { a.val.i };
                 let num2 = unsafe { b.val.i };
-                self.push(Value {
-                    value_type: ValueType::Int,
-                    val: Val { i: num1 * num2 },
-                })
+                let val = match num1.checked_mul(num2) {
+                    Some(i) => Value::int(i),
+                    None => self.integer(BigInt::from(num1).mul(&BigInt::from(num2))),
+                };
+                self.push(val)
             },
             (ValueType::Int, ValueType::Float) => {
                 let num1 = unsafe { a.val.i };
@@ -234,10 +960,16 @@ impl VM {
                     val: Val { f: num1 * num2 }
                 })
             }
+            _ if self.is_big(&a) || self.is_big(&b) => {
+                let val = self.arithmetic(&a, &b, |x, y| Some(x.mul(y)), |x, y| x * y, "Operands must be numbers")?;
+                self.push(val);
+            }
+            _ => return Err(Error::new("Operands must be numbers")),
         }
+        Ok(())
     }
 
-    fn divide(&mut self) {
+    fn divide(&mut self) -> Result<(), Error> {
         let b = self.pop();
         let a = self.pop();
 
@@ -245,10 +977,15 @@ impl VM {
             (ValueType::Int, ValueType::Int) => {
                 let num1 = unsafe { a.val.i };
                 let num2 = unsafe { b.val.i };
-                self.push(Value {
-                    value_type: ValueType::Int,
-                    val: Val { i: num1 / num2 },
-                })
+                if num2 == 0 {
+                    return Err(Error::new("Division by zero"));
+                }
+                let val = match num1.checked_div(num2) {
+                    Some(i) => Value::int(i),
+                    // only the most negative int divided by -1 overflows
+                    None => self.integer(BigInt::from(num1).neg()),
+                };
+                self.push(val)
             },
             (ValueType::Int, ValueType::Float) => {
                 let num1 = unsafe { a.val.i };
@@ -274,31 +1011,454 @@ impl VM {
                     val: Val { f: num1 / num2 }
                 })
             }
+            _ if self.is_big(&a) || self.is_big(&b) => {
+                let val = self.arithmetic(&a, &b, |x, y| x.div_rem(y).map(|(q, _)| q), |x, y| x / y, "Operands must be numbers")?;
+                self.push(val);
+            }
+            _ => return Err(Error::new("Operands must be numbers")),
         }
+        Ok(())
     }
 
-    fn push(&mut self, constant: Value) {
-        if self.stack.len() >= 255 {
-            panic!("Stack overflow");
+    fn compare(&mut self, test: fn(Ordering) -> bool) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+
+        let ordering = match (&a.value_type, &b.value_type) {
+            (ValueType::Int, ValueType::Int) => unsafe { a.val.i.partial_cmp(&b.val.i) },
+            (ValueType::Int, ValueType::Float) => unsafe { (a.val.i as f64).partial_cmp(&b.val.f) },
+            (ValueType::Float, ValueType::Int) => unsafe { a.val.f.partial_cmp(&(b.val.i as f64)) },
+            (ValueType::Float, ValueType::Float) => unsafe { a.val.f.partial_cmp(&b.val.f) },
+            _ if self.is_big(&a) || self.is_big(&b) => match self.operands(&a, &b) {
+                Some(Operands::Big(a, b)) => Some(a.cmp(&b)),
+                Some(Operands::Float(a, b)) => a.partial_cmp(&b),
+                None => return Err(Error::new("Operands must be two numbers or two strings")),
+            },
+            (ValueType::Obj, ValueType::Obj) => match (&self.heap[unsafe { a.val.o }], &self.heap[unsafe { b.val.o }]) {
+                (Obj::String(a), Obj::String(b)) => a.partial_cmp(b),
+                _ => return Err(Error::new("Operands must be two numbers or two strings")),
+            },
+            _ => return Err(Error::new("Operands must be two numbers or two strings")),
+        };
+
+        // comparisons involving NaN are always false
+        self.push(Value::bool(ordering.is_some_and(test)));
+        Ok(())
+    }
+
+    fn values_equal(&self, a: &Value, b: &Value) -> bool {
+        match (&a.value_type, &b.value_type) {
+            (ValueType::Int, ValueType::Int) => unsafe { a.val.i == b.val.i },
+            (ValueType::Int, ValueType::Float) => unsafe { a.val.i as f64 == b.val.f },
+            (ValueType::Float, ValueType::Int) => unsafe { a.val.f == b.val.i as f64 },
+            (ValueType::Float, ValueType::Float) => unsafe { a.val.f == b.val.f },
+            (ValueType::Bool, ValueType::Bool) => unsafe { a.val.b == b.val.b },
+            (ValueType::Nil, ValueType::Nil) => true,
+            (ValueType::Obj, ValueType::Obj) => {
+                let (a, b) = unsafe { (a.val.o, b.val.o) };
+                match (&self.heap[a], &self.heap[b]) {
+                    (Obj::String(a), Obj::String(b)) => a == b,
+                    (Obj::BigInt(a), Obj::BigInt(b)) => a == b,
+                    // everything else compares by identity
+                    _ => a == b,
+                }
+            }
+            // big ints never fit in an int, so they can only equal floats
+            (ValueType::Obj, ValueType::Float) | (ValueType::Float, ValueType::Obj) => {
+                matches!(self.operands(a, b), Some(Operands::Float(a, b)) if a == b)
+            }
+            _ => false,
+        }
+    }
+
+    // an int result, which is only kept as a big int when it doesn't fit in an int
+    pub(crate) fn integer(&mut self, n: BigInt) -> Value {
+        match n.to_i64() {
+            Some(i) => Value::int(i),
+            None => self.alloc(Obj::BigInt(n)),
+        }
+    }
+
+    fn is_big(&self, value: &Value) -> bool {
+        matches!(self.obj(value), Some(Obj::BigInt(_)))
+    }
+
+    // an int or big int as a big int
+    pub(crate) fn bigint(&self, value: &Value) -> Option<BigInt> {
+        match value.value_type {
+            ValueType::Int => Some(BigInt::from(unsafe { value.val.i })),
+            _ => match self.obj(value) {
+                Some(Obj::BigInt(n)) => Some(n.clone()),
+                _ => None,
+            },
         }
+    }
+
+    // any number as a float
+    pub(crate) fn float(&self, value: &Value) -> Option<f64> {
+        match value.value_type {
+            ValueType::Int => Some(unsafe { value.val.i } as f64),
+            ValueType::Float => Some(unsafe { value.val.f }),
+            _ => self.bigint(value).map(|n| n.to_f64()),
+        }
+    }
+
+    // two numbers as big ints, or as floats if either of them is a float the same way ints become floats
+    fn operands(&self, a: &Value, b: &Value) -> Option<Operands> {
+        match (self.bigint(a), self.bigint(b)) {
+            (Some(a), Some(b)) => Some(Operands::Big(a, b)),
+            _ => Some(Operands::Float(self.float(a)?, self.float(b)?)),
+        }
+    }
+
+    // arithmetic on any two numbers, where `big` gives none when dividing by zero
+    fn arithmetic(
+        &mut self,
+        a: &Value,
+        b: &Value,
+        big: fn(&BigInt, &BigInt) -> Option<BigInt>,
+        float: fn(f64, f64) -> f64,
+        message: &str,
+    ) -> Result<Value, Error> {
+        match self.operands(a, b) {
+            Some(Operands::Big(a, b)) => match big(&a, &b) {
+                Some(n) => Ok(self.integer(n)),
+                None => Err(Error::new("Division by zero")),
+            },
+            Some(Operands::Float(a, b)) => Ok(Value::float(float(a, b))),
+            None => Err(Error::new(message)),
+        }
+    }
+
+    // `div` rounds the quotient down rather than towards zero like `/` does on ints
+    fn floor_divide(&mut self) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let val = match (&a.value_type, &b.value_type) {
+            (ValueType::Int, ValueType::Int) => {
+                let num1 = unsafe { a.val.i };
+                match floor_div_mod(num1, unsafe { b.val.i })? {
+                    Some((q, _)) => Value::int(q),
+                    // only the most negative int divided by -1 overflows
+                    None => self.integer(BigInt::from(num1).neg()),
+                }
+            }
+            _ => self.arithmetic(&a, &b, |x, y| x.div_mod_floor(y).map(|(q, _)| q), |x, y| (x / y).floor(), "Operands must be numbers")?,
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    // the remainder of `div`, which has the sign of the divisor
+    fn modulo(&mut self) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let val = match (&a.value_type, &b.value_type) {
+            (ValueType::Int, ValueType::Int) => match floor_div_mod(unsafe { a.val.i }, unsafe { b.val.i })? {
+                Some((_, r)) => Value::int(r),
+                None => Value::int(0),
+            },
+            _ => self.arithmetic(&a, &b, |x, y| x.div_mod_floor(y).map(|(_, r)| r), floor_mod, "Operands must be numbers")?,
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    // ints raised to non-negative ints stay ints, anything else is a float
+    fn power(&mut self) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let val = match (self.bigint(&a), self.bigint(&b)) {
+            (Some(base), Some(exponent)) => {
+                if exponent < BigInt::from(0) {
+                    return Err(Error::new(&format!("Cannot raise an int to the negative power {}, use a float instead", exponent)));
+                }
+                let n = base.pow(&exponent).ok_or_else(|| Error::new("Power is too large"))?;
+                self.integer(n)
+            }
+            _ => match (self.float(&a), self.float(&b)) {
+                (Some(x), Some(y)) => Value::float(x.powf(y)),
+                _ => return Err(Error::new("Operands must be numbers")),
+            },
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    fn bitwise(&mut self, op: fn(i64, i64) -> i64, big: fn(&BigInt, &BigInt) -> BigInt) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let val = match (&a.value_type, &b.value_type) {
+            (ValueType::Int, ValueType::Int) => Value::int(op(unsafe { a.val.i }, unsafe { b.val.i })),
+            _ => match (self.bigint(&a), self.bigint(&b)) {
+                (Some(x), Some(y)) => self.integer(big(&x, &y)),
+                _ => return Err(self.bitwise_error(&a, &b)),
+            },
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    // shifts are arithmetic, so shifting right rounds down and keeps the sign
+    fn shift(&mut self, left: bool) -> Result<(), Error> {
+        let b = self.pop();
+        let a = self.pop();
+        let (n, amount) = match (self.bigint(&a), self.bigint(&b)) {
+            (Some(n), Some(amount)) => (n, amount),
+            _ => return Err(self.bitwise_error(&a, &b)),
+        };
+        if amount < BigInt::from(0) {
+            return Err(Error::new(&format!("Cannot shift by the negative amount {}", amount)));
+        }
+        // an amount too large for an int shifts every bit out to the right
+        let amount = amount.to_i64().unwrap_or(i64::MAX) as u64;
+        let val = match (&a.value_type, left) {
+            (ValueType::Int, false) => Value::int(unsafe { a.val.i } >> amount.min(63)),
+            (ValueType::Int, true) if amount < 64 && (unsafe { a.val.i } << amount) >> amount == unsafe { a.val.i } => {
+                Value::int(unsafe { a.val.i } << amount)
+            }
+            (_, false) => self.integer(n.shr(amount)),
+            (_, true) => {
+                if !n.is_zero() && n.bits().saturating_add(amount) > MAX_BITS {
+                    return Err(Error::new("Shift is too large"));
+                }
+                self.integer(n.shl(amount))
+            }
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    fn bit_not(&mut self) -> Result<(), Error> {
+        let val = self.pop();
+        let val = match val.value_type {
+            ValueType::Int => Value::int(!unsafe { val.val.i }),
+            _ => match self.obj(&val) {
+                Some(Obj::BigInt(n)) => {
+                    let n = n.not();
+                    self.integer(n)
+                }
+                _ => return Err(Error::new(&format!("Bitwise operand must be an int but got {}", self.type_name(&val)))),
+            },
+        };
+        self.push(val);
+        Ok(())
+    }
+
+    fn bitwise_error(&self, a: &Value, b: &Value) -> Error {
+        Error::new(&format!("Bitwise operands must be ints but got {} and {}", self.type_name(a), self.type_name(b)))
+    }
+
+    // the stack is only checked for overflow when a frame is pushed, which bounds its growth
+    fn push(&mut self, constant: Value) {
         self.stack.push(constant);
     }
 
     fn pop(&mut self) -> Value {
-        if self.stack.len() == 0 {
+        if self.stack.is_empty() {
             panic!("Stack underflow");
         }
         self.stack.pop().unwrap()
     }
 
     fn peek(&self) -> &Value {
-        &self.stack.last().unwrap()
+        self.stack.last().unwrap()
+    }
+
+    pub(crate) fn alloc(&mut self, obj: Obj) -> Value {
+        match self.free.pop() {
+            Some(slot) => {
+                self.heap[slot] = obj;
+                Value::obj(slot)
+            }
+            None => {
+                self.heap.push(obj);
+                Value::obj(self.heap.len() - 1)
+            }
+        }
+    }
+
+    // frees every object the stack, the globals and the running frames can't reach. it only runs between
+    // instructions of the outermost program, when nothing outside those roots holds a value
+    fn collect(&mut self) {
+        let mut marked = vec![false; self.heap.len()];
+        let mut grey: Vec<usize> = self.open_upvalues.clone();
+        grey.extend(self.closure);
+        grey.extend(self.frames.iter().filter_map(|frame| frame.closure));
+        grey.extend(self.stack.iter().chain(self.globals.values()).filter_map(heap_index));
+
+        while let Some(index) = grey.pop() {
+            if marked[index] {
+                continue;
+            }
+            marked[index] = true;
+            match &self.heap[index] {
+                Obj::Array(values) | Obj::Enum { values, .. } => grey.extend(values.iter().filter_map(heap_index)),
+                Obj::Map(map) => grey.extend(map.entries().iter().flat_map(|(k, v)| [k, v]).filter_map(heap_index)),
+                Obj::Closure { function, upvalues } => {
+                    grey.extend(heap_index(function));
+                    grey.extend(upvalues);
+                }
+                Obj::Upvalue(Upvalue::Closed(value)) => grey.extend(heap_index(value)),
+                Obj::Struct { methods, protocols, .. } => {
+                    grey.extend(methods.values().filter_map(heap_index));
+                    grey.extend(protocols);
+                }
+                Obj::Instance { struct_obj, fields } => {
+                    grey.push(*struct_obj);
+                    grey.extend(fields.iter().filter_map(heap_index));
+                }
+                Obj::BoundMethod { receiver, method } => grey.extend([receiver, method].into_iter().filter_map(heap_index)),
+                Obj::EnumCase { enum_obj, .. } => grey.push(*enum_obj),
+                _ => {}
+            }
+        }
+
+        for (index, marked) in marked.into_iter().enumerate() {
+            if !marked && !matches!(self.heap[index], Obj::Free) {
+                // dropping a function drops its hold on the chunk it was compiled in
+                self.heap[index] = Obj::Free;
+                self.free.push(index);
+            }
+        }
+        self.next_gc = GC_MIN.max(2 * (self.heap.len() - self.free.len()));
+    }
+
+    // how many objects are allocated and not yet collected
+    #[cfg(test)]
+    pub(crate) fn live_objects(&self) -> usize {
+        self.heap.len() - self.free.len()
+    }
+
+    pub(crate) fn obj(&self, value: &Value) -> Option<&Obj> {
+        match value.value_type {
+            ValueType::Obj => Some(&self.heap[unsafe { value.val.o }]),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn obj_mut(&mut self, value: &Value) -> Option<&mut Obj> {
+        match value.value_type {
+            ValueType::Obj => Some(&mut self.heap[unsafe { value.val.o }]),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn type_name(&self, value: &Value) -> &'static str {
+        match value.value_type {
+            ValueType::Int => "int",
+            ValueType::Float => "float",
+            ValueType::Bool => "bool",
+            ValueType::Nil => "nil",
+            ValueType::Obj => match &self.heap[unsafe { value.val.o }] {
+                Obj::String(_) => "string",
+                Obj::BigInt(_) => "int",
+                Obj::Array(_) => "array",
+                Obj::Map(_) => "map",
+                Obj::Range { .. } => "range",
+                Obj::Function { .. } | Obj::Closure { .. } | Obj::Native { .. } | Obj::BoundMethod { .. } => "function",
+                Obj::Upvalue(_) => "upvalue",
+                Obj::Struct { .. } => "struct",
+                Obj::Protocol { .. } => "protocol",
+                Obj::Instance { .. } => "instance",
+                Obj::Enum { .. } => "enum",
+                Obj::EnumCase { .. } => "enum case",
+                Obj::Free => unreachable!("a value refers to a freed object"),
+            },
+        }
+    }
+
+    pub(crate) fn stringify(&self, value: &Value) -> String {
+        match value.value_type {
+            ValueType::Int => unsafe { value.val.i }.to_string(),
+            ValueType::Float => unsafe { value.val.f }.to_string(),
+            ValueType::Bool => unsafe { value.val.b }.to_string(),
+            ValueType::Nil => "nil".to_string(),
+            ValueType::Obj => match &self.heap[unsafe { value.val.o }] {
+                Obj::String(string) => string.clone(),
+                Obj::BigInt(n) => n.to_string(),
+                Obj::Array(elements) => {
+                    let elements: Vec<String> = elements.iter().map(|e| self.stringify(e)).collect();
+                    format!("[{}]", elements.join(", "))
+                }
+                Obj::Map(map) => {
+                    let entries: Vec<String> = map.entries().iter()
+                        .map(|(k, v)| format!("{}: {}", self.stringify(k), self.stringify(v)))
+                        .collect();
+                    format!("{{{}}}", entries.join(", "))
+                }
+                Obj::Range { start, end } => format!("{}..{}", start, end),
+                Obj::Function { name, .. } => format!("<fun {}>", name),
+                Obj::Closure { function, .. } => self.stringify(function),
+                Obj::Native { name, .. } => format!("<native fun {}>", name),
+                Obj::Upvalue(_) => "<upvalue>".to_string(),
+                Obj::Struct { name, .. } => format!("<struct {}>", name),
+                Obj::Instance { struct_obj, fields } => match &self.heap[*struct_obj] {
+                    Obj::Struct { name, fields: names, .. } => {
+                        let fields: Vec<String> = names.iter().zip(fields)
+                            .map(|(n, v)| format!("{}: {}", n, self.stringify(v)))
+                            .collect();
+                        format!("{} {{ {} }}", name, fields.join(", "))
+                    }
+                    _ => unreachable!("instance of a non-struct"),
+                },
+                Obj::BoundMethod { method, .. } => self.stringify(method),
+                Obj::Enum { name, .. } => format!("<enum {}>", name),
+                Obj::Protocol { name, .. } => format!("<protocol {}>", name),
+                Obj::EnumCase { enum_obj, case } => match &self.heap[*enum_obj] {
+                    Obj::Enum { name, cases, .. } => format!("{}.{}", name, cases[*case]),
+                    _ => unreachable!("case of a non-enum"),
+                },
+                Obj::Free => unreachable!("a value refers to a freed object"),
+            },
+        }
     }
 }
 
+// the heap index a value refers to, if it's an object
+fn heap_index(value: &Value) -> Option<usize> {
+    match value.value_type {
+        ValueType::Obj => Some(unsafe { value.val.o }),
+        _ => None,
+    }
+}
+
+fn is_falsey(value: &Value) -> bool {
+    match value.value_type {
+        ValueType::Nil => true,
+        ValueType::Bool => unsafe { !value.val.b },
+        _ => false,
+    }
+}
+
+// the quotient rounded down and the remainder with the sign of the divisor, or none when that overflows
+fn floor_div_mod(a: i64, b: i64) -> Result<Option<(i64, i64)>, Error> {
+    if b == 0 {
+        return Err(Error::new("Division by zero"));
+    }
+    Ok(match (a.checked_div(b), a.checked_rem(b)) {
+        (Some(q), Some(r)) if r != 0 && (r < 0) != (b < 0) => Some((q - 1, r + b)),
+        (Some(q), Some(r)) => Some((q, r)),
+        _ => None,
+    })
+}
+
+fn floor_mod(a: f64, b: f64) -> f64 {
+    let r = a % b;
+    if r != 0.0 && (r < 0.0) != (b < 0.0) { r + b } else { r }
+}
+
+enum Operands {
+    Big(BigInt, BigInt),
+    Float(f64, f64),
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
+    use crate::compiler::compile;
+    use crate::fixtures;
+    use crate::parser::parse;
 
     #[test]
     fn test_add() {
@@ -307,18 +1467,26 @@ mod test {
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             10,
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             5,
             3, // Add
             0, // Return
         ];
 
         let mut vm = VM::new();
-        let val = vm.interpret(&instructions);
+        let val = vm.interpret(&instructions).unwrap();
         unsafe { assert_eq!(val.val.i, 15) }
     }
     #[test]
@@ -328,18 +1496,26 @@ mod test {
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             10,
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             5,
             4, // Subtract
             0, // Return
         ];
 
         let mut vm = VM::new();
-        let val = vm.interpret(&instructions);
+        let val = vm.interpret(&instructions).unwrap();
         unsafe { assert_eq!(val.val.i, 5) }
     }
 
@@ -350,18 +1526,26 @@ mod test {
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             10,
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             5,
             5, // Multiply
             0, // Return
         ];
 
         let mut vm = VM::new();
-        let val = vm.interpret(&instructions);
+        let val = vm.interpret(&instructions).unwrap();
         unsafe { assert_eq!(val.val.i, 50) }
     }
 
@@ -372,25 +1556,296 @@ mod test {
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             10,
             1, // Constant
             0,
             0,
             0,
+            0,
+            0,
+            0,
+            0,
             5,
             6, // Divide
             0, // Return
         ];
 
         let mut vm = VM::new();
-        let val = vm.interpret(&instructions);
+        let val = vm.interpret(&instructions).unwrap();
         unsafe { assert_eq!(val.val.i, 2) }
     }
 
     #[test]
     fn test_interpret_source() {
-        let s = r#"
-        10 + 10
-        "#;
+        let s = fixtures::engine::INTERPRET_SOURCE;
+
+        assert_eq!(run(s), "20");
+    }
+
+    #[test]
+    fn test_for_range() {
+        let s = fixtures::engine::FOR_RANGE;
+
+        assert_eq!(run(s), "[10, 15]");
+    }
+
+    #[test]
+    fn test_for_array() {
+        let s = fixtures::engine::FOR_ARRAY;
+
+        assert_eq!(run(s), "freerondonumba9");
+    }
+
+    #[test]
+    fn test_for_string() {
+        let s = fixtures::engine::FOR_STRING;
+
+        assert_eq!(run(s), "olleh");
+    }
+
+    #[test]
+    fn test_for_nested_locals() {
+        let s = fixtures::engine::FOR_NESTED_LOCALS;
+
+        assert_eq!(run(s), "xxxx");
+    }
+
+    #[test]
+    fn test_while() {
+        let s = fixtures::engine::WHILE;
+
+        assert_eq!(run(s), "[7, 18]");
+    }
+
+    #[test]
+    fn test_break_pops_locals() {
+        let s = fixtures::engine::BREAK_POPS_LOCALS;
+
+        assert_eq!(run(s), "[frieza!, 97]");
+    }
+
+    #[test]
+    fn test_logic() {
+        let s = fixtures::engine::LOGIC;
+
+        assert_eq!(run(s), "[true, false, true, true, true, false, b, false, true]");
+    }
+
+    #[test]
+    fn test_nan_comparisons() {
+        let s = fixtures::engine::NAN_COMPARISONS;
+
+        assert_eq!(run(s), "[false, false, false, false, false, false, false]");
+    }
+
+    #[test]
+    fn test_functions() {
+        let s = fixtures::engine::FUNCTIONS;
+
+        assert_eq!(run(s), "[5, 610, nil]");
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let s = fixtures::engine::INTERPOLATION;
+
+        assert_eq!(run(s), "[9000, over 9001!, [1.5, nil] and inner 9000]");
+    }
+
+    #[test]
+    fn test_garbage_collection() {
+        let s = fixtures::engine::GARBAGE_COLLECTION;
+
+        let code = compile(parse(s).unwrap(), &[]).unwrap();
+        let mut vm = VM::new();
+        let val = vm.interpret(&code).unwrap();
+        assert_eq!(vm.stringify(&val), "[12497500, 5001, 1, 2, {0: <fun lambda>, 1: <fun lambda>, 2: <fun lambda>, 3: <fun lambda>, 4: <fun lambda>}]");
+        // the list's 5000 nodes are live, but not the maps, arrays and strings made alongside them
+        assert!(vm.live_objects() < 10000, "{} live objects", vm.live_objects());
+    }
+
+    #[test]
+    fn test_finished_chunks_are_dropped() {
+        let mut vm = VM::new();
+        vm.interpret(&compile(parse("fun add(a, b)\nreturn a + b\nend\n").unwrap(), &[]).unwrap()).unwrap();
+        let functions = Rc::downgrade(&vm.code);
+        vm.interpret(&compile(parse("1 + 2\n").unwrap(), &[]).unwrap()).unwrap();
+        let sum = Rc::downgrade(&vm.code);
+        vm.interpret(&compile(parse("add(1, 2)\n").unwrap(), &["add".to_string()]).unwrap()).unwrap();
+
+        // the chunk `add` was compiled in lives as long as `add` does
+        assert!(functions.upgrade().is_some());
+        assert!(sum.upgrade().is_none());
+    }
+
+    #[test]
+    fn test_deep_recursion() {
+        let s = fixtures::engine::DEEP_RECURSION;
+
+        assert_eq!(run(s), "[true, 2485, 125250]");
+        assert_eq!(error("fun f(n)\nreturn f(n + 1)\nend\nf(0)\n"), "Stack overflow");
+    }
+
+    #[test]
+    fn test_closure_counter() {
+        let s = fixtures::engine::CLOSURE_COUNTER;
+
+        assert_eq!(run(s), "[3, 2]");
+    }
+
+    #[test]
+    fn test_closures_share_upvalues() {
+        let s = fixtures::engine::CLOSURES_SHARE_UPVALUES;
+
+        assert_eq!(run(s), "after");
+    }
+
+    #[test]
+    fn test_closures_in_loops() {
+        let s = fixtures::engine::CLOSURES_IN_LOOPS;
+
+        assert_eq!(run(s), "[0, 10]");
+    }
+
+    #[test]
+    fn test_lambdas() {
+        let s = fixtures::engine::LAMBDAS;
+
+        assert_eq!(run(s), "[42, 15, 2, nil]");
+    }
+
+    #[test]
+    fn test_captured_protocols() {
+        let s = fixtures::engine::CAPTURED_PROTOCOLS;
+
+        assert_eq!(run(s), "true");
+    }
+
+    #[test]
+    fn test_nested_closures() {
+        let s = fixtures::engine::NESTED_CLOSURES;
+
+        assert_eq!(run(s), "[outer, 120]");
+    }
+
+    #[test]
+    fn test_struct() {
+        let s = fixtures::engine::STRUCT;
+
+        assert_eq!(run(s), "[500000, 500000, Saiyan { name: kakarot, powerLevel: 500000 }]");
+    }
+
+    #[test]
+    fn test_compound_assignment() {
+        let s = fixtures::engine::COMPOUND_ASSIGNMENT;
+
+        assert_eq!(run(s), "[3, 6, [11, 18], 3]");
+    }
+
+    #[test]
+    fn test_bound_method() {
+        let s = fixtures::engine::BOUND_METHOD;
+
+        assert_eq!(run(s), "3");
+    }
+
+    #[test]
+    fn test_enum() {
+        let s = fixtures::engine::ENUM;
+
+        assert_eq!(run(s), "[Form.superSaiyan, 450000, true, false, Form.base]");
+    }
+
+    #[test]
+    fn test_protocol() {
+        let s = fixtures::engine::PROTOCOL;
+
+        assert_eq!(run(s), "[31, true, true, false, false, false]");
+    }
+
+    #[test]
+    fn test_maps() {
+        let s = fixtures::engine::MAPS;
+
+        assert_eq!(run(s), "[9001, 50, 17552, ab, {goku: 9001, Form.superSaiyan: 50, vegeta: 8500, Form.base: 1}]");
+    }
+
+    #[test]
+    fn test_array_indexing() {
+        let s = fixtures::engine::ARRAY_INDEXING;
+
+        assert_eq!(run(s), "[[10, 2, 6], 2, 14]");
+    }
+
+    #[test]
+    fn test_index_errors() {
+        assert_eq!(error("{}[1.5]\n"), "Unhashable map key of type float");
+        assert_eq!(error("{[1]: 2}\n"), "Unhashable map key of type array");
+        assert_eq!(error("{\"a\": 1}[\"b\"]\n"), "Map has no key b");
+        assert_eq!(error("[1][1]\n"), "Array index 1 is out of bounds for length 1");
+        assert_eq!(error("[1][\"0\"]\n"), "Array index must be an int but got string");
+        assert_eq!(error("1[0]\n"), "Cannot index int");
+        assert_eq!(error("for k, v in 0..2\nend\n"), "Can only iterate over maps and arrays with two variables");
+    }
+
+    #[test]
+    fn test_big_ints() {
+        assert_eq!(run("9223372036854775807 + 1\n"), "9223372036854775808");
+        assert_eq!(run("-9223372036854775807 - 2\n"), "-9223372036854775809");
+        assert_eq!(run("4611686018427387904 * 4\n"), "18446744073709551616");
+        assert_eq!(run("(-9223372036854775807 - 1) / -1\n"), "9223372036854775808");
+        assert_eq!(run("let x = -9223372036854775807 - 1\n-x\n"), "9223372036854775808");
+        // results that fit go back to being ints
+        assert_eq!(run("let x = 9223372036854775807 + 1\nx - 1\n"), "9223372036854775807");
+        let s = fixtures::engine::BIG_INTS;
+        assert_eq!(run(s), "265252859812191058636308480000000");
+        assert_eq!(run("let x = 9223372036854775807 * 9223372036854775807\nx / 9223372036854775807\n"), "9223372036854775807");
+        assert_eq!(run("let x = 9223372036854775807 + 1\n[x == 9223372036854775807 + 1, x > 9223372036854775807, x < 1.0e19, x == 9223372036854775808.0]\n"), "[true, true, true, true]");
+        assert_eq!(run("(9223372036854775807 + 1) * 0.5\n"), "4611686018427388000");
+        assert_eq!(run("let m = {9223372036854775807 + 1: \"big\"}\nm[9223372036854775807 + 1]\n"), "big");
+        assert_eq!(error("(9223372036854775807 + 1) / 0\n"), "Division by zero");
+        assert_eq!(error("[1][9223372036854775807 + 1]\n"), "Array index 9223372036854775808 is out of bounds for length 1");
+        assert_eq!(run("{9223372036854775807 + 1: \"b\"}[9223372036854775808]\n"), "b");
+        assert_eq!(run("[18446744073709551616, -9223372036854775808, 9223372036854775808 - 1]\n"), "[18446744073709551616, -9223372036854775808, 9223372036854775807]");
+        assert_eq!(run("[0xffff_ffff_ffff_ffff, 0x8000_0000_0000_0000 - 1]\n"), "[18446744073709551615, 9223372036854775807]");
+        assert_eq!(error("1 / 0\n"), "Division by zero");
+        assert_eq!(run("1.0 / 0\n"), "inf");
+    }
+
+    #[test]
+    fn test_operators() {
+        assert_eq!(run("[7 % 3, -7 % 3, 7 % -3, 7.5 % 2, -7.5 % 2]\n"), "[1, 2, -2, 1.5, 0.5]");
+        assert_eq!(run("[7 div 2, -7 div 2, 7.5 div 2, 7 / 2, -7 / 2]\n"), "[3, -4, 3, 3, -3]");
+        assert_eq!(run("[2 ** 10, 2 ** 3 ** 2, -2 ** 2, 2 ** -1.0, 4 ** 0.5, 2 ** 64]\n"), "[1024, 512, -4, 0.5, 2, 18446744073709551616]");
+        assert_eq!(run("[6 & 3, 6 | 3, 6 ^ 3, ~5, ~-1, 1 << 4, -16 >> 2, -1 >> 100, 1 | 2 ^ 3 & 4 << 1]\n"), "[2, 7, 5, -6, 0, 16, -4, -1, 3]");
+        assert_eq!(run("[1 << 64, (1 << 64) >> 63, (1 << 64) & ~1, -(1 << 64) | 1, (1 << 64) % 10, (1 << 64) div -7]\n"),
+            "[18446744073709551616, 2, 18446744073709551616, -18446744073709551615, 6, -2635249153387078803]");
+        assert_eq!(run("(-9223372036854775807 - 1) div -1\n"), "9223372036854775808");
+        assert_eq!(run("(-9223372036854775807 - 1) % -1\n"), "0");
+        assert_eq!(error("1 % 0\n"), "Division by zero");
+        assert_eq!(error("1 div 0\n"), "Division by zero");
+        assert_eq!(error("2 ** -1\n"), "Cannot raise an int to the negative power -1, use a float instead");
+        assert_eq!(error("2 ** 10000000\n"), "Power is too large");
+        assert_eq!(error("1.5 & 1\n"), "Bitwise operands must be ints but got float and int");
+        assert_eq!(error("1 << 2.0\n"), "Bitwise operands must be ints but got int and float");
+        assert_eq!(error("~1.5\n"), "Bitwise operand must be an int but got float");
+        assert_eq!(error("1 << -1\n"), "Cannot shift by the negative amount -1");
+        assert_eq!(error("1 << 10000000\n"), "Shift is too large");
+    }
+
+    fn error(s: &str) -> String {
+        let code = compile(parse(s).unwrap(), &[]).unwrap();
+        VM::new().interpret(&code).err().expect("error").message().to_string()
+    }
+
+    fn run(s: &str) -> String {
+        let code = compile(parse(s).unwrap(), &[]).unwrap();
+        let mut vm = VM::new();
+        let val = vm.interpret(&code).unwrap();
+        vm.stringify(&val)
     }
 }